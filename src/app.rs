@@ -1,27 +1,37 @@
 use cosmic::{
     app::{Core, Task},
     iced::{
+        event::Status,
         keyboard::{self, Key},
-        Event, Subscription,
+        mouse, Event, Subscription,
     },
     iced_core::SmolStr,
     Application, ApplicationExt, Element
 };
-use evdev_rs::enums::EventCode;
 use std::{
+    collections::HashMap,
     fs::File,
     io::Read,
     sync::{Arc, Mutex},
     thread,
-    time::Instant,
+    time::{Duration, Instant},
 };
 
 use crate::{
-    simulator::simulate_keys,
-    config::{AppData, GlobalHotkey, KeyBehaviorMode, ModifierBehaviorMode, HoldBehaviorMode, TempHotkeyState},
-    utils::start_global_hotkey_listener, 
+    simulator::{simulate_keys, type_text_once, SimulationKey, SimulationSettings},
+    config::{AppData, GlobalHotkey, HotkeyAction, HotkeyActivation, HotkeyBackend, HotkeyModifiers, HotkeySettings, KeyBehaviorMode, KeyChord, KeyOverride, KeyboardLayout, ModifierBehaviorMode, ModifierSide, UnicodeFallback, HoldBehaviorMode, HotkeyTarget, RecordingState, TempHotkeyState, TimelineState},
+    utils::{start_global_hotkey_listener, wait_for_keys_released, watch, HotkeyEdge},
+    clipboard::read_clipboard_text,
+    macros::{Macro, MacroStep, PlaybackOptions, StepField},
+    cadence::TypingProfile,
+    macro_dsl::{format_macro, parse_macro},
+    evemu::{evemu_to_macro, format_evemu, macro_to_evemu, parse_evemu},
+    xdotool::import_xdotool,
+    typing::LayoutKeymap,
+    error::AppError,
+    recorder::Recorder,
     ui::View,
-    constants::DEFAULT_INTERVAL_MS,
+    constants::{DEFAULT_INTERVAL_MS, DEFAULT_STEP_DELAY_MS, MAX_KEY_WEIGHT, MAX_INTERVAL_MS, MIN_PLAYBACK_SPEED, MAX_PLAYBACK_SPEED, PASTE_RELEASE_TIMEOUT_MS, STEP_EDIT_SAVE_MS},
     utils::persistence::save_app_data,
 };
 
@@ -29,6 +39,8 @@ use crate::{
 pub struct KeyEvent {
     pub key: Key,
     pub modifiers: cosmic::iced::keyboard::Modifiers,
+    /// Tells left from right modifier keys.
+    pub location: keyboard::Location,
 }
 
 impl KeyEvent {
@@ -36,6 +48,7 @@ impl KeyEvent {
         Self {
             key: Key::Character(SmolStr::from("KEY_BTN_LEFT")),
             modifiers: cosmic::iced::keyboard::Modifiers::empty(),
+            location: keyboard::Location::Standard,
         }
     }
 
@@ -43,6 +56,7 @@ impl KeyEvent {
         Self {
             key: Key::Character(SmolStr::from("KEY_BTN_MIDDLE")),
             modifiers: cosmic::iced::keyboard::Modifiers::empty(),
+            location: keyboard::Location::Standard,
         }
     }
 
@@ -50,6 +64,7 @@ impl KeyEvent {
         Self {
             key: Key::Character(SmolStr::from("KEY_BTN_RIGHT")),
             modifiers: cosmic::iced::keyboard::Modifiers::empty(),
+            location: keyboard::Location::Standard,
         }
     }
 }
@@ -57,22 +72,30 @@ impl KeyEvent {
 // Main application struct managing UI state and background threads
 pub struct InputSimulatorApp {
     running: Arc<Mutex<bool>>,
+    paused: Arc<Mutex<bool>>,
     interval_ms: Arc<Mutex<u64>>,
     core: Core,
     app_data: Arc<Mutex<AppData>>,
     capturing: Arc<Mutex<bool>>,
-    selected_keys: Arc<Mutex<Vec<EventCode>>>,
+    selected_keys: Arc<Mutex<Vec<SimulationKey>>>,
     key_behavior: Arc<Mutex<KeyBehaviorMode>>,  
     previous_hotkey_state: Arc<Mutex<bool>>,
     last_toggle_time: Arc<Mutex<Option<Instant>>>,
     capturing_hotkey: Arc<Mutex<bool>>,
     settings_panel_open: Arc<Mutex<bool>>,
+    recorder: Arc<Recorder>,
+    hotkey_settings: watch::Sender<HotkeySettings>,
+    // The latest run, so a profile hotkey can wait for it to release its keys
+    simulation_thread: Arc<Mutex<Option<thread::JoinHandle<()>>>>,
+    // When a step field was last edited without saving; typing saves once it settles
+    step_edit_at: Option<Instant>,
 }
 
 impl Default for InputSimulatorApp {
     fn default() -> Self {
         Self {
             running: Arc::new(Mutex::new(false)),
+            paused: Arc::new(Mutex::new(false)),
             interval_ms: Arc::new(Mutex::new(DEFAULT_INTERVAL_MS)),
             core: Core::default(),
             app_data: Arc::new(Mutex::new(AppData {
@@ -83,9 +106,37 @@ impl Default for InputSimulatorApp {
                 key_behavior: KeyBehaviorMode::Click,
                 modifier_behavior: ModifierBehaviorMode::Click,
                 hold_behavior: HoldBehaviorMode::default(),
+                key_weights: HashMap::new(),
+                random_no_repeat: false,
+                random_seed: None,
+                key_overrides: HashMap::new(),
+                sequence_delays: Vec::new(),
+                macros: Vec::new(),
+                active_macro: None,
+                macro_playback: PlaybackOptions::default(),
+                macro_file: String::new(),
+                script_file: String::new(),
+                keyboard_layout: KeyboardLayout::default(),
+                unicode_fallback: UnicodeFallback::default(),
+                typing_profile: TypingProfile::default(),
+                paste_keybind: None,
+                start_keybind: None,
+                stop_keybind: None,
+                pause_keybind: None,
+                profiles: Vec::new(),
+                profile_bindings: Vec::new(),
+                active_profile: None,
+                hotkey_backend: HotkeyBackend::default(),
+                evemu_trace_file: String::new(),
                 settings_panel_open: true,
                 capturing_global_hotkey: false,
                 temp_hotkey: TempHotkeyState::default(),
+                recording: RecordingState::default(),
+                macro_file_error: None,
+                script_status: None,
+                type_text_input: String::new(),
+                profile_name_input: String::new(),
+                timeline: TimelineState::default(),
             })),
             capturing: Arc::new(Mutex::new(false)),
             selected_keys: Arc::new(Mutex::new(Vec::new())),
@@ -94,6 +145,10 @@ impl Default for InputSimulatorApp {
             last_toggle_time: Arc::new(Mutex::new(None)),
             capturing_hotkey: Arc::new(Mutex::new(false)),
             settings_panel_open: Arc::new(Mutex::new(true)),
+            recorder: Arc::new(Recorder::default()),
+            hotkey_settings: watch::Sender::new(AppData::default().hotkey_settings()),
+            simulation_thread: Arc::new(Mutex::new(None)),
+            step_edit_at: None,
         }
     }
 }
@@ -134,6 +189,7 @@ impl Application for InputSimulatorApp {
             *settings_panel_open = app_data.settings_panel_open;
         }
         
+        app.hotkey_settings.send_if_changed(app.app_data.lock().unwrap().hotkey_settings());
         app.start_global_hotkey_listener();
         
         // Set initial window size based on settings panel state
@@ -145,6 +201,7 @@ impl Application for InputSimulatorApp {
     fn update(&mut self, message: Self::Message) -> Task<Self::Message> {
         match message {
             Message::ToggleRunning                => self.handle_toggle_running(),
+            Message::TogglePaused                 => Self::toggle_paused(&self.running, &self.paused),
             Message::SetInterval(interval)         => self.handle_set_interval(interval),
             Message::SetIntervalAndSave(interval)    => self.handle_set_interval_and_save(interval),
             Message::UpdateInterval(input)         => self.handle_update_interval(input),
@@ -155,9 +212,99 @@ impl Application for InputSimulatorApp {
             Message::CancelCapture                 => self.handle_cancel_capture(),
             Message::UpdateKeyBehaviorMode(mode)   => self.handle_update_key_behavior_mode(mode),
             Message::UpdateHoldBehaviorMode(mode)  => self.handle_update_hold_behavior_mode(mode),
-            Message::CaptureGlobalHotkey           => self.handle_capture_global_hotkey(),
+            Message::UpdateKeyWeight(key, input)   => self.handle_update_key_weight(key, input),
+            Message::ToggleRandomNoRepeat(enabled) => self.handle_toggle_random_no_repeat(enabled),
+            Message::UpdateRandomSeed(input)       => self.handle_update_random_seed(input),
+            Message::UpdateStepDelay(index, input) => self.handle_update_step_delay(index, input),
+            Message::SelectMacro(name)             => self.handle_select_macro(name),
+            Message::SaveKeysAsMacro               => self.handle_save_keys_as_macro(),
+            Message::SelectMacroStep(index)        => self.app_data.lock().unwrap().timeline.selected = Some(index),
+            Message::StartMacroStepDrag(index)     => self.handle_start_macro_step_drag(index),
+            Message::HoverMacroStep(index)         => self.handle_hover_macro_step(index),
+            Message::DropMacroStep                 => self.handle_drop_macro_step(),
+            Message::CancelMacroStepDrag           => self.handle_cancel_macro_step_drag(),
+            Message::MoveMacroStep(from, to)       => self.handle_move_macro_step(from, to),
+            Message::UpdateMacroStep(index, field, value) => self.handle_update_macro_step(index, field, value),
+            Message::CommitMacroStep               => self.handle_commit_macro_step(),
+            Message::InsertMacroStep(kind)         => self.handle_insert_macro_step(kind),
+            Message::DeleteMacroStep(index)        => self.handle_delete_macro_step(index),
+            Message::PlayMacroFromStep(index)      => self.handle_play_macro_from_step(index),
+            Message::ToggleRecording               => self.handle_toggle_recording(),
+            Message::SetPlaybackSpeed(speed)       => self.handle_set_playback_speed(speed),
+            Message::UpdateMacroFile(path)         => self.handle_update_macro_file(path),
+            Message::LoadMacroFile                 => self.handle_load_macro_file(),
+            Message::SaveMacroFile                 => self.handle_save_macro_file(),
+            Message::ImportEvemuFile               => self.handle_import_evemu_file(),
+            Message::ExportEvemuFile               => self.handle_export_evemu_file(),
+            Message::ImportXdotoolFile             => self.load_macro_file_with(import_xdotool),
+            Message::UpdateTraceFile(path)         => self.update_state(|app_data| app_data.evemu_trace_file = path),
+            Message::UpdateHotkeyBackend(backend)  => self.update_state(|app_data| {
+                log::info!("Hotkey listener set to {}", backend);
+                app_data.hotkey_backend = backend;
+            }),
+            Message::UpdateHotkeyActivation(activation) => self.update_state(|app_data| {
+                log::info!("Hotkey activation set to {:?}", activation);
+                app_data.global_keybind.activation = activation;
+            }),
+            Message::UpdateReleaseDelay(input)     => self.handle_update_release_delay(input),
+            Message::UpdateScriptFile(path)        => self.handle_update_script_file(path),
+            Message::CheckScript                   => self.handle_check_script(),
+            Message::UpdateKeyboardLayout(layout)  => self.update_state(|app_data| app_data.keyboard_layout.layout = layout.trim().to_string()),
+            Message::UpdateKeyboardVariant(variant) => self.update_state(|app_data| app_data.keyboard_layout.variant = variant.trim().to_string()),
+            Message::UpdateUnicodeFallback(fallback) => self.update_state(|app_data| app_data.unicode_fallback = fallback),
+            Message::ToggleHumanizeTyping(enabled) => self.update_state(|app_data| app_data.typing_profile.humanize = enabled),
+            Message::SetTypingJitter(jitter)       => self.update_state(|app_data| app_data.typing_profile.jitter = jitter),
+            Message::SetBigramVariation(variation) => self.update_state(|app_data| app_data.typing_profile.bigram_variation = variation),
+            Message::UpdateTypingDelay(input)      => self.handle_update_typing_profile(input, "typing delay", |profile, value| {
+                profile.delay_ms = value.unwrap_or(TypingProfile::default().delay_ms);
+            }),
+            Message::UpdatePunctuationPause(input) => self.handle_update_typing_profile(input, "punctuation pause", |profile, value| {
+                profile.punctuation_pause_ms = value.unwrap_or(0);
+            }),
+            Message::UpdateSpacePause(input)       => self.handle_update_typing_profile(input, "space pause", |profile, value| {
+                profile.space_pause_ms = value.unwrap_or(0);
+            }),
+            Message::UpdateBurstLength(input)      => self.handle_update_typing_profile(input, "burst length", |profile, value| {
+                profile.burst_length = value.unwrap_or(0).min(u32::MAX as u64) as u32;
+            }),
+            Message::UpdateTypingSeed(input)       => self.handle_update_typing_profile(input, "typing seed", |profile, value| {
+                profile.seed = value;
+            }),
+            Message::UpdateTypeText(text)          => self.app_data.lock().unwrap().type_text_input = text,
+            Message::SaveTextAsMacro               => self.handle_save_text_as_macro(),
+            Message::UpdateMaxIdle(input)          => self.handle_update_max_idle(input),
+            Message::UpdateLoopCount(input)        => self.handle_update_loop_count(input),
+            Message::UpdateKeyOverrideMode(key, mode) => self.handle_update_key_override(key, |o| o.mode = mode),
+            // What was typed is kept, so the field can be cleared and retyped; a run clamps it
+            Message::UpdateKeyOverrideInterval(key, input) => {
+                match parse_millis(&input) {
+                    Some(value) => self.handle_update_key_override(key, |o| o.interval_ms = value),
+                    None => log::warn!("Invalid interval input for {}: {}", key, input),
+                }
+            },
+            Message::UpdateKeyOverridePressDuration(key, input) => {
+                match parse_millis(&input) {
+                    Some(value) => self.handle_update_key_override(key, |o| o.press_duration_ms = value),
+                    None => log::warn!("Invalid press duration input for {}: {}", key, input),
+                }
+            },
+            Message::CaptureGlobalHotkey           => self.handle_capture_global_hotkey(HotkeyTarget::Simulation),
+            Message::CaptureActionHotkey(action)   => self.handle_capture_global_hotkey(HotkeyTarget::Action(action)),
+            Message::ClearActionHotkey(action)     => self.update_state(|app_data| {
+                log::info!("Cleared the {} hotkey", action);
+                app_data.set_action_keybind(&action, None);
+            }),
+            Message::UpdateProfileName(name)       => self.app_data.lock().unwrap().profile_name_input = name,
+            Message::SaveProfile                   => self.handle_save_profile(),
+            Message::LoadProfile(name)             => self.handle_load_profile(name),
+            Message::DeleteProfile(name)           => self.update_state(|app_data| {
+                log::info!("Deleted profile {}", name);
+                app_data.delete_profile(&name);
+            }),
             Message::FinalizeGlobalHotkey          => self.handle_finalize_global_hotkey(),
             Message::CancelGlobalHotkey            => self.handle_cancel_global_hotkey(),
+            Message::ToggleExactHotkey(enabled)    => self.update_state(|app_data| app_data.temp_hotkey.modifiers.exact = enabled),
+            Message::ToggleHotkeySides(enabled)    => self.update_state(|app_data| app_data.temp_hotkey.distinguish_sides = enabled),
             Message::ToggleSettingsPanel           => {
                 let panel_open = {
                     let mut settings_panel_open = self.settings_panel_open.lock().unwrap();
@@ -171,7 +318,7 @@ impl Application for InputSimulatorApp {
                 
                 return self.resize_window(panel_open);
             },
-            Message::RefreshUiState                => {},
+            Message::RefreshUiState                => self.save_settled_step_edit(),
             Message::Noop                          => {},
         }
         Task::none()
@@ -181,6 +328,7 @@ impl Application for InputSimulatorApp {
         let app_data = self.app_data.lock().unwrap();
         let view = View::new(
             *self.running.lock().unwrap(),
+            *self.paused.lock().unwrap(),
             *self.interval_ms.lock().unwrap() as f64,
             app_data,
             *self.capturing.lock().unwrap(),
@@ -197,18 +345,24 @@ impl Application for InputSimulatorApp {
 
     fn subscription(&self) -> Subscription<Message> {
         Subscription::batch(vec![
-            cosmic::iced::event::listen_with(|event, _status, _context| {
+            cosmic::iced::event::listen_with(|event, status, _context| {
                 match event {
                     Event::Keyboard(keyboard::Event::KeyPressed { 
                         key,
                         modifiers,
+                        location,
                         ..
                     }) => {
                         Some(Message::AddKey(KeyEvent { 
                             key: key.clone(), 
-                            modifiers 
+                            modifiers,
+                            location,
                         }))
                     }
+                    // The timeline captures releases over it; any other release ends a drag
+                    Event::Mouse(mouse::Event::ButtonReleased(_)) if status == Status::Ignored => {
+                        Some(Message::CancelMacroStepDrag)
+                    }
                     _ => None,
                 }
             }),
@@ -221,34 +375,40 @@ impl InputSimulatorApp {
     // Helper function to spawn a simulation thread with proper Arc cloning
     fn spawn_simulation_thread(
         running: Arc<Mutex<bool>>,
+        paused: Arc<Mutex<bool>>,
         interval_ms: Arc<Mutex<u64>>,
-        selected_keys: Arc<Mutex<Vec<EventCode>>>,
+        selected_keys: Arc<Mutex<Vec<SimulationKey>>>,
         key_behavior: Arc<Mutex<KeyBehaviorMode>>,
         app_data: Arc<Mutex<AppData>>,
-    ) {
+        run_while_held: bool,
+    ) -> thread::JoinHandle<()> {
         // Clone Arcs before moving them into the closure
         let running_inner = Arc::clone(&running);
         let interval_ms_inner = Arc::clone(&interval_ms);
         let selected_keys_inner = Arc::clone(&selected_keys);
         let key_behavior_inner = Arc::clone(&key_behavior);
         let app_data_inner = Arc::clone(&app_data);
+        // Every run starts unpaused
+        *paused.lock().unwrap() = false;
 
         thread::spawn(move || {
-            let (mod_behavior, hold_behavior) = {
-                let ad = app_data_inner.lock().unwrap();
-                (ad.modifier_behavior, ad.hold_behavior)
+            let settings = {
+                let mut ad = app_data_inner.lock().unwrap();
+                let settings = SimulationSettings { run_while_held, ..SimulationSettings::from_app_data(&ad) };
+                ad.timeline.play_from = None;
+                settings
             };
             if let Err(e) = simulate_keys(
                 running_inner,
+                paused,
                 interval_ms_inner,
                 selected_keys_inner,
                 key_behavior_inner,
-                mod_behavior,
-                hold_behavior,
+                settings,
             ) {
                 log::error!("Failed to simulate keys: {}", e);
             }
-        });
+        })
     }
 
     // Resize window based on the settings panel state
@@ -274,24 +434,32 @@ impl InputSimulatorApp {
     // Starts the input simulation thread with current configuration
     fn start_simulation(&self) {
         let running = Arc::clone(&self.running);
+        let paused = Arc::clone(&self.paused);
         let interval_ms = Arc::clone(&self.interval_ms);
         let selected_keys = Arc::clone(&self.selected_keys);
         let key_behavior = Arc::clone(&self.key_behavior);
         let app_data = Arc::clone(&self.app_data);
 
         {
-            let app_data_guard = app_data.lock().unwrap();
+            let mut app_data_guard = app_data.lock().unwrap();
             let mut keys_lock = selected_keys.lock().unwrap();
             let mut behavior_lock = key_behavior.lock().unwrap();
-            crate::simulator::initialize_simulation_keys(&app_data_guard, &mut keys_lock, &mut behavior_lock);
-            if keys_lock.is_empty() {
-                log::warn!("No valid keys for simulation, skipping start.");
+            let ready = crate::simulator::initialize_simulation_keys(&app_data_guard, &mut keys_lock, &mut behavior_lock);
+            if !ready {
+                log::warn!("Nothing to simulate, skipping start.");
+                if app_data_guard.key_behavior == KeyBehaviorMode::Script {
+                    app_data_guard.script_status = crate::simulator::read_script_source(&app_data_guard)
+                        .err()
+                        .map(|e| e.to_string());
+                }
+                app_data_guard.timeline.play_from = None;
                 *running.lock().unwrap() = false;
                 return;
             }
         }
 
-        Self::spawn_simulation_thread(running, interval_ms, selected_keys, key_behavior, app_data);
+        let handle = Self::spawn_simulation_thread(running, paused, interval_ms, selected_keys, key_behavior, app_data, false);
+        *self.simulation_thread.lock().unwrap() = Some(handle);
     }
 
     // Persists application state to disk using the unified persistence function.
@@ -316,8 +484,9 @@ impl InputSimulatorApp {
                 let mut json = String::new();
                 if file.read_to_string(&mut json).is_ok() {
                     match serde_json::from_str::<AppData>(&json) {
-                        Ok(data) => {
+                        Ok(mut data) => {
                             log::info!("Loaded app data: {:?}", data.selected_keys);
+                            data.assign_profile_ids();
                             *self.interval_ms.lock().unwrap() = data.interval_ms;
                             self.app_data = Arc::new(Mutex::new(data));
                         }
@@ -333,52 +502,275 @@ impl InputSimulatorApp {
     fn start_global_hotkey_listener(&self) {
         // Capture all necessary state with a single clone at the beginning
         let running = Arc::clone(&self.running);
+        let paused = Arc::clone(&self.paused);
         let interval_ms = Arc::clone(&self.interval_ms);
         let selected_keys = Arc::clone(&self.selected_keys);
         let key_behavior = Arc::clone(&self.key_behavior);
         let app_data = Arc::clone(&self.app_data);
         let previous_hotkey_state = Arc::clone(&self.previous_hotkey_state);
         let last_toggle_time = Arc::clone(&self.last_toggle_time);
+        let recorder = Arc::clone(&self.recorder);
+        let simulation_thread = Arc::clone(&self.simulation_thread);
+        let action_running = Arc::clone(&running);
+        let action_paused = Arc::clone(&paused);
+        let action_interval_ms = Arc::clone(&interval_ms);
+        let action_app_data = Arc::clone(&app_data);
+        let action_simulation_thread = Arc::clone(&simulation_thread);
+
+        // Starts a run while `running` is locked, or clears it if there's nothing to simulate
+        let start_run = {
+            let running = Arc::clone(&running);
+            let app_data = Arc::clone(&app_data);
+            Arc::new(move |running_lock: &mut bool, run_while_held: bool| {
+                // Initialize simulation keys from latest app_data.
+                {
+                    let app_data_guard = app_data.lock().unwrap();
+                    let mut keys_lock = selected_keys.lock().unwrap();
+                    let mut behavior_lock = key_behavior.lock().unwrap();
+                    let ready = crate::simulator::initialize_simulation_keys(&app_data_guard, &mut keys_lock, &mut behavior_lock);
+                    if !ready {
+                        log::warn!("Nothing to simulate, skipping simulation start.");
+                        *running_lock = false;
+                        return;
+                    }
+                }
+                *running_lock = true;
+
+                // Use the static helper function to spawn the simulation thread
+                let handle = Self::spawn_simulation_thread(
+                    Arc::clone(&running),
+                    Arc::clone(&paused),
+                    Arc::clone(&interval_ms),
+                    Arc::clone(&selected_keys),
+                    Arc::clone(&key_behavior),
+                    Arc::clone(&app_data),
+                    run_while_held,
+                );
+                *simulation_thread.lock().unwrap() = Some(handle);
+            })
+        };
+        let action_start_run = Arc::clone(&start_run);
 
         start_global_hotkey_listener(
             Arc::clone(&running),
-            Arc::clone(&interval_ms),
-            Arc::clone(&selected_keys),
-            Arc::clone(&key_behavior),
+            Arc::clone(&self.interval_ms),
+            Arc::clone(&self.selected_keys),
+            Arc::clone(&self.key_behavior),
             Arc::clone(&previous_hotkey_state),
             Arc::clone(&last_toggle_time),
-            Arc::clone(&app_data),
-            Arc::new(move || {
-                log::info!("Global hotkey pressed.");
+            self.hotkey_settings.subscribe(),
+            Arc::new(move |edge| {
+                log::info!("Global hotkey {:?}.", edge);
+                if edge == HotkeyEdge::Pressed && Self::handle_recording_hotkey(&recorder, &app_data) {
+                    return;
+                }
+                let activation = app_data.lock().unwrap().global_keybind.activation;
                 let mut running_lock = running.lock().unwrap();
-                *running_lock = !*running_lock;
-                if *running_lock {
-                    // Initialize simulation keys from latest app_data.
-                    {
-                        let app_data_guard = app_data.lock().unwrap();
-                        let mut keys_lock = selected_keys.lock().unwrap();
-                        let mut behavior_lock = key_behavior.lock().unwrap();
-                        crate::simulator::initialize_simulation_keys(&app_data_guard, &mut keys_lock, &mut behavior_lock);
-                        if keys_lock.is_empty() {
-                            log::warn!("No valid keys for simulation, skipping simulation start.");
-                            *running_lock = false;
-                            return;
+                let run = match (edge, activation.is_hold()) {
+                    (HotkeyEdge::Pressed, false) => !*running_lock,
+                    (HotkeyEdge::Released, false) => return,
+                    (HotkeyEdge::Pressed, true) => true,
+                    (HotkeyEdge::Released, true) => false,
+                };
+                if run && !*running_lock {
+                    start_run(&mut *running_lock, activation.is_hold());
+                } else {
+                    *running_lock = run;
+                }
+            }),
+            Arc::new(move |action| {
+                log::info!("{} hotkey pressed.", action);
+                match action {
+                    HotkeyAction::Profile(name) => Self::handle_profile_hotkey(
+                        name,
+                        &action_running,
+                        &action_interval_ms,
+                        &action_app_data,
+                        &action_simulation_thread,
+                        Arc::clone(&action_start_run),
+                    ),
+                    HotkeyAction::Paste => Self::handle_paste_hotkey(&action_running, &action_app_data, &action_simulation_thread),
+                    HotkeyAction::Start => {
+                        let mut running_lock = action_running.lock().unwrap();
+                        if !*running_lock {
+                            action_start_run(&mut *running_lock, false);
+                        } else if std::mem::take(&mut *action_paused.lock().unwrap()) {
+                            log::info!("Resuming the simulation");
                         }
                     }
-
-                    // Use the static helper function to spawn the simulation thread
-                    Self::spawn_simulation_thread(
-                        Arc::clone(&running),
-                        Arc::clone(&interval_ms),
-                        Arc::clone(&selected_keys),
-                        Arc::clone(&key_behavior),
-                        Arc::clone(&app_data)
-                    );
+                    HotkeyAction::Stop => *action_running.lock().unwrap() = false,
+                    HotkeyAction::PauseResume => Self::toggle_paused(&action_running, &action_paused),
                 }
             })
         );
     }
 
+    // Pauses or resumes the current run; the pause hotkey and the Pause button share it.
+    fn toggle_paused(running: &Mutex<bool>, paused: &Mutex<bool>) {
+        if !*running.lock().unwrap() {
+            log::info!("Simulation isn't running, nothing to pause");
+            return;
+        }
+        let mut paused_lock = paused.lock().unwrap();
+        *paused_lock = !*paused_lock;
+        log::info!("{} the simulation", if *paused_lock { "Pausing" } else { "Resuming" });
+    }
+
+    // Runs a profile, stopping whatever runs now; stops the profile instead if it's the one running.
+    // The switch waits for the stopped run on its own thread, so other hotkeys aren't held up.
+    fn handle_profile_hotkey(
+        name: String,
+        running: &Arc<Mutex<bool>>,
+        interval_ms: &Arc<Mutex<u64>>,
+        app_data: &Arc<Mutex<AppData>>,
+        simulation_thread: &Arc<Mutex<Option<thread::JoinHandle<()>>>>,
+        start_run: Arc<dyn Fn(&mut bool, bool) + Send + Sync>,
+    ) {
+        let was_running = std::mem::replace(&mut *running.lock().unwrap(), false);
+        if was_running && app_data.lock().unwrap().active_profile.as_deref() == Some(name.as_str()) {
+            log::info!("Stopped profile {}", name);
+            return;
+        }
+
+        let running = Arc::clone(running);
+        let interval_ms = Arc::clone(interval_ms);
+        let app_data = Arc::clone(app_data);
+        let simulation_thread = Arc::clone(simulation_thread);
+        thread::spawn(move || {
+            // One switch at a time, so each waits for the run the one before started
+            static SWITCHING: Mutex<()> = Mutex::new(());
+            let _switching = SWITCHING.lock().unwrap();
+            *running.lock().unwrap() = false;
+
+            // The stopped run releases its keys before the next one creates its device
+            let previous = simulation_thread.lock().unwrap().take();
+            if let Some(handle) = previous {
+                if handle.join().is_err() {
+                    log::error!("The previous simulation thread panicked");
+                }
+            }
+
+            let mut running_lock = running.lock().unwrap();
+            if *running_lock {
+                log::info!("Another run started meanwhile, not switching to profile {}", name);
+                return;
+            }
+            {
+                let mut app_data = app_data.lock().unwrap();
+                if !app_data.load_profile(&name) {
+                    log::warn!("No profile named {}", name);
+                    return;
+                }
+                *interval_ms.lock().unwrap() = app_data.interval_ms;
+                if let Err(e) = save_app_data(&mut app_data) {
+                    log::error!("Failed to save app data: {}", e);
+                }
+            }
+            log::info!("Running profile {}", name);
+            start_run(&mut *running_lock, false);
+        });
+    }
+
+    // Types the clipboard text on its own thread; the main hotkey or Stop cancels it.
+    fn handle_paste_hotkey(
+        running: &Arc<Mutex<bool>>,
+        app_data: &Arc<Mutex<AppData>>,
+        simulation_thread: &Mutex<Option<thread::JoinHandle<()>>>,
+    ) {
+        {
+            let mut running_lock = running.lock().unwrap();
+            if *running_lock {
+                log::warn!("Simulation is running, ignoring the paste hotkey");
+                return;
+            }
+            *running_lock = true;
+        }
+
+        let (layout, fallback, profile, backend) = {
+            let app_data = app_data.lock().unwrap();
+            (app_data.keyboard_layout.clone(), app_data.unicode_fallback, app_data.typing_profile, app_data.hotkey_backend)
+        };
+        let running = Arc::clone(running);
+        // Kept like a run, so a profile hotkey waits for the typing to release its keys
+        let mut simulation_thread = simulation_thread.lock().unwrap();
+        let previous = simulation_thread.take();
+        *simulation_thread = Some(thread::spawn(move || {
+            // A stopped run may still be releasing its keys
+            if previous.is_some_and(|handle| handle.join().is_err()) {
+                log::error!("The previous simulation thread panicked");
+            }
+            let result = read_clipboard_text().and_then(|text| {
+                if text.is_empty() {
+                    log::info!("Clipboard has no text to type");
+                    return Ok(());
+                }
+                if !wait_for_keys_released(backend, Duration::from_millis(PASTE_RELEASE_TIMEOUT_MS)) {
+                    log::warn!("Keys are still held, typing the clipboard anyway");
+                }
+                type_text_once(&running, &text, &layout, fallback, profile)
+            });
+            if let Err(e) = result {
+                log::error!("Failed to type the clipboard: {}", e);
+            }
+            *running.lock().unwrap() = false;
+        }));
+    }
+
+    // Starts or finishes an armed macro recording; returns whether the hotkey was used for it.
+    fn handle_recording_hotkey(recorder: &Recorder, app_data: &Arc<Mutex<AppData>>) -> bool {
+        let mut app_data = app_data.lock().unwrap();
+        match app_data.recording {
+            RecordingState::Off => false,
+            RecordingState::Armed => {
+                match recorder.start() {
+                    Ok(()) => {
+                        log::info!("Macro recording started");
+                        app_data.recording = RecordingState::Recording;
+                    }
+                    Err(e) => {
+                        log::error!("Failed to start macro recording: {}", e);
+                        app_data.recording = RecordingState::Off;
+                    }
+                }
+                true
+            }
+            RecordingState::Recording => {
+                Self::finish_recording(recorder, &mut app_data);
+                if let Err(e) = save_app_data(&mut app_data) {
+                    log::error!("Failed to save recorded macro: {}", e);
+                }
+                true
+            }
+        }
+    }
+
+    // Stores the recording as a new macro and makes it the active one.
+    fn finish_recording(recorder: &Recorder, app_data: &mut AppData) {
+        let events = recorder.stop();
+        // The hotkey press that stopped the recording isn't part of the macro.
+        let hotkey = crate::utils::key_utils::normalize_key(&app_data.global_keybind.key);
+        let name = app_data.unused_macro_name("Recording");
+        let recorded = crate::recorder::events_to_macro(name.clone(), &events, &[hotkey]);
+
+        log::info!("Recorded macro {} with {} steps", name, recorded.steps.len());
+        app_data.macros.push(recorded);
+        app_data.active_macro = Some(name);
+        app_data.key_behavior = KeyBehaviorMode::Macro;
+        app_data.recording = RecordingState::Off;
+    }
+
+    fn handle_toggle_recording(&mut self) {
+        let recorder = Arc::clone(&self.recorder);
+        self.update_state(|app_data| match app_data.recording {
+            RecordingState::Off => {
+                log::info!("Macro recording armed, waiting for the global hotkey");
+                app_data.recording = RecordingState::Armed;
+            }
+            RecordingState::Armed => app_data.recording = RecordingState::Off,
+            RecordingState::Recording => Self::finish_recording(&recorder, app_data),
+        });
+    }
+
     // Helper: Toggle running state and start simulation if needed.
     fn handle_toggle_running(&mut self) {
         let mut running = self.running.lock().unwrap();
@@ -395,7 +787,10 @@ impl InputSimulatorApp {
     {
         let result = {
             let mut app_data = self.app_data.lock().unwrap();
-            update_fn(&mut app_data)
+            let result = update_fn(&mut app_data);
+            // The hotkey listener only hears about changes it cares about
+            self.hotkey_settings.send_if_changed(app_data.hotkey_settings());
+            result
         };
         let _ = self.save_app_data();
         result
@@ -448,6 +843,425 @@ impl InputSimulatorApp {
         });
     }
 
+    fn handle_update_key_weight(&mut self, key: String, input: String) {
+        match input.trim().parse::<u32>() {
+            Ok(weight) => {
+                let weight = weight.min(MAX_KEY_WEIGHT);
+                self.update_state(|app_data| {
+                    log::info!("Weight for {} updated to {}", key, weight);
+                    app_data.key_weights.insert(key, weight);
+                });
+            }
+            Err(_) => log::warn!("Invalid weight input for {}: {}", key, input),
+        }
+    }
+
+    fn handle_toggle_random_no_repeat(&mut self, enabled: bool) {
+        self.update_state(|app_data| {
+            app_data.random_no_repeat = enabled;
+            log::info!("Random no-repeat set to: {}", enabled);
+        });
+    }
+
+    fn handle_update_random_seed(&mut self, input: String) {
+        let input = input.trim();
+        let seed = if input.is_empty() {
+            None
+        } else if let Ok(seed) = input.parse::<u64>() {
+            Some(seed)
+        } else {
+            log::warn!("Invalid random seed input: {}", input);
+            return;
+        };
+        self.update_state(|app_data| {
+            app_data.random_seed = seed;
+            log::info!("Random seed set to: {:?}", seed);
+        });
+    }
+
+    fn handle_update_step_delay(&mut self, index: usize, input: String) {
+        match input.trim().parse::<u64>() {
+            Ok(delay) => {
+                let delay = delay.min(MAX_INTERVAL_MS);
+                self.update_state(|app_data| {
+                    if index >= app_data.selected_keys.len() {
+                        return;
+                    }
+                    if app_data.sequence_delays.len() <= index {
+                        app_data.sequence_delays.resize(app_data.selected_keys.len(), DEFAULT_STEP_DELAY_MS);
+                    }
+                    log::info!("Delay after step {} updated to {} ms", index + 1, delay);
+                    app_data.sequence_delays[index] = delay;
+                });
+            }
+            Err(_) => log::warn!("Invalid delay input for step {}: {}", index + 1, input),
+        }
+    }
+
+    fn handle_set_playback_speed(&mut self, speed: f64) {
+        let speed = speed.clamp(MIN_PLAYBACK_SPEED, MAX_PLAYBACK_SPEED);
+        self.update_state(|app_data| {
+            app_data.macro_playback.speed = speed;
+            log::info!("Macro playback speed set to {}x", speed);
+        });
+    }
+
+    fn handle_update_max_idle(&mut self, input: String) {
+        let input = input.trim();
+        let max_idle_ms = if input.is_empty() {
+            None
+        } else if let Ok(ms) = input.parse::<u64>() {
+            Some(ms)
+        } else {
+            log::warn!("Invalid idle limit input: {}", input);
+            return;
+        };
+        self.update_state(|app_data| {
+            app_data.macro_playback.max_idle_ms = max_idle_ms;
+            log::info!("Macro idle gap limit set to: {:?}", max_idle_ms);
+        });
+    }
+
+    // Applies a number typed into the typing profile controls; blank input passes None
+    fn handle_update_typing_profile(&mut self, input: String, label: &str, apply: impl FnOnce(&mut TypingProfile, Option<u64>)) {
+        let input = input.trim();
+        let value = if input.is_empty() {
+            None
+        } else if let Ok(value) = input.parse::<u64>() {
+            Some(value)
+        } else {
+            log::warn!("Invalid {} input: {}", label, input);
+            return;
+        };
+        self.update_state(|app_data| {
+            apply(&mut app_data.typing_profile, value);
+            log::info!("Typing profile updated: {:?}", app_data.typing_profile);
+        });
+    }
+
+    fn handle_update_loop_count(&mut self, input: String) {
+        let input = input.trim();
+        let loop_count = if input.is_empty() {
+            0
+        } else if let Ok(count) = input.parse::<u32>() {
+            count
+        } else {
+            log::warn!("Invalid loop count input: {}", input);
+            return;
+        };
+        self.update_state(|app_data| {
+            app_data.macro_playback.loop_count = loop_count;
+            log::info!("Macro loop count set to: {}", loop_count);
+        });
+    }
+
+    fn handle_update_release_delay(&mut self, input: String) {
+        let input = input.trim();
+        let release_delay_ms = if input.is_empty() {
+            0
+        } else if let Ok(delay) = input.parse::<u64>() {
+            delay
+        } else {
+            log::warn!("Invalid release delay input: {}", input);
+            return;
+        };
+        self.update_state(|app_data| {
+            if let HotkeyActivation::HoldWithDelay { .. } = app_data.global_keybind.activation {
+                app_data.global_keybind.activation = HotkeyActivation::HoldWithDelay { release_delay_ms };
+                log::info!("Hotkey release delay set to: {} ms", release_delay_ms);
+            }
+        });
+    }
+
+    fn handle_save_profile(&mut self) {
+        self.update_state(|app_data| {
+            let name = std::mem::take(&mut app_data.profile_name_input).trim().to_string();
+            if name.is_empty() {
+                return;
+            }
+            log::info!("Saved the current settings as profile {}", name);
+            app_data.save_profile(name);
+        });
+    }
+
+    fn handle_load_profile(&mut self, name: String) {
+        let interval = self.update_state(|app_data| {
+            if !app_data.load_profile(&name) {
+                log::warn!("No profile named {}", name);
+                return None;
+            }
+            log::info!("Loaded profile {}", name);
+            Some(app_data.interval_ms)
+        });
+        if let Some(interval) = interval {
+            *self.interval_ms.lock().unwrap() = interval;
+        }
+    }
+
+    fn handle_select_macro(&mut self, name: String) {
+        self.update_state(|app_data| {
+            log::info!("Active macro set to: {}", name);
+            app_data.active_macro = Some(name);
+            app_data.timeline = TimelineState::default();
+        });
+    }
+
+    // Applies an edit to the active macro and its timeline selection, then saves
+    fn update_timeline(&mut self, edit: impl FnOnce(&mut Macro, &mut TimelineState)) {
+        self.update_state(|app_data| {
+            let mut timeline = std::mem::take(&mut app_data.timeline);
+            if let Some(active) = app_data.active_macro_mut() {
+                edit(active, &mut timeline);
+            }
+            app_data.timeline = timeline;
+        });
+    }
+
+    fn handle_start_macro_step_drag(&mut self, index: usize) {
+        let timeline = &mut self.app_data.lock().unwrap().timeline;
+        timeline.selected = Some(index);
+        timeline.dragging = Some(index);
+        timeline.drop_target = Some(index);
+    }
+
+    fn handle_hover_macro_step(&mut self, index: usize) {
+        let timeline = &mut self.app_data.lock().unwrap().timeline;
+        if timeline.dragging.is_some() {
+            timeline.drop_target = Some(index);
+        }
+    }
+
+    fn handle_drop_macro_step(&mut self) {
+        let (dragging, drop_target) = {
+            let timeline = &mut self.app_data.lock().unwrap().timeline;
+            (timeline.dragging.take(), timeline.drop_target.take())
+        };
+        if let (Some(from), Some(to)) = (dragging, drop_target) {
+            if from != to {
+                self.handle_move_macro_step(from, to);
+            }
+        }
+    }
+
+    fn handle_cancel_macro_step_drag(&mut self) {
+        let timeline = &mut self.app_data.lock().unwrap().timeline;
+        timeline.dragging = None;
+        timeline.drop_target = None;
+    }
+
+    fn handle_move_macro_step(&mut self, from: usize, to: usize) {
+        self.update_timeline(|active, timeline| {
+            if active.move_step(from, to) {
+                log::info!("Moved step {} of {} to {}", from + 1, active.name, to + 1);
+                timeline.selected = Some(to);
+            }
+        });
+    }
+
+    // Field edits are saved by `save_settled_step_edit` rather than on every keystroke
+    fn handle_update_macro_step(&mut self, index: usize, field: StepField, value: String) {
+        let app_data = &mut *self.app_data.lock().unwrap();
+        let Some(active) = app_data.active_macro_mut() else {
+            return;
+        };
+        match active.steps.get_mut(index) {
+            Some(step) if step.set_field(field, &value) => self.step_edit_at = Some(Instant::now()),
+            Some(_) => log::warn!("Invalid {:?} input for step {}: {}", field, index + 1, value),
+            None => log::warn!("No step {} in {}", index + 1, active.name),
+        }
+    }
+
+    fn save_settled_step_edit(&mut self) {
+        let settled = self.step_edit_at
+            .is_some_and(|edited| edited.elapsed() >= Duration::from_millis(STEP_EDIT_SAVE_MS));
+        if settled {
+            self.step_edit_at = None;
+            let _ = self.save_app_data();
+        }
+    }
+
+    fn handle_commit_macro_step(&mut self) {
+        if self.step_edit_at.take().is_some() {
+            let _ = self.save_app_data();
+        }
+    }
+
+    fn handle_insert_macro_step(&mut self, kind: usize) {
+        let Some(step) = MacroStep::from_kind(kind) else {
+            return;
+        };
+        self.update_timeline(|active, timeline| {
+            let index = timeline.selected
+                .map_or(active.steps.len(), |selected| (selected + 1).min(active.steps.len()));
+            log::info!("Inserted {} step at {} in {}", step.kind(), index + 1, active.name);
+            active.steps.insert(index, step);
+            timeline.selected = Some(index);
+        });
+    }
+
+    fn handle_delete_macro_step(&mut self, index: usize) {
+        self.update_timeline(|active, timeline| {
+            if index >= active.steps.len() {
+                return;
+            }
+            let step = active.steps.remove(index);
+            log::info!("Deleted {} step {} from {}", step.kind(), index + 1, active.name);
+            // The next step takes the deleted one's place in the selection
+            timeline.selected = active.steps.len().checked_sub(1).map(|last| index.min(last));
+        });
+    }
+
+    fn handle_play_macro_from_step(&mut self, index: usize) {
+        if *self.running.lock().unwrap() {
+            log::warn!("Simulation already running, not playing from step {}", index + 1);
+            return;
+        }
+        self.app_data.lock().unwrap().timeline.play_from = Some(index);
+        log::info!("Playing the active macro from step {}", index + 1);
+        self.handle_toggle_running();
+    }
+
+    // Helper: Store the selected keys as a new macro of taps, using the sequence step delays.
+    fn handle_save_keys_as_macro(&mut self) {
+        self.update_state(|app_data| {
+            if app_data.selected_keys.is_empty() {
+                log::warn!("No keys selected, not creating a macro");
+                return;
+            }
+            let name = app_data.unused_macro_name("Macro");
+            let new_macro = Macro::from_keys(name.clone(), &app_data.selected_keys, |index| app_data.step_delay(index));
+            log::info!("Created macro {} with {} steps", name, new_macro.steps.len());
+            app_data.macros.push(new_macro);
+            app_data.active_macro = Some(name);
+        });
+    }
+
+    fn handle_update_macro_file(&mut self, path: String) {
+        self.update_state(|app_data| {
+            app_data.macro_file = path;
+            app_data.macro_file_error = None;
+        });
+    }
+
+    fn handle_load_macro_file(&mut self) {
+        self.load_macro_file_with(parse_macro);
+    }
+
+    fn handle_import_evemu_file(&mut self) {
+        self.load_macro_file_with(|name, source| Ok(evemu_to_macro(name, &parse_evemu(source)?)));
+    }
+
+    // Loads the macro file as a macro named after the file, replacing one with the same name.
+    fn load_macro_file_with(&mut self, parse: impl FnOnce(String, &str) -> crate::error::Result<Macro>) {
+        self.update_state(|app_data| {
+            let path = std::path::Path::new(&app_data.macro_file);
+            let name = path.file_stem()
+                .map(|stem| stem.to_string_lossy().into_owned())
+                .unwrap_or_else(|| app_data.unused_macro_name("Macro"));
+
+            let loaded = std::fs::read_to_string(path)
+                .map_err(AppError::from)
+                .and_then(|source| parse(name.clone(), &source));
+            match loaded {
+                Ok(loaded) => {
+                    log::info!("Loaded macro {} with {} steps from {}", name, loaded.steps.len(), path.display());
+                    app_data.macros.retain(|m| m.name != name);
+                    app_data.macros.push(loaded);
+                    app_data.active_macro = Some(name);
+                    app_data.macro_file_error = None;
+                }
+                Err(e) => {
+                    log::error!("Failed to load macro file {}: {}", path.display(), e);
+                    app_data.macro_file_error = Some(e.to_string());
+                }
+            }
+        });
+    }
+
+    fn handle_save_macro_file(&mut self) {
+        self.save_macro_file_with(|_, active| format_macro(active));
+    }
+
+    fn handle_export_evemu_file(&mut self) {
+        self.save_macro_file_with(|app_data, active| {
+            let keymap = if active.typed_text().is_empty() {
+                LayoutKeymap::default()
+            } else {
+                LayoutKeymap::new(&app_data.keyboard_layout).unwrap_or_else(|e| {
+                    log::error!("{}", e);
+                    LayoutKeymap::default()
+                })
+            };
+            format_evemu(&active.name, &macro_to_evemu(&active.steps, &keymap))
+        });
+    }
+
+    // Writes the active macro to the macro file in the format `format` produces.
+    fn save_macro_file_with(&mut self, format: impl FnOnce(&AppData, &Macro) -> String) {
+        self.update_state(|app_data| {
+            let Some(active) = app_data.active_macro() else {
+                app_data.macro_file_error = Some("No macro selected".to_string());
+                return;
+            };
+            let source = format(app_data, active);
+            match std::fs::write(&app_data.macro_file, source) {
+                Ok(()) => {
+                    log::info!("Saved macro to {}", app_data.macro_file);
+                    app_data.macro_file_error = None;
+                }
+                Err(e) => {
+                    log::error!("Failed to save macro file {}: {}", app_data.macro_file, e);
+                    app_data.macro_file_error = Some(e.to_string());
+                }
+            }
+        });
+    }
+
+    fn handle_update_script_file(&mut self, path: String) {
+        self.update_state(|app_data| {
+            app_data.script_file = path;
+            app_data.script_status = None;
+        });
+    }
+
+    fn handle_check_script(&mut self) {
+        self.update_state(|app_data| {
+            let checked = std::fs::read_to_string(&app_data.script_file)
+                .map_err(AppError::from)
+                .and_then(|source| crate::script::check_script(&source));
+            app_data.script_status = Some(match checked {
+                Ok(()) => "Script OK".to_string(),
+                Err(e) => e.to_string(),
+            });
+        });
+    }
+
+    fn handle_save_text_as_macro(&mut self) {
+        self.update_state(|app_data| {
+            let text = std::mem::take(&mut app_data.type_text_input);
+            if text.is_empty() {
+                return;
+            }
+            let name = app_data.unused_macro_name("Text");
+            log::info!("Created macro {} typing {} characters", name, text.chars().count());
+            app_data.macros.push(Macro { name: name.clone(), steps: vec![MacroStep::Type { text, profile: None }] });
+            app_data.active_macro = Some(name);
+        });
+    }
+
+    // Helper: Edit one key's override, starting from its current (or default) settings.
+    fn handle_update_key_override<F>(&mut self, key: String, edit: F)
+    where
+        F: FnOnce(&mut KeyOverride)
+    {
+        self.update_state(|app_data| {
+            let mut key_override = app_data.key_override(&key);
+            edit(&mut key_override);
+            log::info!("Override for {} updated to {:?}", key, key_override);
+            app_data.key_overrides.insert(key, key_override);
+        });
+    }
+
     fn handle_capture_keys(&mut self) {
         *self.capturing.lock().unwrap() = true;
         self.update_state(|app_data| {
@@ -461,14 +1275,28 @@ impl InputSimulatorApp {
         self.update_state(|app_data| {
             log::info!("Finalizing captured keys: {:?}", app_data.captured_keys);
             app_data.selected_keys = app_data.captured_keys.clone();
+            // Steps may have moved, so start the new sequence from default delays.
+            app_data.sequence_delays = vec![DEFAULT_STEP_DELAY_MS; app_data.selected_keys.len()];
         });
     }
 
-    fn handle_capture_global_hotkey(&mut self) {
+    fn handle_capture_global_hotkey(&mut self, target: HotkeyTarget) {
         *self.capturing_hotkey.lock().unwrap() = true;
         self.update_state(|app_data| {
             app_data.capturing_global_hotkey = true;
-            app_data.temp_hotkey = TempHotkeyState::default();
+            let current = match &target {
+                HotkeyTarget::Simulation => Some(&app_data.global_keybind),
+                HotkeyTarget::Action(action) => app_data.action_keybind(action),
+            }
+            .map(|hotkey| hotkey.modifiers)
+            .unwrap_or_default();
+            // A recaptured hotkey keeps how it matches modifiers
+            app_data.temp_hotkey = TempHotkeyState {
+                target,
+                modifiers: HotkeyModifiers { exact: current.exact, ..HotkeyModifiers::default() },
+                distinguish_sides: current != current.without_sides(),
+                ..TempHotkeyState::default()
+            };
         });
     }
 
@@ -477,20 +1305,27 @@ impl InputSimulatorApp {
         self.update_state(|app_data| {
             if let Some(key) = &app_data.temp_hotkey.key {
                 let normalized = crate::utils::key_utils::normalize_key(key);
-                let modifiers = &app_data.temp_hotkey.modifiers;
-                let hotkey_desc = format!(
-                    "{}{}{}{}{}",
-                    if modifiers.ctrl { "Ctrl+" } else { "" },
-                    if modifiers.alt { "Alt+" } else { "" },
-                    if modifiers.shift { "Shift+" } else { "" },
-                    if modifiers.super_key { "Super+" } else { "" },
-                    normalized
-                );
-                log::info!("Setting new global hotkey: {}", hotkey_desc);
-                app_data.global_keybind = GlobalHotkey {
+                let modifiers = app_data.temp_hotkey.saved_modifiers();
+                let hotkey_desc = crate::ui::components::format_hotkey_text(&modifiers, Some(&normalized));
+                let hotkey = GlobalHotkey {
                     key: normalized,
-                    modifiers: app_data.temp_hotkey.modifiers,
+                    modifiers,
+                    ..GlobalHotkey::default()
                 };
+                match app_data.temp_hotkey.target.clone() {
+                    HotkeyTarget::Simulation => {
+                        log::info!("Setting new global hotkey: {}", hotkey_desc);
+                        // A new key keeps the activation mode
+                        app_data.global_keybind = GlobalHotkey { activation: app_data.global_keybind.activation, ..hotkey };
+                    }
+                    HotkeyTarget::Action(action) => {
+                        if app_data.set_action_keybind(&action, Some(hotkey)) {
+                            log::info!("Setting new {} hotkey: {}", action, hotkey_desc);
+                        } else {
+                            log::warn!("{} is the global hotkey, keeping the {} hotkey", hotkey_desc, action);
+                        }
+                    }
+                }
             }
             app_data.capturing_global_hotkey = false;
         });
@@ -504,16 +1339,61 @@ impl InputSimulatorApp {
         
         self.update_state(|app_data| {
             if app_data.capturing_global_hotkey {
+                let modifiers = &mut app_data.temp_hotkey.modifiers;
+                let held = [
+                    key_event.modifiers.control(),
+                    key_event.modifiers.alt(),
+                    key_event.modifiers.shift(),
+                    key_event.modifiers.logo(),
+                ];
+                let flags = [
+                    (&mut modifiers.ctrl, &mut modifiers.ctrl_side),
+                    (&mut modifiers.alt, &mut modifiers.alt_side),
+                    (&mut modifiers.shift, &mut modifiers.shift_side),
+                    (&mut modifiers.super_key, &mut modifiers.super_side),
+                ];
+                // A modifier keeps the side it was pressed with while it stays held
+                for ((set, side), held) in flags.into_iter().zip(held) {
+                    *set = held;
+                    if !held {
+                        *side = ModifierSide::Either;
+                    }
+                }
+                let side = match key_event.location {
+                    keyboard::Location::Left => ModifierSide::Left,
+                    keyboard::Location::Right => ModifierSide::Right,
+                    _ => ModifierSide::Either,
+                };
+                match normalized.as_str() {
+                    "Control" => modifiers.ctrl_side = side,
+                    "Alt" => modifiers.alt_side = side,
+                    "Shift" => modifiers.shift_side = side,
+                    "Super" => modifiers.super_side = side,
+                    _ => {}
+                }
                 app_data.temp_hotkey.key = Some(normalized);
-                let temp_hotkey = &mut app_data.temp_hotkey;
-                temp_hotkey.modifiers.ctrl = key_event.modifiers.control();
-                temp_hotkey.modifiers.alt = key_event.modifiers.alt();
-                temp_hotkey.modifiers.shift = key_event.modifiers.shift();
-                temp_hotkey.modifiers.super_key = key_event.modifiers.logo();
             } else if is_capturing {
-                if !app_data.captured_keys.contains(&normalized) {
-                    log::debug!("Captured new key: {}", normalized);
-                    app_data.captured_keys.push(normalized);
+                let modifiers = HotkeyModifiers {
+                    ctrl: key_event.modifiers.control(),
+                    alt: key_event.modifiers.alt(),
+                    shift: key_event.modifiers.shift(),
+                    super_key: key_event.modifiers.logo(),
+                    ..HotkeyModifiers::default()
+                };
+                let entry = if modifiers.is_empty() || crate::utils::key_utils::is_modifier_key_name(&normalized) {
+                    normalized
+                } else {
+                    // The held modifiers were captured as bare keys when pressed; fold them into the chord.
+                    while app_data.captured_keys.last().is_some_and(|last| modifiers.includes_key(last)) {
+                        app_data.captured_keys.pop();
+                    }
+                    KeyChord { modifiers, key: normalized }.to_string()
+                };
+                // Sequences keep every press in order, duplicates included.
+                let is_sequence = app_data.key_behavior == KeyBehaviorMode::Sequence;
+                if is_sequence || !app_data.captured_keys.contains(&entry) {
+                    log::debug!("Captured new key: {}", entry);
+                    app_data.captured_keys.push(entry);
                 }
             }
         });
@@ -539,6 +1419,8 @@ impl InputSimulatorApp {
 #[derive(Debug, Clone)]
 pub enum Message {
     ToggleRunning,
+    /// Pauses or resumes the current run.
+    TogglePaused,
     SetInterval(u64),
     CaptureKeys,
     AddKey(KeyEvent),
@@ -549,15 +1431,81 @@ pub enum Message {
     SetIntervalAndSave(u64),
     UpdateKeyBehaviorMode(KeyBehaviorMode),
     CaptureGlobalHotkey,
+    CaptureActionHotkey(HotkeyAction),
+    ClearActionHotkey(HotkeyAction),
+    UpdateProfileName(String),
+    SaveProfile,
+    LoadProfile(String),
+    DeleteProfile(String),
     FinalizeGlobalHotkey,
     CancelGlobalHotkey,
+    ToggleExactHotkey(bool),
+    ToggleHotkeySides(bool),
     UpdateModifierBehaviorMode(ModifierBehaviorMode),
     UpdateHoldBehaviorMode(HoldBehaviorMode),
+    UpdateKeyWeight(String, String),
+    ToggleRandomNoRepeat(bool),
+    UpdateRandomSeed(String),
+    UpdateStepDelay(usize, String),
+    SelectMacro(String),
+    SaveKeysAsMacro,
+    SelectMacroStep(usize),
+    StartMacroStepDrag(usize),
+    HoverMacroStep(usize),
+    DropMacroStep,
+    /// Ends a step drag that left the timeline or was released elsewhere.
+    CancelMacroStepDrag,
+    MoveMacroStep(usize, usize),
+    UpdateMacroStep(usize, StepField, String),
+    /// Saves step field edits right away instead of once typing settles.
+    CommitMacroStep,
+    /// Inserts a step of `STEP_KINDS[index]` after the selected step.
+    InsertMacroStep(usize),
+    DeleteMacroStep(usize),
+    PlayMacroFromStep(usize),
+    ToggleRecording,
+    SetPlaybackSpeed(f64),
+    UpdateMacroFile(String),
+    LoadMacroFile,
+    SaveMacroFile,
+    ImportEvemuFile,
+    ExportEvemuFile,
+    ImportXdotoolFile,
+    UpdateTraceFile(String),
+    UpdateHotkeyBackend(HotkeyBackend),
+    UpdateHotkeyActivation(HotkeyActivation),
+    UpdateReleaseDelay(String),
+    UpdateScriptFile(String),
+    CheckScript,
+    UpdateKeyboardLayout(String),
+    UpdateKeyboardVariant(String),
+    UpdateUnicodeFallback(UnicodeFallback),
+    ToggleHumanizeTyping(bool),
+    UpdateTypingDelay(String),
+    SetTypingJitter(f64),
+    UpdatePunctuationPause(String),
+    UpdateSpacePause(String),
+    SetBigramVariation(f64),
+    UpdateBurstLength(String),
+    UpdateTypingSeed(String),
+    UpdateTypeText(String),
+    SaveTextAsMacro,
+    UpdateMaxIdle(String),
+    UpdateLoopCount(String),
+    UpdateKeyOverrideMode(String, KeyBehaviorMode),
+    UpdateKeyOverrideInterval(String, String),
+    UpdateKeyOverridePressDuration(String, String),
     ToggleSettingsPanel,
     RefreshUiState,
 }
 
 // Timer subscription that periodically sends a message to refresh the UI state
+// A millisecond text field's value; an empty field counts as 0
+fn parse_millis(input: &str) -> Option<u64> {
+    let input = input.trim();
+    if input.is_empty() { Some(0) } else { input.parse().ok() }
+}
+
 fn timer_subscription(interval_ms: u64) -> Subscription<Message> {
     use cosmic::iced::futures::stream;
 
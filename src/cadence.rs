@@ -0,0 +1,204 @@
+//! Human-looking typing rhythm for typed text. Each character waits a log-normally
+//! distributed delay around the profile's average, scaled by a fixed factor per pair of
+//! consecutive characters, with extra pauses after spaces and punctuation. Burst typing
+//! types runs of characters quickly with a hesitation between runs.
+
+use std::{collections::HashMap, f64::consts::TAU, time::Duration};
+
+use rand::{rngs::StdRng, Rng};
+use serde::{Deserialize, Serialize};
+
+use crate::{constants::TYPE_KEY_DELAY_MS, utils::random::create_rng};
+
+// Characters in a burst take this share of the average delay
+const BURST_SPEEDUP: f64 = 0.4;
+// The hesitation between bursts, in average delays
+const BURST_PAUSE_FACTOR: f64 = 4.0;
+
+/// Timing of typed text.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct TypingProfile {
+    /// Off types every character after a fixed short delay.
+    pub humanize: bool,
+    /// Average delay after a character.
+    pub delay_ms: u64,
+    /// Spread of the per-character delay, as the sigma of a log-normal distribution.
+    pub jitter: f64,
+    /// Extra pause after punctuation.
+    pub punctuation_pause_ms: u64,
+    /// Extra pause after whitespace.
+    pub space_pause_ms: u64,
+    /// How much faster or slower a pair of consecutive characters is typed, e.g. 0.2 = ±20%.
+    pub bigram_variation: f64,
+    /// Characters per burst; 0 turns burst typing off.
+    pub burst_length: u32,
+    /// Seed for reproducible timing; random when unset.
+    pub seed: Option<u64>,
+}
+
+impl Default for TypingProfile {
+    fn default() -> Self {
+        Self {
+            humanize: false,
+            delay_ms: 120,
+            jitter: 0.35,
+            punctuation_pause_ms: 300,
+            space_pause_ms: 80,
+            bigram_variation: 0.2,
+            burst_length: 0,
+            seed: None,
+        }
+    }
+}
+
+impl TypingProfile {
+    /// The same delay after every character, like `xdotool type --delay`.
+    pub fn fixed(delay_ms: u64) -> Self {
+        Self {
+            humanize: true,
+            delay_ms,
+            jitter: 0.0,
+            punctuation_pause_ms: 0,
+            space_pause_ms: 0,
+            bigram_variation: 0.0,
+            burst_length: 0,
+            seed: None,
+        }
+    }
+}
+
+/// Draws the delays of a typing run from a `TypingProfile`.
+#[derive(Debug)]
+pub struct Cadence {
+    profile: TypingProfile,
+    rng: StdRng,
+    bigrams: HashMap<(char, char), f64>,
+    previous: Option<char>,
+    burst_left: u32,
+}
+
+impl Cadence {
+    pub fn new(profile: TypingProfile) -> Self {
+        Self {
+            profile,
+            rng: create_rng(profile.seed),
+            bigrams: HashMap::new(),
+            previous: None,
+            burst_left: 0,
+        }
+    }
+
+    /// The wait after typing `c`.
+    pub fn delay_after(&mut self, c: char) -> Duration {
+        if !self.profile.humanize {
+            return Duration::from_millis(TYPE_KEY_DELAY_MS);
+        }
+
+        let mut delay = self.profile.delay_ms as f64 * self.jitter_factor() * self.bigram_factor(c);
+
+        if self.profile.burst_length > 0 {
+            if self.burst_left == 0 {
+                self.burst_left = self.profile.burst_length;
+                delay += self.profile.delay_ms as f64 * BURST_PAUSE_FACTOR;
+            } else {
+                delay *= BURST_SPEEDUP;
+            }
+            self.burst_left -= 1;
+        }
+
+        if c.is_whitespace() {
+            delay += self.profile.space_pause_ms as f64 * self.jitter_factor();
+        } else if is_punctuation(c) {
+            delay += self.profile.punctuation_pause_ms as f64 * self.jitter_factor();
+        }
+
+        self.previous = Some(c);
+        Duration::from_millis(delay.max(0.0).round() as u64)
+    }
+
+    // Log-normal with a mean of 1, from a Box-Muller normal sample
+    fn jitter_factor(&mut self) -> f64 {
+        let sigma = self.profile.jitter.max(0.0);
+        let u1: f64 = 1.0 - self.rng.random::<f64>();
+        let u2: f64 = self.rng.random();
+        let z = (-2.0 * u1.ln()).sqrt() * (TAU * u2).cos();
+        (sigma * z - sigma * sigma / 2.0).exp()
+    }
+
+    // Fixed for the run, so repeated pairs keep their rhythm
+    fn bigram_factor(&mut self, c: char) -> f64 {
+        let variation = self.profile.bigram_variation.clamp(0.0, 0.9);
+        let Some(previous) = self.previous else {
+            return 1.0;
+        };
+        if variation == 0.0 {
+            return 1.0;
+        }
+        let rng = &mut self.rng;
+        *self.bigrams
+            .entry((previous, c))
+            .or_insert_with(|| 1.0 + rng.random_range(-variation..=variation))
+    }
+}
+
+fn is_punctuation(c: char) -> bool {
+    c.is_ascii_punctuation() || matches!(c, '。' | '、' | '，' | '…' | '¿' | '¡')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn humanized(seed: u64) -> TypingProfile {
+        TypingProfile { humanize: true, seed: Some(seed), ..TypingProfile::default() }
+    }
+
+    fn delays(profile: TypingProfile, text: &str) -> Vec<Duration> {
+        let mut cadence = Cadence::new(profile);
+        text.chars().map(|c| cadence.delay_after(c)).collect()
+    }
+
+    #[test]
+    fn test_fixed_delay_when_off() {
+        let expected = Duration::from_millis(TYPE_KEY_DELAY_MS);
+        assert!(delays(TypingProfile::default(), "Hi, you.").iter().all(|&d| d == expected));
+    }
+
+    #[test]
+    fn test_seed_reproduces_timing() {
+        let text = "The quick brown fox, again and again.";
+        assert_eq!(delays(humanized(7), text), delays(humanized(7), text));
+        assert_ne!(delays(humanized(7), text), delays(humanized(8), text));
+    }
+
+    #[test]
+    fn test_pauses_after_punctuation_and_spaces() {
+        // Without jitter or pair variation only the pauses differ
+        let profile = TypingProfile { jitter: 0.0, bigram_variation: 0.0, ..humanized(1) };
+        let d = delays(profile, "a b.");
+        assert_eq!(d[0], Duration::from_millis(120));
+        assert_eq!(d[1], Duration::from_millis(200));
+        assert_eq!(d[3], Duration::from_millis(420));
+    }
+
+    #[test]
+    fn test_bigram_factor_repeats() {
+        let profile = TypingProfile { jitter: 0.0, ..humanized(3) };
+        let d = delays(profile, "ththth");
+        assert_eq!(d[1], d[3]);
+        assert_eq!(d[3], d[5]);
+        assert_eq!(d[2], d[4]);
+    }
+
+    #[test]
+    fn test_burst_typing() {
+        let profile = TypingProfile { jitter: 0.0, bigram_variation: 0.0, burst_length: 3, ..humanized(1) };
+        let d = delays(profile, "abcdef");
+        // Each burst opens with a hesitation, then types quickly
+        assert_eq!(d[0], Duration::from_millis(600));
+        assert_eq!(d[1], Duration::from_millis(48));
+        assert_eq!(d[2], Duration::from_millis(48));
+        assert_eq!(d[3], Duration::from_millis(600));
+    }
+}
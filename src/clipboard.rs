@@ -0,0 +1,61 @@
+//! Reads the clipboard text for the paste hotkey, with `wl-paste` on Wayland and `xclip` on X11.
+
+use std::process::Command;
+
+use crate::error::{AppError, Result};
+
+// Clipboard readers in the order they are tried; Wayland sessions prefer wl-paste
+fn clipboard_commands(wayland: bool) -> Vec<(&'static str, &'static [&'static str])> {
+    let wl_paste: (&str, &[&str]) = ("wl-paste", &["--no-newline", "--type", "text/plain"]);
+    let xclip: (&str, &[&str]) = ("xclip", &["-selection", "clipboard", "-out"]);
+    if wayland {
+        vec![wl_paste, xclip]
+    } else {
+        vec![xclip, wl_paste]
+    }
+}
+
+// Line endings become plain newlines so each line break types a single Return
+fn normalize_clipboard_text(text: &str) -> String {
+    text.replace("\r\n", "\n").replace('\r', "\n")
+}
+
+/// The current clipboard text.
+pub fn read_clipboard_text() -> Result<String> {
+    let wayland = std::env::var_os("WAYLAND_DISPLAY").is_some();
+    let mut failures = Vec::new();
+
+    for (program, args) in clipboard_commands(wayland) {
+        match Command::new(program).args(args).output() {
+            Ok(output) if output.status.success() => {
+                let text = String::from_utf8_lossy(&output.stdout);
+                return Ok(normalize_clipboard_text(&text));
+            }
+            Ok(output) => failures.push(format!(
+                "{}: {}",
+                program,
+                String::from_utf8_lossy(&output.stderr).trim()
+            )),
+            Err(e) => failures.push(format!("{}: {}", program, e)),
+        }
+    }
+
+    Err(AppError::Clipboard(failures.join("; ")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_clipboard_command_order() {
+        let programs = |wayland| clipboard_commands(wayland).into_iter().map(|(program, _)| program).collect::<Vec<_>>();
+        assert_eq!(programs(true), ["wl-paste", "xclip"]);
+        assert_eq!(programs(false), ["xclip", "wl-paste"]);
+    }
+
+    #[test]
+    fn test_normalize_clipboard_text() {
+        assert_eq!(normalize_clipboard_text("a\r\nb\rc\n"), "a\nb\nc\n");
+    }
+}
@@ -1,15 +1,34 @@
 use serde::{Serialize, Deserialize};
+use std::collections::HashMap;
+use crate::cadence::TypingProfile;
+use crate::macros::{Macro, PlaybackOptions};
+use crate::utils::key_utils::normalize_key;
 use std::str::FromStr;
 
-const KEY_BEHAVIOR_MODES: [(&str, KeyBehaviorMode); 2] = [
+const KEY_BEHAVIOR_MODES: [(&str, KeyBehaviorMode); 7] = [
     ("Click", KeyBehaviorMode::Click),
     ("Hold", KeyBehaviorMode::Hold),
+    ("Random", KeyBehaviorMode::Random),
+    ("Per Key", KeyBehaviorMode::PerKey),
+    ("Sequence", KeyBehaviorMode::Sequence),
+    ("Macro", KeyBehaviorMode::Macro),
+    ("Script", KeyBehaviorMode::Script),
 ];
 
 #[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
 pub enum KeyBehaviorMode {
     Hold,
     Click,
+    /// Click one weighted random key from `selected_keys` per interval.
+    Random,
+    /// Run every key concurrently with its own `KeyOverride`.
+    PerKey,
+    /// Click `selected_keys` in captured order, duplicates included, once per interval.
+    Sequence,
+    /// Play `AppData::active_macro` once per interval.
+    Macro,
+    /// Run the Rhai script in `AppData::script_file` once per interval.
+    Script,
 }
 
 #[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
@@ -75,6 +94,8 @@ impl Default for KeyBehaviorMode {
 pub enum ModifierBehaviorMode {
     Hold,
     Click,
+    /// Press modifiers once at start and keep them down while other keys are clicked.
+    Latch,
 }
 
 impl std::fmt::Display for ModifierBehaviorMode {
@@ -82,6 +103,7 @@ impl std::fmt::Display for ModifierBehaviorMode {
         match self {
             ModifierBehaviorMode::Hold => write!(f, "Hold"),
             ModifierBehaviorMode::Click => write!(f, "Click"),
+            ModifierBehaviorMode::Latch => write!(f, "Latch"),
         }
     }
 }
@@ -93,6 +115,7 @@ impl FromStr for ModifierBehaviorMode {
         match input {
             "Hold" => Ok(ModifierBehaviorMode::Hold),
             "Click" => Ok(ModifierBehaviorMode::Click),
+            "Latch" => Ok(ModifierBehaviorMode::Latch),
             _ => Err(()),
         }
     }
@@ -105,19 +128,214 @@ impl Default for ModifierBehaviorMode {
 }
 
 
-#[derive(Debug, Clone, Copy, Serialize, Deserialize, Default)]
+/// Per-key settings used by `KeyBehaviorMode::PerKey`.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+pub struct KeyOverride {
+    /// `Hold` keeps the key down for the whole run, anything else clicks it.
+    pub mode: KeyBehaviorMode,
+    pub interval_ms: u64,
+    /// How long each click keeps the key down; 0 releases immediately.
+    #[serde(default)]
+    pub press_duration_ms: u64,
+}
+
+impl KeyOverride {
+    /// The override with its timing in the range a run allows; edits store what was typed.
+    pub fn clamped(self) -> Self {
+        use crate::constants::{MAX_INTERVAL_MS, MIN_INTERVAL_MS};
+        Self {
+            interval_ms: self.interval_ms.clamp(MIN_INTERVAL_MS, MAX_INTERVAL_MS),
+            press_duration_ms: self.press_duration_ms.min(MAX_INTERVAL_MS),
+            ..self
+        }
+    }
+}
+
+/// Which key of a modifier a hotkey wants, e.g. Right Ctrl.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+pub enum ModifierSide {
+    Either,
+    Left,
+    Right,
+}
+
+impl Default for ModifierSide {
+    fn default() -> Self {
+        ModifierSide::Either
+    }
+}
+
+impl ModifierSide {
+    /// Whether the modifier is down, given whether its left and right keys are.
+    pub fn is_held(&self, left: bool, right: bool) -> bool {
+        match self {
+            ModifierSide::Either => left || right,
+            ModifierSide::Left => left,
+            ModifierSide::Right => right,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, Default, PartialEq, Eq)]
 pub struct HotkeyModifiers {
     pub ctrl: bool,
     pub alt: bool,
     pub shift: bool,
     pub super_key: bool,
+    #[serde(default)]
+    pub ctrl_side: ModifierSide,
+    #[serde(default)]
+    pub alt_side: ModifierSide,
+    #[serde(default)]
+    pub shift_side: ModifierSide,
+    #[serde(default)]
+    pub super_side: ModifierSide,
+    /// Only these modifiers may be down, so Ctrl+Shift+F8 doesn't fire a Ctrl+F8 hotkey.
+    #[serde(default)]
+    pub exact: bool,
+}
+
+impl HotkeyModifiers {
+    pub fn is_empty(&self) -> bool {
+        !(self.ctrl || self.alt || self.shift || self.super_key)
+    }
+
+    /// Whether `key` (a normalized key name) is one of the modifiers set here.
+    pub fn includes_key(&self, key: &str) -> bool {
+        match key {
+            "Control" => self.ctrl,
+            "Alt" => self.alt,
+            "Shift" => self.shift,
+            "Super" => self.super_key,
+            _ => false,
+        }
+    }
+
+    /// Each modifier with whether it's set and its side, in Ctrl, Alt, Shift, Super order.
+    pub fn sides(&self) -> [(bool, ModifierSide); 4] {
+        [
+            (self.ctrl, self.ctrl_side),
+            (self.alt, self.alt_side),
+            (self.shift, self.shift_side),
+            (self.super_key, self.super_side),
+        ]
+    }
+
+    /// The same modifiers, matched on either side.
+    pub fn without_sides(&self) -> Self {
+        Self {
+            ctrl_side: ModifierSide::Either,
+            alt_side: ModifierSide::Either,
+            shift_side: ModifierSide::Either,
+            super_side: ModifierSide::Either,
+            ..*self
+        }
+    }
+
+    /// Whether the modifiers held match these, given whether the left and right key of
+    /// each modifier is down, in Ctrl, Alt, Shift, Super order.
+    pub fn matches(&self, held: [(bool, bool); 4]) -> bool {
+        self.sides().into_iter().zip(held).all(|((set, side), (left, right))| {
+            if !set {
+                return !self.exact || !(left || right);
+            }
+            // An exact side-specific modifier rules out its other side too
+            let other_side = match side {
+                ModifierSide::Either => false,
+                ModifierSide::Left => right,
+                ModifierSide::Right => left,
+            };
+            side.is_held(left, right) && !(self.exact && other_side)
+        })
+    }
+}
+
+const CHORD_MODIFIERS: [&str; 4] = ["Ctrl", "Alt", "Shift", "Super"];
+
+/// A `selected_keys` entry pressed as one unit, stored and displayed as e.g. "Ctrl+C".
+/// Plain entries like "C" are chords without modifiers.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KeyChord {
+    pub modifiers: HotkeyModifiers,
+    pub key: String,
+}
+
+impl std::fmt::Display for KeyChord {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let flags = [self.modifiers.ctrl, self.modifiers.alt, self.modifiers.shift, self.modifiers.super_key];
+        for (name, _) in CHORD_MODIFIERS.iter().zip(flags).filter(|(_, set)| *set) {
+            write!(f, "{}+", name)?;
+        }
+        write!(f, "{}", self.key)
+    }
+}
+
+impl FromStr for KeyChord {
+    type Err = ();
+
+    fn from_str(input: &str) -> Result<KeyChord, Self::Err> {
+        let mut modifiers = HotkeyModifiers::default();
+        let mut rest = input.trim();
+
+        // Strip modifier prefixes one by one so a trailing "+" key still parses.
+        while let Some((name, key)) = rest.split_once('+').filter(|(_, key)| !key.is_empty()) {
+            match name {
+                "Ctrl" => modifiers.ctrl = true,
+                "Alt" => modifiers.alt = true,
+                "Shift" => modifiers.shift = true,
+                "Super" => modifiers.super_key = true,
+                _ => break,
+            }
+            rest = key;
+        }
+
+        if rest.is_empty() {
+            return Err(());
+        }
+        Ok(KeyChord { modifiers, key: rest.to_string() })
+    }
+}
+
+/// What pressing and releasing the global hotkey does.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+pub enum HotkeyActivation {
+    /// Each press starts or stops the simulation.
+    Toggle,
+    /// Runs only while the hotkey is held.
+    Hold,
+    /// Starts on press and stops `release_delay_ms` after release; pressing again in time keeps it running.
+    HoldWithDelay { release_delay_ms: u64 },
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+impl Default for HotkeyActivation {
+    fn default() -> Self {
+        HotkeyActivation::Toggle
+    }
+}
+
+impl HotkeyActivation {
+    pub fn is_hold(&self) -> bool {
+        !matches!(self, HotkeyActivation::Toggle)
+    }
+}
+
+impl std::fmt::Display for HotkeyActivation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            HotkeyActivation::Toggle => write!(f, "Toggle"),
+            HotkeyActivation::Hold => write!(f, "Hold"),
+            HotkeyActivation::HoldWithDelay { .. } => write!(f, "Hold + Release Delay"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub struct GlobalHotkey {
     pub key: String,
     #[serde(flatten)]
     pub modifiers: HotkeyModifiers,
+    #[serde(default)]
+    pub activation: HotkeyActivation,
 }
 
 impl Default for GlobalHotkey {
@@ -125,14 +343,91 @@ impl Default for GlobalHotkey {
         Self {
             key: "F8".to_string(),
             modifiers: HotkeyModifiers::default(),
+            activation: HotkeyActivation::default(),
+        }
+    }
+}
+
+impl GlobalHotkey {
+    /// Whether both hotkeys are pressed the same way, whatever their activation mode.
+    pub fn same_trigger(&self, other: &GlobalHotkey) -> bool {
+        normalize_key(&self.key) == normalize_key(&other.key) && self.modifiers == other.modifiers
+    }
+}
+
+/// A hotkey that does one thing on each press, next to the global hotkey.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum HotkeyAction {
+    /// Types the clipboard text.
+    Paste,
+    /// Starts the simulation, or resumes it when paused.
+    Start,
+    Stop,
+    /// Pauses the simulation where it is, or resumes it from there.
+    PauseResume,
+    /// Runs the named profile, stopping any other run; stops it if it's the one running.
+    Profile(String),
+}
+
+pub const HOTKEY_ACTIONS: [HotkeyAction; 4] = [
+    HotkeyAction::Start,
+    HotkeyAction::Stop,
+    HotkeyAction::PauseResume,
+    HotkeyAction::Paste,
+];
+
+impl std::fmt::Display for HotkeyAction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            HotkeyAction::Paste => write!(f, "Paste"),
+            HotkeyAction::Start => write!(f, "Start"),
+            HotkeyAction::Stop => write!(f, "Stop"),
+            HotkeyAction::PauseResume => write!(f, "Pause/Resume"),
+            HotkeyAction::Profile(name) => write!(f, "Profile \"{}\"", name),
         }
     }
 }
 
+/// Which hotkey a hotkey capture sets.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum HotkeyTarget {
+    /// Starts and stops the simulation.
+    Simulation,
+    Action(HotkeyAction),
+}
+
+impl Default for HotkeyTarget {
+    fn default() -> Self {
+        HotkeyTarget::Simulation
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct TempHotkeyState {
     pub key: Option<String>,
+    /// Sides are tracked as modifiers are pressed and kept only with `distinguish_sides`.
     pub modifiers: HotkeyModifiers,
+    pub target: HotkeyTarget,
+    pub distinguish_sides: bool,
+}
+
+/// Selection and drag state of the macro timeline editor.
+#[derive(Debug, Clone, Default)]
+pub struct TimelineState {
+    pub selected: Option<usize>,
+    /// Step being dragged by its handle.
+    pub dragging: Option<usize>,
+    /// Step the pointer is over while dragging; the dragged step is dropped here.
+    pub drop_target: Option<usize>,
+    /// Step the next run starts at instead of the first one; cleared once the run starts.
+    pub play_from: Option<usize>,
+}
+
+impl TempHotkeyState {
+    /// The modifiers the captured hotkey is saved with.
+    pub fn saved_modifiers(&self) -> HotkeyModifiers {
+        if self.distinguish_sides { self.modifiers } else { self.modifiers.without_sides() }
+    }
 }
 
 impl Default for TempHotkeyState {
@@ -140,10 +435,146 @@ impl Default for TempHotkeyState {
         Self {
             key: None,
             modifiers: HotkeyModifiers::default(),
+            target: HotkeyTarget::default(),
+            distinguish_sides: false,
+        }
+    }
+}
+
+/// xkb layout used to type text, e.g. layout "de" with variant "nodeadkeys".
+/// Empty fields use the system default (`XKB_DEFAULT_LAYOUT`, usually "us").
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct KeyboardLayout {
+    #[serde(default)]
+    pub layout: String,
+    #[serde(default)]
+    pub variant: String,
+}
+
+impl std::fmt::Display for KeyboardLayout {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match (self.layout.as_str(), self.variant.as_str()) {
+            ("", _) => write!(f, "system default"),
+            (layout, "") => write!(f, "{}", layout),
+            (layout, variant) => write!(f, "{}({})", layout, variant),
+        }
+    }
+}
+
+/// How typed text enters characters the keyboard layout can't produce (emoji, CJK).
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+pub enum UnicodeFallback {
+    /// Skip such characters.
+    Skip,
+    /// GTK/IBus hex entry: Ctrl+Shift+U, the code point in hex, then Space.
+    HexEntry,
+    /// Briefly map a spare keycode to the character with xmodmap (X11 and Xwayland clients).
+    RemapKey,
+}
+
+impl Default for UnicodeFallback {
+    fn default() -> Self {
+        UnicodeFallback::Skip
+    }
+}
+
+impl std::fmt::Display for UnicodeFallback {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            UnicodeFallback::Skip => write!(f, "Skip"),
+            UnicodeFallback::HexEntry => write!(f, "Ctrl+Shift+U"),
+            UnicodeFallback::RemapKey => write!(f, "Remap Key"),
+        }
+    }
+}
+
+/// How the global hotkeys are detected.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+pub enum HotkeyBackend {
+    /// Polls the keyboard state through X11; misses keys pressed in pure Wayland windows.
+    DeviceQuery,
+    /// Reads keyboards from /dev/input, whatever window has focus; needs the input group.
+    Evdev,
+    /// Registers the hotkeys with the compositor through the XDG GlobalShortcuts portal.
+    Portal,
+}
+
+impl Default for HotkeyBackend {
+    fn default() -> Self {
+        HotkeyBackend::DeviceQuery
+    }
+}
+
+impl std::fmt::Display for HotkeyBackend {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            HotkeyBackend::DeviceQuery => write!(f, "X11"),
+            HotkeyBackend::Evdev => write!(f, "evdev"),
+            HotkeyBackend::Portal => write!(f, "Portal"),
         }
     }
 }
 
+/// Simulation settings saved under a name, so a hotkey can switch to them in one press.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct Profile {
+    pub name: String,
+    /// Stays with the profile when its name changes; names its portal shortcut. 0 until
+    /// `assign_profile_ids` gives it one.
+    #[serde(default)]
+    pub id: u32,
+    #[serde(default)]
+    pub selected_keys: Vec<String>,
+    pub interval_ms: u64,
+    pub key_behavior: KeyBehaviorMode,
+    #[serde(default)]
+    pub modifier_behavior: ModifierBehaviorMode,
+    #[serde(default)]
+    pub hold_behavior: HoldBehaviorMode,
+    #[serde(default)]
+    pub key_weights: HashMap<String, u32>,
+    #[serde(default)]
+    pub key_overrides: HashMap<String, KeyOverride>,
+    #[serde(default)]
+    pub sequence_delays: Vec<u64>,
+    #[serde(default)]
+    pub active_macro: Option<String>,
+    #[serde(default)]
+    pub script_file: String,
+}
+
+/// A hotkey that runs a profile; it toggles on press whatever its activation mode.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ProfileBinding {
+    pub hotkey: GlobalHotkey,
+    pub profile: String,
+}
+
+/// The settings the global hotkey listener follows; `AppData` changes reach it through a watch channel.
+#[derive(Debug, Clone, PartialEq)]
+pub struct HotkeySettings {
+    pub backend: HotkeyBackend,
+    pub hotkey: GlobalHotkey,
+    /// The action hotkeys that are set, in `HOTKEY_ACTIONS` order, then the profile bindings.
+    pub actions: Vec<(HotkeyAction, GlobalHotkey)>,
+    /// The ids of the bound profiles, by name.
+    pub profile_ids: HashMap<String, u32>,
+}
+
+/// Macro recording progress; the global hotkey starts and stops an armed recording.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RecordingState {
+    Off,
+    Armed,
+    Recording,
+}
+
+impl Default for RecordingState {
+    fn default() -> Self {
+        RecordingState::Off
+    }
+}
+
 #[derive(Debug, Default, Serialize, Deserialize)]
 pub struct AppData {
     #[serde(skip)]
@@ -157,10 +588,406 @@ pub struct AppData {
     pub modifier_behavior: ModifierBehaviorMode,
     #[serde(default)]
     pub hold_behavior: HoldBehaviorMode,
+    /// Per-key weights for `KeyBehaviorMode::Random`, keyed by normalized key name.
+    /// Keys without an entry use `DEFAULT_KEY_WEIGHT`.
+    #[serde(default)]
+    pub key_weights: HashMap<String, u32>,
+    #[serde(default)]
+    pub random_no_repeat: bool,
+    #[serde(default)]
+    pub random_seed: Option<u64>,
+    /// Per-key settings for `KeyBehaviorMode::PerKey`, keyed by normalized key name.
+    #[serde(default)]
+    pub key_overrides: HashMap<String, KeyOverride>,
+    /// Delay after each `selected_keys` step in `KeyBehaviorMode::Sequence`, by position.
+    #[serde(default)]
+    pub sequence_delays: Vec<u64>,
+    #[serde(default)]
+    pub macros: Vec<Macro>,
+    /// Name of the macro run by `KeyBehaviorMode::Macro`.
+    #[serde(default)]
+    pub active_macro: Option<String>,
+    #[serde(default)]
+    pub macro_playback: PlaybackOptions,
+    /// Text file the active macro is loaded from and saved to, see `macro_dsl`.
+    #[serde(default)]
+    pub macro_file: String,
+    #[serde(default)]
+    pub script_file: String,
+    #[serde(default)]
+    pub keyboard_layout: KeyboardLayout,
+    #[serde(default)]
+    pub unicode_fallback: UnicodeFallback,
+    #[serde(default)]
+    pub typing_profile: TypingProfile,
+    /// Types the clipboard text with the keyboard layout and typing profile; off when unset.
+    #[serde(default)]
+    pub paste_keybind: Option<GlobalHotkey>,
+    /// Start, stop and pause/resume the simulation; off when unset.
+    #[serde(default)]
+    pub start_keybind: Option<GlobalHotkey>,
+    #[serde(default)]
+    pub stop_keybind: Option<GlobalHotkey>,
+    #[serde(default)]
+    pub pause_keybind: Option<GlobalHotkey>,
+    #[serde(default)]
+    pub profiles: Vec<Profile>,
+    #[serde(default)]
+    pub profile_bindings: Vec<ProfileBinding>,
+    /// The profile last loaded into the settings above.
+    #[serde(default)]
+    pub active_profile: Option<String>,
+    /// How the global and paste hotkeys are detected.
+    #[serde(default)]
+    pub hotkey_backend: HotkeyBackend,
+    /// evemu recording of the events each simulation run emits; off when empty.
+    #[serde(default)]
+    pub evemu_trace_file: String,
     #[serde(default)]
     pub settings_panel_open: bool,
     #[serde(skip)]
     pub capturing_global_hotkey: bool,
     #[serde(skip)]
     pub temp_hotkey: TempHotkeyState,
-}
\ No newline at end of file
+    #[serde(skip)]
+    pub recording: RecordingState,
+    #[serde(skip)]
+    pub macro_file_error: Option<String>,
+    /// Result of the last script check, shown under the script file.
+    #[serde(skip)]
+    pub script_status: Option<String>,
+    /// Text entered for a new type-text macro.
+    #[serde(skip)]
+    pub type_text_input: String,
+    /// Name entered for a new profile.
+    #[serde(skip)]
+    pub profile_name_input: String,
+    #[serde(skip)]
+    pub timeline: TimelineState,
+}
+
+impl AppData {
+    pub fn key_weight(&self, key: &str) -> u32 {
+        self.key_weights
+            .get(key)
+            .copied()
+            .unwrap_or(crate::constants::DEFAULT_KEY_WEIGHT)
+    }
+
+    /// First of "`prefix` 1", "`prefix` 2", ... that no macro uses yet.
+    pub fn unused_macro_name(&self, prefix: &str) -> String {
+        (1..)
+            .map(|n| format!("{} {}", prefix, n))
+            .find(|name| self.macros.iter().all(|m| &m.name != name))
+            .unwrap()
+    }
+
+    /// The listener settings, with the default hotkey in place of an empty one.
+    pub fn hotkey_settings(&self) -> HotkeySettings {
+        let hotkey = if self.global_keybind.key.trim().is_empty() {
+            GlobalHotkey::default()
+        } else {
+            self.global_keybind.clone()
+        };
+        let mut actions: Vec<(HotkeyAction, GlobalHotkey)> = HOTKEY_ACTIONS
+            .iter()
+            .filter_map(|action| self.action_keybind(action).map(|hotkey| (action.clone(), hotkey.clone())))
+            .collect();
+        actions.extend(self.profile_bindings.iter().map(|binding| {
+            (HotkeyAction::Profile(binding.profile.clone()), binding.hotkey.clone())
+        }));
+        let profile_ids = self.profiles
+            .iter()
+            .filter(|profile| profile.id != 0 && self.profile_bindings.iter().any(|binding| binding.profile == profile.name))
+            .map(|profile| (profile.name.clone(), profile.id))
+            .collect();
+        HotkeySettings { backend: self.hotkey_backend, hotkey, actions, profile_ids }
+    }
+
+    /// The hotkey of `action`; the first binding of a profile bound more than once.
+    pub fn action_keybind(&self, action: &HotkeyAction) -> Option<&GlobalHotkey> {
+        match action {
+            HotkeyAction::Paste => self.paste_keybind.as_ref(),
+            HotkeyAction::Start => self.start_keybind.as_ref(),
+            HotkeyAction::Stop => self.stop_keybind.as_ref(),
+            HotkeyAction::PauseResume => self.pause_keybind.as_ref(),
+            HotkeyAction::Profile(name) => self.profile_bindings
+                .iter()
+                .find(|binding| &binding.profile == name)
+                .map(|binding| &binding.hotkey),
+        }
+    }
+
+    /// Sets or clears the hotkey of `action`; a profile's other bindings are replaced.
+    /// Returns false and changes nothing for a paste hotkey that is the global hotkey,
+    /// since the global hotkey stops a paste.
+    pub fn set_action_keybind(&mut self, action: &HotkeyAction, hotkey: Option<GlobalHotkey>) -> bool {
+        if *action == HotkeyAction::Paste && hotkey.as_ref().is_some_and(|hotkey| hotkey.same_trigger(&self.global_keybind)) {
+            return false;
+        }
+        let keybind = match action {
+            HotkeyAction::Paste => &mut self.paste_keybind,
+            HotkeyAction::Start => &mut self.start_keybind,
+            HotkeyAction::Stop => &mut self.stop_keybind,
+            HotkeyAction::PauseResume => &mut self.pause_keybind,
+            HotkeyAction::Profile(name) => {
+                self.profile_bindings.retain(|binding| &binding.profile != name);
+                if let Some(hotkey) = hotkey {
+                    self.profile_bindings.push(ProfileBinding { hotkey, profile: name.clone() });
+                }
+                return true;
+            }
+        };
+        *keybind = hotkey;
+        true
+    }
+
+    /// Saves the current simulation settings as profile `name`, replacing one with that name.
+    pub fn save_profile(&mut self, name: String) {
+        let existing = self.profiles.iter().position(|p| p.name == name);
+        let profile = Profile {
+            name: name.clone(),
+            id: existing.map_or(0, |index| self.profiles[index].id),
+            selected_keys: self.selected_keys.clone(),
+            interval_ms: self.interval_ms,
+            key_behavior: self.key_behavior,
+            modifier_behavior: self.modifier_behavior,
+            hold_behavior: self.hold_behavior,
+            key_weights: self.key_weights.clone(),
+            key_overrides: self.key_overrides.clone(),
+            sequence_delays: self.sequence_delays.clone(),
+            active_macro: self.active_macro.clone(),
+            script_file: self.script_file.clone(),
+        };
+        match existing {
+            Some(index) => self.profiles[index] = profile,
+            None => self.profiles.push(profile),
+        }
+        self.assign_profile_ids();
+        self.active_profile = Some(name);
+    }
+
+    /// Gives each profile without an id the next unused one.
+    pub fn assign_profile_ids(&mut self) {
+        let mut next = self.profiles.iter().map(|profile| profile.id).max().unwrap_or(0);
+        for profile in self.profiles.iter_mut().filter(|profile| profile.id == 0) {
+            next += 1;
+            profile.id = next;
+        }
+    }
+
+    /// Replaces the simulation settings with profile `name`'s; false if there's no such profile.
+    pub fn load_profile(&mut self, name: &str) -> bool {
+        let Some(profile) = self.profiles.iter().find(|p| p.name == name).cloned() else {
+            return false;
+        };
+        self.selected_keys = profile.selected_keys;
+        self.interval_ms = profile.interval_ms;
+        self.key_behavior = profile.key_behavior;
+        self.modifier_behavior = profile.modifier_behavior;
+        self.hold_behavior = profile.hold_behavior;
+        self.key_weights = profile.key_weights;
+        self.key_overrides = profile.key_overrides;
+        self.sequence_delays = profile.sequence_delays;
+        self.active_macro = profile.active_macro;
+        self.script_file = profile.script_file;
+        self.timeline = TimelineState::default();
+        self.active_profile = Some(profile.name);
+        true
+    }
+
+    /// Removes profile `name` and its hotkeys.
+    pub fn delete_profile(&mut self, name: &str) {
+        self.profiles.retain(|p| p.name != name);
+        self.profile_bindings.retain(|binding| binding.profile != name);
+        if self.active_profile.as_deref() == Some(name) {
+            self.active_profile = None;
+        }
+    }
+
+    pub fn active_macro(&self) -> Option<&Macro> {
+        let name = self.active_macro.as_ref()?;
+        self.macros.iter().find(|m| &m.name == name)
+    }
+
+    pub fn active_macro_mut(&mut self) -> Option<&mut Macro> {
+        let name = self.active_macro.as_ref()?;
+        self.macros.iter_mut().find(|m| &m.name == name)
+    }
+
+    pub fn step_delay(&self, index: usize) -> u64 {
+        self.sequence_delays
+            .get(index)
+            .copied()
+            .unwrap_or(crate::constants::DEFAULT_STEP_DELAY_MS)
+    }
+
+    /// The `selected_keys` entries that press the key of the global hotkey or of any action
+    /// or profile hotkey, bare or in a chord. While that hotkey's modifiers are held, such a
+    /// press completes it.
+    pub fn hotkey_overlaps(&self) -> Vec<String> {
+        let settings = self.hotkey_settings();
+        let hotkeys: Vec<String> = std::iter::once(&settings.hotkey)
+            .chain(settings.actions.iter().map(|(_, hotkey)| hotkey))
+            .map(|hotkey| normalize_key(&hotkey.key))
+            .collect();
+        self.selected_keys
+            .iter()
+            .filter(|entry| {
+                let key = entry.parse::<KeyChord>().map_or_else(|_| entry.to_string(), |chord| chord.key);
+                hotkeys.contains(&normalize_key(&key))
+            })
+            .cloned()
+            .collect()
+    }
+
+    /// Returns the override for `key`, falling back to a click at the global interval.
+    pub fn key_override(&self, key: &str) -> KeyOverride {
+        self.key_overrides
+            .get(key)
+            .copied()
+            .unwrap_or(KeyOverride {
+                mode: KeyBehaviorMode::Click,
+                interval_ms: self.interval_ms,
+                press_duration_ms: 0,
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_key_chord_round_trip() {
+        let chord: KeyChord = "Ctrl+Shift+C".parse().unwrap();
+        assert!(chord.modifiers.ctrl && chord.modifiers.shift);
+        assert!(!chord.modifiers.alt && !chord.modifiers.super_key);
+        assert_eq!(chord.key, "C");
+        assert_eq!(chord.to_string(), "Ctrl+Shift+C");
+
+        // Modifiers are always displayed in Ctrl, Alt, Shift, Super order
+        assert_eq!("Super+Alt+V".parse::<KeyChord>().unwrap().to_string(), "Alt+Super+V");
+    }
+
+    #[test]
+    fn test_key_chord_plain_keys() {
+        let chord: KeyChord = "BTN_LEFT".parse().unwrap();
+        assert!(chord.modifiers.is_empty());
+        assert_eq!(chord.key, "BTN_LEFT");
+
+        // Bare modifiers and a "+" key are keys, not chord separators
+        assert_eq!("Control".parse::<KeyChord>().unwrap().key, "Control");
+        assert_eq!("Ctrl++".parse::<KeyChord>().unwrap().key, "+");
+        assert!("".parse::<KeyChord>().is_err());
+    }
+
+    #[test]
+    fn test_modifier_matching() {
+        const NONE: (bool, bool) = (false, false);
+        const LEFT: (bool, bool) = (true, false);
+        const RIGHT: (bool, bool) = (false, true);
+        let ctrl = HotkeyModifiers { ctrl: true, ..HotkeyModifiers::default() };
+
+        // Extra modifiers only keep an exact hotkey from firing
+        assert!(ctrl.matches([LEFT, NONE, NONE, NONE]));
+        assert!(ctrl.matches([RIGHT, NONE, LEFT, NONE]));
+        assert!(!ctrl.matches([NONE, NONE, LEFT, NONE]));
+        let exact = HotkeyModifiers { exact: true, ..ctrl };
+        assert!(exact.matches([RIGHT, NONE, NONE, NONE]));
+        assert!(!exact.matches([LEFT, NONE, LEFT, NONE]));
+        assert!(!HotkeyModifiers { exact: true, ..HotkeyModifiers::default() }.matches([NONE, NONE, NONE, RIGHT]));
+
+        let right_ctrl = HotkeyModifiers { ctrl_side: ModifierSide::Right, ..ctrl };
+        assert!(right_ctrl.matches([RIGHT, NONE, NONE, NONE]));
+        assert!(!right_ctrl.matches([LEFT, NONE, NONE, NONE]));
+        assert!(right_ctrl.matches([(true, true), NONE, NONE, NONE]));
+        assert!(!HotkeyModifiers { exact: true, ..right_ctrl }.matches([(true, true), NONE, NONE, NONE]));
+        assert_eq!(right_ctrl.without_sides(), ctrl);
+    }
+
+    #[test]
+    fn test_hotkey_settings_actions() {
+        let mut app_data = AppData::default();
+        assert!(app_data.hotkey_settings().actions.is_empty());
+
+        let hotkey = |key: &str| GlobalHotkey { key: key.to_string(), ..GlobalHotkey::default() };
+        app_data.paste_keybind = Some(hotkey("V"));
+        app_data.set_action_keybind(&HotkeyAction::PauseResume, Some(hotkey("F10")));
+        app_data.profile_bindings.push(ProfileBinding { hotkey: hotkey("F9"), profile: "hold W".to_string() });
+        assert_eq!(app_data.hotkey_settings().actions, vec![
+            (HotkeyAction::PauseResume, hotkey("F10")),
+            (HotkeyAction::Paste, hotkey("V")),
+            (HotkeyAction::Profile("hold W".to_string()), hotkey("F9")),
+        ]);
+
+        // The global hotkey can't also paste
+        assert!(!app_data.set_action_keybind(&HotkeyAction::Paste, Some(hotkey("F8"))));
+        assert_eq!(app_data.paste_keybind, Some(hotkey("V")));
+    }
+
+    #[test]
+    fn test_profiles() {
+        let mut app_data = AppData::default();
+        app_data.selected_keys = vec!["BTN_LEFT".to_string()];
+        app_data.interval_ms = 50;
+        app_data.save_profile("farm clicks".to_string());
+
+        app_data.selected_keys = vec!["W".to_string()];
+        app_data.key_behavior = KeyBehaviorMode::Hold;
+        app_data.save_profile("hold W".to_string());
+        let f9 = GlobalHotkey { key: "F9".to_string(), ..GlobalHotkey::default() };
+        app_data.set_action_keybind(&HotkeyAction::Profile("hold W".to_string()), Some(f9.clone()));
+
+        assert!(app_data.load_profile("farm clicks"));
+        assert_eq!(app_data.selected_keys, vec!["BTN_LEFT".to_string()]);
+        assert_eq!(app_data.key_behavior, KeyBehaviorMode::Click);
+        assert_eq!(app_data.interval_ms, 50);
+        assert_eq!(app_data.active_profile.as_deref(), Some("farm clicks"));
+        assert!(!app_data.load_profile("missing"));
+
+        // Saving under an existing name replaces the profile
+        app_data.save_profile("hold W".to_string());
+        assert_eq!(app_data.profiles.len(), 2);
+        assert_eq!(app_data.action_keybind(&HotkeyAction::Profile("hold W".to_string())), Some(&f9));
+        // Ids stay put, and only bound profiles reach the listener
+        assert_eq!(app_data.profiles.iter().map(|p| p.id).collect::<Vec<_>>(), vec![1, 2]);
+        assert_eq!(app_data.hotkey_settings().profile_ids, HashMap::from([("hold W".to_string(), 2)]));
+
+        app_data.delete_profile("hold W");
+        assert_eq!(app_data.profiles.len(), 1);
+        assert!(app_data.profile_bindings.is_empty());
+        assert_eq!(app_data.active_profile, None);
+    }
+
+    #[test]
+    fn test_key_override_clamped() {
+        let typed = KeyOverride { mode: KeyBehaviorMode::Click, interval_ms: 2, press_duration_ms: 99_999 };
+        assert_eq!(typed.clamped(), KeyOverride {
+            mode: KeyBehaviorMode::Click,
+            interval_ms: crate::constants::MIN_INTERVAL_MS,
+            press_duration_ms: crate::constants::MAX_INTERVAL_MS,
+        });
+    }
+
+    #[test]
+    fn test_hotkey_overlaps() {
+        let mut app_data = AppData::default();
+        app_data.global_keybind = GlobalHotkey {
+            modifiers: HotkeyModifiers { ctrl: true, ..HotkeyModifiers::default() },
+            ..GlobalHotkey::default()
+        };
+        app_data.selected_keys = vec!["A".to_string(), "F8".to_string(), "Shift+F8".to_string(), "Control".to_string()];
+        assert_eq!(app_data.hotkey_overlaps(), vec!["F8".to_string(), "Shift+F8".to_string()]);
+
+        app_data.selected_keys = vec!["a".to_string()];
+        app_data.global_keybind.key = "A".to_string();
+        assert_eq!(app_data.hotkey_overlaps(), vec!["a".to_string()]);
+
+        app_data.selected_keys = vec!["F9".to_string(), "Control+F10".to_string(), "F11".to_string()];
+        let hotkey = |key: &str| Some(GlobalHotkey { key: key.to_string(), ..GlobalHotkey::default() });
+        assert!(app_data.set_action_keybind(&HotkeyAction::Paste, hotkey("F9")));
+        assert!(app_data.set_action_keybind(&HotkeyAction::Profile("typing".to_string()), hotkey("F10")));
+        assert_eq!(app_data.hotkey_overlaps(), vec!["F9".to_string(), "Control+F10".to_string()]);
+    }
+}
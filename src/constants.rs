@@ -3,9 +3,34 @@ pub const MAX_INTERVAL_MS: u64 = 5000;
 pub const DEFAULT_INTERVAL_MS: u64 = 100;
 pub const SIMULATION_HOLD_DELAY_MS: u64 = 50;
 
+pub const DEFAULT_KEY_WEIGHT: u32 = 1;
+pub const MAX_KEY_WEIGHT: u32 = 1000;
+pub const SCHEDULE_POLL_MS: u64 = 10;
+pub const DEFAULT_STEP_DELAY_MS: u64 = 50;
+pub const TYPE_KEY_DELAY_MS: u64 = 10;
+pub const REMAP_SETTLE_MS: u64 = 30;
+pub const PASTE_START_DELAY_MS: u64 = 300;
+pub const PASTE_RELEASE_TIMEOUT_MS: u64 = 2000;
+/// Release delay a hold-with-delay hotkey starts with.
+pub const DEFAULT_RELEASE_DELAY_MS: u64 = 300;
+pub const MIN_PLAYBACK_SPEED: f64 = 0.25;
+pub const MAX_PLAYBACK_SPEED: f64 = 10.0;
+
 pub const MAX_RETRIES: u32 = 3;
 pub const RETRY_DELAY_MS: u64 = 5;
 pub const MAX_DEVICE_INIT_RETRIES: u32 = 3;
 pub const DEVICE_INIT_RETRY_DELAY_MS: u64 = 100;
 
-pub const LISTENER_SLEEP_MS: u64 = 10;
\ No newline at end of file
+pub const LISTENER_SLEEP_MS: u64 = 10;
+pub const RECORDER_POLL_MS: u64 = 5;
+/// How long after the virtual device lets go of a key a press of it still counts as our own
+/// in the X11 and portal hotkey listeners, which can't see the source device and may report
+/// the press late. A few X11 polls; a tap they missed must not swallow a real press after.
+pub const SYNTHETIC_ECHO_MS: u64 = 50;
+/// How long the timeline's step fields stay unedited before the change is saved.
+pub const STEP_EDIT_SAVE_MS: u64 = 1000;
+/// How often the evdev hotkey listener looks for newly plugged keyboards.
+pub const EVDEV_RESCAN_MS: u64 = 2000;
+
+/// Name of the uinput device the simulator creates; the recorder ignores it.
+pub const VIRTUAL_DEVICE_NAME: &str = "input_simulator";
\ No newline at end of file
@@ -19,6 +19,31 @@ pub enum AppError {
 
     #[error("Logger initialization error")]
     Logger,
+
+    #[error("D-Bus error: {0}")]
+    DBus(#[from] zbus::Error),
+
+    #[error("Portal error: {0}")]
+    Portal(String),
+
+    #[error("Clipboard error: {0}")]
+    Clipboard(String),
+
+    #[error("Script error: {0}")]
+    Script(String),
+
+    #[error("Macro syntax error at line {line}, column {column}: {message}")]
+    MacroSyntax {
+        line: usize,
+        column: usize,
+        message: String,
+    },
+
+    #[error("evemu recording error at line {line}: {message}")]
+    Evemu {
+        line: usize,
+        message: String,
+    },
 }
 
 pub type Result<T> = std::result::Result<T, AppError>;
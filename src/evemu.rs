@@ -0,0 +1,434 @@
+//! The evemu-record text format, for exchanging input traces with evemu-record,
+//! evemu-play and other Linux input debugging tools. Each event is one line:
+//!
+//! ```text
+//! E: 0.016042 0001 001e 0001	# EV_KEY / KEY_A 1
+//! ```
+//!
+//! with the time in seconds, the type and code in hex and the value in decimal. Device
+//! description lines are written for reference but ignored on import.
+//!
+//! Macros export to evemu events and evemu recordings import as macros, so a recording
+//! replays through the virtual device like any other macro. A simulation run with a trace
+//! file set also writes every event it emits as an evemu recording.
+
+use std::{
+    cell::RefCell,
+    fs::File,
+    io::{self, BufWriter, Write},
+    time::Instant,
+};
+
+use evdev_rs::{
+    enums::{
+        int_to_ev_abs, int_to_ev_key, int_to_ev_led, int_to_ev_msc, int_to_ev_rel, int_to_ev_rep, int_to_ev_snd,
+        int_to_ev_sw, int_to_ev_syn, int_to_event_type, EventCode, EventType, EV_REL, EV_SYN,
+    },
+    util::event_code_to_int,
+    InputEvent, TimeVal,
+};
+
+use crate::{
+    config::UnicodeFallback,
+    constants::{TYPE_KEY_DELAY_MS, VIRTUAL_DEVICE_NAME},
+    error::{AppError, Result},
+    macros::{Macro, MacroStep},
+    recorder::{events_to_macro, recorded_input, RecordedEvent},
+    simulator::{map_simulation_key, SimulationKey},
+    typing::LayoutKeymap,
+};
+
+const BUS_VIRTUAL: u16 = 0x06;
+const EV_SYN_TYPE: u16 = 0;
+
+/// One `E:` line of an evemu recording.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EvemuEvent {
+    pub time_us: i64,
+    pub event_type: u16,
+    pub code: u16,
+    pub value: i32,
+}
+
+impl EvemuEvent {
+    fn new(time_us: i64, code: &EventCode, value: i32) -> Self {
+        let (event_type, code) = event_code_to_int(code);
+        Self { time_us, event_type: event_type as u16, code: code as u16, value }
+    }
+
+    fn is_syn_report(&self) -> bool {
+        self.event_type == EV_SYN_TYPE && self.code == EV_SYN::SYN_REPORT as u16
+    }
+
+    fn to_input_event(self) -> Option<InputEvent> {
+        let time = TimeVal::new(self.time_us.div_euclid(1_000_000), self.time_us.rem_euclid(1_000_000));
+        Some(InputEvent::new(&time, &event_code(self.event_type, self.code)?, self.value))
+    }
+}
+
+// The event code for a type and code pair, or None when evdev doesn't know it. Unlike
+// int_to_event_code this never panics on codes a recording made up.
+fn event_code(event_type: u16, code: u16) -> Option<EventCode> {
+    let code = code as u32;
+    match int_to_event_type(event_type as u32)? {
+        EventType::EV_SYN => int_to_ev_syn(code).map(EventCode::EV_SYN),
+        EventType::EV_KEY => int_to_ev_key(code).map(EventCode::EV_KEY),
+        EventType::EV_REL => int_to_ev_rel(code).map(EventCode::EV_REL),
+        EventType::EV_ABS => int_to_ev_abs(code).map(EventCode::EV_ABS),
+        EventType::EV_MSC => int_to_ev_msc(code).map(EventCode::EV_MSC),
+        EventType::EV_SW => int_to_ev_sw(code).map(EventCode::EV_SW),
+        EventType::EV_LED => int_to_ev_led(code).map(EventCode::EV_LED),
+        EventType::EV_SND => int_to_ev_snd(code).map(EventCode::EV_SND),
+        EventType::EV_REP => int_to_ev_rep(code).map(EventCode::EV_REP),
+        _ => None,
+    }
+}
+
+// Microseconds from the fraction of a timestamp, so "0.1" is 100000 like evemu-play reads it
+fn parse_fraction_us(fraction: &str) -> Option<i64> {
+    if fraction.is_empty() || fraction.len() > 6 || !fraction.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    format!("{:0<6}", fraction).parse().ok()
+}
+
+/// Reads the events of an evemu recording.
+pub fn parse_evemu(source: &str) -> Result<Vec<EvemuEvent>> {
+    let mut events = Vec::new();
+
+    for (index, line) in source.lines().enumerate() {
+        let Some(rest) = line.trim_start().strip_prefix("E:") else {
+            continue;
+        };
+        let error = |message: &str| AppError::Evemu { line: index + 1, message: message.to_string() };
+
+        let fields: Vec<&str> = rest.split('#').next().unwrap_or_default().split_whitespace().collect();
+        let [time, event_type, code, value] = fields[..] else {
+            return Err(error("expected a time, type, code and value"));
+        };
+        let (secs, usecs) = time.split_once('.').ok_or_else(|| error("invalid timestamp"))?;
+        let secs = secs.parse::<i64>().map_err(|_| error("invalid timestamp"))?;
+        let usecs = parse_fraction_us(usecs).ok_or_else(|| error("invalid timestamp"))?;
+        let event_type = u16::from_str_radix(event_type, 16).map_err(|_| error("invalid event type"))?;
+        let code = u16::from_str_radix(code, 16).map_err(|_| error("invalid event code"))?;
+        let value = value.parse::<i32>().map_err(|_| error("invalid event value"))?;
+        if event_code(event_type, code).is_none() {
+            return Err(error("unknown event type and code"));
+        }
+
+        events.push(EvemuEvent { time_us: secs * 1_000_000 + usecs, event_type, code, value });
+    }
+
+    if events.is_empty() {
+        return Err(AppError::Evemu { line: 1, message: "no events in evemu recording".to_string() });
+    }
+    Ok(events)
+}
+
+// "EV_KEY(KEY_A)" becomes "EV_KEY / KEY_A", like evemu-record's comments
+fn event_name(event: &EvemuEvent) -> String {
+    match event_code(event.event_type, event.code) {
+        Some(code) => format!("{:?}", code).replacen('(', " / ", 1).trim_end_matches(')').to_string(),
+        None => format!("{:04x} / {:04x}", event.event_type, event.code),
+    }
+}
+
+// Writes an evemu recording one event at a time, with times relative to `start_us`
+struct EvemuWriter<W: Write> {
+    out: W,
+    start_us: i64,
+    last_report_us: i64,
+}
+
+impl<W: Write> EvemuWriter<W> {
+    fn new(mut out: W, name: &str, start_us: i64) -> io::Result<Self> {
+        writeln!(out, "# EVEMU 1.3")?;
+        writeln!(out, "N: {}", name)?;
+        writeln!(out, "I: {:04x} 0000 0000 0000", BUS_VIRTUAL)?;
+        writeln!(out, "################################")?;
+        writeln!(out, "#      Waiting for events      #")?;
+        writeln!(out, "################################")?;
+        Ok(Self { out, start_us, last_report_us: start_us })
+    }
+
+    fn event(&mut self, event: &EvemuEvent) -> io::Result<()> {
+        let time_us = (event.time_us - self.start_us).max(0);
+        write!(
+            self.out,
+            "E: {}.{:06} {:04x} {:04x} {:04}\t",
+            time_us / 1_000_000,
+            time_us % 1_000_000,
+            event.event_type,
+            event.code,
+            event.value
+        )?;
+        if event.is_syn_report() {
+            writeln!(self.out, "# ------------ SYN_REPORT (0) ---------- +{}ms", (event.time_us - self.last_report_us) / 1000)?;
+            self.last_report_us = event.time_us;
+        } else {
+            writeln!(self.out, "# {} {}", event_name(event), event.value)?;
+        }
+        Ok(())
+    }
+}
+
+/// Writes `events` as an evemu recording of a device called `name`, with times relative
+/// to the first event.
+pub fn format_evemu(name: &str, events: &[EvemuEvent]) -> String {
+    let start_us = events.first().map_or(0, |event| event.time_us);
+    // Writing to a Vec can't fail
+    let mut writer = EvemuWriter::new(Vec::new(), name, start_us).expect("writing to memory");
+    for event in events {
+        writer.event(event).expect("writing to memory");
+    }
+    String::from_utf8(writer.out).expect("evemu recordings are ASCII")
+}
+
+/// Imports an evemu recording as a macro with the recorded timing.
+pub fn evemu_to_macro(name: String, events: &[EvemuEvent]) -> Macro {
+    let recorded: Vec<RecordedEvent> = events
+        .iter()
+        .filter_map(|event| {
+            recorded_input(&event.to_input_event()?).map(|input| RecordedEvent { time_us: event.time_us, input })
+        })
+        .collect();
+    events_to_macro(name, &recorded, &[])
+}
+
+// Lays out macro steps as timed events, the way the simulator plays them
+struct EventWriter<'a> {
+    keymap: &'a LayoutKeymap,
+    time_us: i64,
+    events: Vec<EvemuEvent>,
+}
+
+impl EventWriter<'_> {
+    fn push(&mut self, code: EventCode, value: i32) {
+        self.events.push(EvemuEvent::new(self.time_us, &code, value));
+    }
+
+    fn report(&mut self) {
+        self.push(EventCode::EV_SYN(EV_SYN::SYN_REPORT), 0);
+    }
+
+    fn key(&mut self, key: &SimulationKey, value: i32) {
+        if value == 0 {
+            key.iter().rev().for_each(|&code| self.push(code, 0));
+        } else {
+            key.iter().for_each(|&code| self.push(code, value));
+        }
+        self.report();
+    }
+
+    fn tap(&mut self, key: &SimulationKey) {
+        self.key(key, 1);
+        self.key(key, 0);
+    }
+
+    fn rel(&mut self, axes: [(EV_REL, i32); 2]) {
+        for (axis, value) in axes.into_iter().filter(|(_, value)| *value != 0) {
+            self.push(EventCode::EV_REL(axis), value);
+        }
+        self.report();
+    }
+
+    fn steps(&mut self, steps: &[MacroStep]) {
+        for step in steps {
+            match step {
+                MacroStep::KeyDown { key } => {
+                    if let Some(key) = lookup_key(key) {
+                        self.key(&key, 1);
+                    }
+                }
+                MacroStep::KeyUp { key } => {
+                    if let Some(key) = lookup_key(key) {
+                        self.key(&key, 0);
+                    }
+                }
+                MacroStep::Tap { key } => {
+                    if let Some(key) = lookup_key(key) {
+                        self.tap(&key);
+                    }
+                }
+                MacroStep::MouseClick { button } => {
+                    if let Some(key) = lookup_key(button.key_name()) {
+                        self.tap(&key);
+                    }
+                }
+                MacroStep::Chord { keys } => {
+                    let keys: Vec<SimulationKey> = keys.iter().filter_map(map_simulation_key).collect();
+                    let codes: SimulationKey = keys.into_iter().flatten().collect();
+                    self.tap(&codes);
+                }
+                MacroStep::Type { text, .. } => {
+                    for action in self.keymap.plan_text(text, UnicodeFallback::Skip) {
+                        self.tap(&action.key());
+                        self.time_us += TYPE_KEY_DELAY_MS as i64 * 1000;
+                    }
+                }
+                MacroStep::MouseMove { dx, dy } => self.rel([(EV_REL::REL_X, *dx), (EV_REL::REL_Y, *dy)]),
+                MacroStep::Scroll { dx, dy } => self.rel([(EV_REL::REL_HWHEEL, *dx), (EV_REL::REL_WHEEL, *dy)]),
+                MacroStep::Wait { ms } => self.time_us += *ms as i64 * 1000,
+                MacroStep::Repeat { count, steps } => {
+                    for _ in 0..*count {
+                        self.steps(steps);
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn lookup_key(name: &str) -> Option<SimulationKey> {
+    let key = map_simulation_key(&name.to_string());
+    if key.is_none() {
+        log::warn!("Skipping unmapped macro key in evemu export: {}", name);
+    }
+    key
+}
+
+/// Exports macro steps as the events the simulator emits for one playback at 1x speed.
+pub fn macro_to_evemu(steps: &[MacroStep], keymap: &LayoutKeymap) -> Vec<EvemuEvent> {
+    let mut writer = EventWriter { keymap, time_us: 0, events: Vec::new() };
+    writer.steps(steps);
+    writer.events
+}
+
+// A trace file being written as the events go out, so long runs don't pile up in memory
+struct Trace {
+    start: Instant,
+    writer: EvemuWriter<BufWriter<File>>,
+    count: usize,
+    error: Option<io::Error>,
+}
+
+thread_local! {
+    // The trace of the events written by this simulation thread
+    static TRACE: RefCell<Option<Trace>> = const { RefCell::new(None) };
+}
+
+/// Starts writing the events this thread writes to the virtual device to `path`.
+pub fn start_trace(path: &str) -> Result<()> {
+    let writer = EvemuWriter::new(BufWriter::new(File::create(path)?), VIRTUAL_DEVICE_NAME, 0)?;
+    let trace = Trace { start: Instant::now(), writer, count: 0, error: None };
+    TRACE.with(|cell| *cell.borrow_mut() = Some(trace));
+    Ok(())
+}
+
+/// Adds a written event to the trace, if one was started on this thread.
+pub(crate) fn trace_event(event: &InputEvent) {
+    TRACE.with(|cell| {
+        if let Some(trace) = cell.borrow_mut().as_mut().filter(|trace| trace.error.is_none()) {
+            let time_us = trace.start.elapsed().as_micros() as i64;
+            match trace.writer.event(&EvemuEvent::new(time_us, &event.event_code, event.value)) {
+                Ok(()) => trace.count += 1,
+                // Kept for finish_trace, the run goes on without the trace
+                Err(e) => trace.error = Some(e),
+            }
+        }
+    });
+}
+
+/// Ends the trace on this thread and flushes it to its file.
+pub fn finish_trace() -> Result<()> {
+    let Some(mut trace) = TRACE.with(|cell| cell.borrow_mut().take()) else {
+        return Ok(());
+    };
+    if let Some(e) = trace.error {
+        return Err(e.into());
+    }
+    trace.writer.out.flush()?;
+    log::info!("Wrote {} traced events", trace.count);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use evdev_rs::enums::EV_KEY;
+
+    const RECORDING: &str = "\
+# EVEMU 1.3
+N: Example Keyboard
+I: 0003 046d c31c 0110
+################################
+#      Waiting for events      #
+################################
+E: 0.000001 0004 0004 458756\t# EV_MSC / MSC_SCAN             458756
+E: 0.000001 0001 001e 0001\t# EV_KEY / KEY_A                1
+E: 0.000001 0000 0000 0000\t# ------------ SYN_REPORT (0) ---------- +0ms
+E: 0.080001 0001 001e 0000\t# EV_KEY / KEY_A                0
+E: 0.080001 0000 0000 0000\t# ------------ SYN_REPORT (0) ---------- +80ms
+E: 0.100001 0002 0000 -003\t# EV_REL / REL_X                -3
+E: 0.100001 0000 0000 0000\t# ------------ SYN_REPORT (0) ---------- +20ms
+";
+
+    #[test]
+    fn test_parse_evemu() {
+        let events = parse_evemu(RECORDING).unwrap();
+        assert_eq!(events.len(), 7);
+        assert_eq!(events[1], EvemuEvent { time_us: 1, event_type: 1, code: 0x1e, value: 1 });
+        assert_eq!(events[5], EvemuEvent { time_us: 100_001, event_type: 2, code: 0, value: -3 });
+
+        assert!(matches!(parse_evemu("E: 0.1 0001\n"), Err(AppError::Evemu { line: 1, .. })));
+        assert!(parse_evemu("# EVEMU 1.3\n").is_err());
+    }
+
+    #[test]
+    fn test_parse_evemu_fraction() {
+        let events = parse_evemu("E: 0.1 0001 001e 0001\nE: 2.05 0001 001e 0000\n").unwrap();
+        assert_eq!(events[0].time_us, 100_000);
+        assert_eq!(events[1].time_us, 2_050_000);
+        assert!(matches!(parse_evemu("E: 0.1234567 0001 001e 0001\n"), Err(AppError::Evemu { line: 1, .. })));
+        assert!(matches!(parse_evemu("E: 0.-1 0001 001e 0001\n"), Err(AppError::Evemu { line: 1, .. })));
+    }
+
+    #[test]
+    fn test_parse_evemu_unknown_code() {
+        let source = "E: 0.000000 0001 001e 0001\nE: 0.000000 0000 00ff 0000\n";
+        assert!(matches!(parse_evemu(source), Err(AppError::Evemu { line: 2, .. })));
+        assert!(matches!(parse_evemu("E: 0.000000 001f 0000 0000\n"), Err(AppError::Evemu { line: 1, .. })));
+    }
+
+    #[test]
+    fn test_format_evemu_round_trip() {
+        let events = parse_evemu(RECORDING).unwrap();
+        let formatted = format_evemu("input_simulator", &events);
+        assert!(formatted.contains("E: 0.000000 0001 001e 0001\t# EV_KEY / KEY_A 1\n"));
+        assert!(formatted.contains("E: 0.100000 0002 0000 -003\t"));
+
+        let shifted: Vec<EvemuEvent> = events.iter().map(|event| EvemuEvent { time_us: event.time_us - 1, ..*event }).collect();
+        assert_eq!(parse_evemu(&formatted).unwrap(), shifted);
+    }
+
+    #[test]
+    fn test_evemu_to_macro() {
+        let imported = evemu_to_macro("Imported".to_string(), &parse_evemu(RECORDING).unwrap());
+        assert_eq!(imported.steps, vec![
+            MacroStep::KeyDown { key: "A".to_string() },
+            MacroStep::Wait { ms: 80 },
+            MacroStep::KeyUp { key: "A".to_string() },
+            MacroStep::Wait { ms: 20 },
+            MacroStep::MouseMove { dx: -3, dy: 0 },
+        ]);
+    }
+
+    #[test]
+    fn test_macro_to_evemu() {
+        let steps = vec![
+            MacroStep::Tap { key: "A".to_string() },
+            MacroStep::Wait { ms: 50 },
+            MacroStep::MouseMove { dx: 4, dy: 0 },
+        ];
+        let key_a = EventCode::EV_KEY(EV_KEY::KEY_A);
+        let report = EventCode::EV_SYN(EV_SYN::SYN_REPORT);
+        assert_eq!(macro_to_evemu(&steps, &LayoutKeymap::default()), vec![
+            EvemuEvent::new(0, &key_a, 1),
+            EvemuEvent::new(0, &report, 0),
+            EvemuEvent::new(0, &key_a, 0),
+            EvemuEvent::new(0, &report, 0),
+            EvemuEvent::new(50_000, &EventCode::EV_REL(EV_REL::REL_X), 4),
+            EvemuEvent::new(50_000, &report, 0),
+        ]);
+    }
+}
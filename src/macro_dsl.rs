@@ -0,0 +1,507 @@
+//! Plain-text macro format, one command per line (or separated by `;`):
+//!
+//! ```text
+//! # Save, then farm
+//! tap Ctrl+S
+//! wait 200ms
+//! repeat 5 { click left; move 10 0 }
+//! type "hello"
+//! ```
+//!
+//! Commands: `tap KEY`, `down KEY`, `up KEY`, `chord KEY KEY...`, `wait 200ms|1.5s|200`,
+//! `move DX DY`, `click left|middle|right`, `scroll DY` or `scroll DX DY`, `type "TEXT"`
+//! and `repeat N { ... }`. Keys use the `selected_keys` names; quote keys such as `";"`.
+//!
+//! `type` takes its own typing profile as options after the text, e.g.
+//! `type "hello" delay=80ms jitter=0.2`. Options start from the default profile with
+//! humanizing on; the others are `humanize=on|off`, `punctuation=MS`, `space=MS`,
+//! `bigram=F`, `burst=N` and `seed=N`. Without options the global profile is used.
+
+use std::fmt::Write;
+
+use crate::{
+    cadence::TypingProfile,
+    error::{AppError, Result},
+    macros::{Macro, MacroStep, MouseButton},
+};
+
+#[derive(Debug, Clone, PartialEq)]
+enum TokenKind {
+    Word(String),
+    Str(String),
+    OpenBrace,
+    CloseBrace,
+    Separator,
+}
+
+#[derive(Debug, Clone)]
+struct Token {
+    kind: TokenKind,
+    line: usize,
+    column: usize,
+}
+
+fn syntax_error(line: usize, column: usize, message: impl Into<String>) -> AppError {
+    AppError::MacroSyntax { line, column, message: message.into() }
+}
+
+fn is_word_char(c: char) -> bool {
+    !c.is_whitespace() && !matches!(c, ';' | '{' | '}' | '"' | '#')
+}
+
+fn tokenize(source: &str) -> Result<Vec<Token>> {
+    let mut tokens = Vec::new();
+
+    for (line_index, line) in source.lines().enumerate() {
+        let line_number = line_index + 1;
+        let mut chars = line.char_indices().peekable();
+
+        while let Some(&(offset, c)) = chars.peek() {
+            let column = line[..offset].chars().count() + 1;
+            let token = |kind| Token { kind, line: line_number, column };
+
+            match c {
+                '#' => break,
+                c if c.is_whitespace() => {
+                    chars.next();
+                }
+                ';' | '{' | '}' => {
+                    chars.next();
+                    tokens.push(token(match c {
+                        ';' => TokenKind::Separator,
+                        '{' => TokenKind::OpenBrace,
+                        _ => TokenKind::CloseBrace,
+                    }));
+                }
+                '"' => {
+                    chars.next();
+                    let mut text = String::new();
+                    let mut closed = false;
+                    while let Some((_, c)) = chars.next() {
+                        match c {
+                            '"' => {
+                                closed = true;
+                                break;
+                            }
+                            '\\' => match chars.next() {
+                                Some((_, 'n')) => text.push('\n'),
+                                Some((_, 't')) => text.push('\t'),
+                                Some((_, c @ ('"' | '\\'))) => text.push(c),
+                                Some((escape_offset, c)) => {
+                                    return Err(syntax_error(
+                                        line_number,
+                                        line[..escape_offset].chars().count() + 1,
+                                        format!("unknown escape \\{}", c),
+                                    ));
+                                }
+                                None => break,
+                            },
+                            c => text.push(c),
+                        }
+                    }
+                    if !closed {
+                        return Err(syntax_error(line_number, column, "unterminated string"));
+                    }
+                    tokens.push(token(TokenKind::Str(text)));
+                }
+                _ => {
+                    let mut word = String::new();
+                    while let Some(&(_, c)) = chars.peek() {
+                        if !is_word_char(c) {
+                            break;
+                        }
+                        word.push(c);
+                        chars.next();
+                    }
+                    tokens.push(token(TokenKind::Word(word)));
+                }
+            }
+        }
+
+        tokens.push(Token { kind: TokenKind::Separator, line: line_number, column: line.chars().count() + 1 });
+    }
+
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    position: usize,
+    // Reported for errors at the end of the input
+    end: (usize, usize),
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.position)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.position).cloned();
+        self.position += 1;
+        token
+    }
+
+    fn error_here(&self, message: impl Into<String>) -> AppError {
+        let (line, column) = self.peek().map_or(self.end, |token| (token.line, token.column));
+        syntax_error(line, column, message)
+    }
+
+    fn skip_separators(&mut self) {
+        while self.peek().is_some_and(|token| token.kind == TokenKind::Separator) {
+            self.position += 1;
+        }
+    }
+
+    // Parses statements until end of input, or until the closing brace when `nested`
+    fn parse_block(&mut self, nested: bool) -> Result<Vec<MacroStep>> {
+        let mut steps = Vec::new();
+        loop {
+            self.skip_separators();
+            match self.peek().map(|token| &token.kind) {
+                None if nested => return Err(self.error_here("missing closing }")),
+                None => return Ok(steps),
+                Some(TokenKind::CloseBrace) if nested => {
+                    self.position += 1;
+                    return Ok(steps);
+                }
+                _ => steps.push(self.parse_statement()?),
+            }
+
+            match self.peek().map(|token| &token.kind) {
+                None | Some(TokenKind::Separator) => {}
+                Some(TokenKind::CloseBrace) if nested => {}
+                _ => return Err(self.error_here("expected end of command")),
+            }
+        }
+    }
+
+    fn parse_statement(&mut self) -> Result<MacroStep> {
+        let token = self.next().expect("parse_statement called at end of input");
+        let command = match token.kind {
+            TokenKind::Word(word) => word,
+            _ => return Err(syntax_error(token.line, token.column, "expected a command")),
+        };
+
+        match command.to_lowercase().as_str() {
+            "tap" => Ok(MacroStep::Tap { key: self.parse_key()? }),
+            "down" => Ok(MacroStep::KeyDown { key: self.parse_key()? }),
+            "up" => Ok(MacroStep::KeyUp { key: self.parse_key()? }),
+            "chord" => {
+                let mut keys = vec![self.parse_key()?];
+                while self.peek().is_some_and(|token| matches!(token.kind, TokenKind::Word(_) | TokenKind::Str(_))) {
+                    keys.push(self.parse_key()?);
+                }
+                Ok(MacroStep::Chord { keys })
+            }
+            "wait" => Ok(MacroStep::Wait { ms: self.parse_duration()? }),
+            "move" => Ok(MacroStep::MouseMove { dx: self.parse_number()?, dy: self.parse_number()? }),
+            "click" => Ok(MacroStep::MouseClick { button: self.parse_button()? }),
+            "scroll" => {
+                let first = self.parse_number()?;
+                if self.peek().is_some_and(|token| matches!(token.kind, TokenKind::Word(_))) {
+                    Ok(MacroStep::Scroll { dx: first, dy: self.parse_number()? })
+                } else {
+                    Ok(MacroStep::Scroll { dx: 0, dy: first })
+                }
+            }
+            "type" => match self.next() {
+                Some(Token { kind: TokenKind::Str(text), .. }) => Ok(MacroStep::Type { text, profile: self.parse_typing_profile()? }),
+                _ => {
+                    self.position -= 1;
+                    Err(self.error_here("expected a quoted string"))
+                }
+            },
+            "repeat" => {
+                let count = self.parse_number()?;
+                match self.next() {
+                    Some(Token { kind: TokenKind::OpenBrace, .. }) => {}
+                    _ => {
+                        self.position -= 1;
+                        return Err(self.error_here("expected {"));
+                    }
+                }
+                let steps = self.parse_block(true)?;
+                if count > 0 && steps.is_empty() {
+                    return Err(syntax_error(token.line, token.column, "repeat needs at least one step"));
+                }
+                Ok(MacroStep::Repeat { count, steps })
+            }
+            _ => Err(syntax_error(token.line, token.column, format!("unknown command `{}`", command))),
+        }
+    }
+
+    fn parse_key(&mut self) -> Result<String> {
+        match self.next() {
+            Some(Token { kind: TokenKind::Word(key) | TokenKind::Str(key), .. }) if !key.is_empty() => Ok(key),
+            _ => {
+                self.position -= 1;
+                Err(self.error_here("expected a key"))
+            }
+        }
+    }
+
+    fn parse_word(&mut self, expected: &str) -> Result<(String, usize, usize)> {
+        match self.next() {
+            Some(Token { kind: TokenKind::Word(word), line, column }) => Ok((word, line, column)),
+            _ => {
+                self.position -= 1;
+                Err(self.error_here(format!("expected {}", expected)))
+            }
+        }
+    }
+
+    fn parse_number<T: std::str::FromStr>(&mut self) -> Result<T> {
+        let (word, line, column) = self.parse_word("a number")?;
+        word.parse()
+            .map_err(|_| syntax_error(line, column, format!("invalid number `{}`", word)))
+    }
+
+    fn parse_duration(&mut self) -> Result<u64> {
+        let (word, line, column) = self.parse_word("a duration")?;
+        duration_ms(&word).ok_or_else(|| syntax_error(line, column, format!("invalid duration `{}`", word)))
+    }
+
+    // The `name=value` options after `type "TEXT"`, if any
+    fn parse_typing_profile(&mut self) -> Result<Option<TypingProfile>> {
+        let mut profile = None;
+        while let Some(Token { kind: TokenKind::Word(word), line, column }) = self.peek().cloned() {
+            self.position += 1;
+            let invalid = || syntax_error(line, column, format!("invalid typing option `{}`", word));
+            let (name, value) = word.split_once('=').ok_or_else(invalid)?;
+            let options = profile.get_or_insert(TypingProfile { humanize: true, ..TypingProfile::default() });
+            match name {
+                "humanize" => options.humanize = match value {
+                    "on" => true,
+                    "off" => false,
+                    _ => return Err(invalid()),
+                },
+                "delay" => options.delay_ms = duration_ms(value).ok_or_else(invalid)?,
+                "punctuation" => options.punctuation_pause_ms = duration_ms(value).ok_or_else(invalid)?,
+                "space" => options.space_pause_ms = duration_ms(value).ok_or_else(invalid)?,
+                "jitter" => options.jitter = factor(value).ok_or_else(invalid)?,
+                "bigram" => options.bigram_variation = factor(value).ok_or_else(invalid)?,
+                "burst" => options.burst_length = value.parse().map_err(|_| invalid())?,
+                "seed" => options.seed = Some(value.parse().map_err(|_| invalid())?),
+                _ => return Err(syntax_error(line, column, format!("unknown typing option `{}`", name))),
+            }
+        }
+        Ok(profile)
+    }
+
+    fn parse_button(&mut self) -> Result<MouseButton> {
+        let (word, line, column) = self.parse_word("a mouse button")?;
+        match word.to_lowercase().as_str() {
+            "left" => Ok(MouseButton::Left),
+            "middle" => Ok(MouseButton::Middle),
+            "right" => Ok(MouseButton::Right),
+            _ => Err(syntax_error(line, column, format!("unknown mouse button `{}`", word))),
+        }
+    }
+}
+
+// `200ms`, `1.5s` or a plain number of milliseconds
+fn duration_ms(word: &str) -> Option<u64> {
+    if let Some(ms) = word.strip_suffix("ms") {
+        ms.parse().ok()
+    } else if let Some(seconds) = word.strip_suffix('s') {
+        let seconds: f64 = seconds.parse().ok()?;
+        if !seconds.is_finite() || seconds < 0.0 {
+            return None;
+        }
+        Some((seconds * 1000.0).round() as u64)
+    } else {
+        word.parse().ok()
+    }
+}
+
+fn factor(word: &str) -> Option<f64> {
+    word.parse().ok().filter(|value: &f64| value.is_finite() && *value >= 0.0)
+}
+
+/// Parses macro source into the steps of a macro called `name`.
+pub fn parse_macro(name: String, source: &str) -> Result<Macro> {
+    let tokens = tokenize(source)?;
+    let end = tokens.last().map_or((1, 1), |token| (token.line, token.column));
+    let mut parser = Parser { tokens, position: 0, end };
+    let steps = parser.parse_block(false)?;
+    Ok(Macro { name, steps })
+}
+
+fn format_key(key: &str) -> String {
+    if !key.is_empty() && key.chars().all(is_word_char) {
+        key.to_string()
+    } else {
+        format_string(key)
+    }
+}
+
+fn format_string(text: &str) -> String {
+    let mut quoted = String::from("\"");
+    for c in text.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\t' => quoted.push_str("\\t"),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+// Always starts with `humanize`, so a profile equal to the default still reads back as one
+fn format_typing_profile(profile: &TypingProfile) -> String {
+    let default = TypingProfile::default();
+    let mut options = vec![format!("humanize={}", if profile.humanize { "on" } else { "off" })];
+    if profile.delay_ms != default.delay_ms {
+        options.push(format!("delay={}ms", profile.delay_ms));
+    }
+    if profile.jitter != default.jitter {
+        options.push(format!("jitter={}", profile.jitter));
+    }
+    if profile.punctuation_pause_ms != default.punctuation_pause_ms {
+        options.push(format!("punctuation={}ms", profile.punctuation_pause_ms));
+    }
+    if profile.space_pause_ms != default.space_pause_ms {
+        options.push(format!("space={}ms", profile.space_pause_ms));
+    }
+    if profile.bigram_variation != default.bigram_variation {
+        options.push(format!("bigram={}", profile.bigram_variation));
+    }
+    if profile.burst_length != default.burst_length {
+        options.push(format!("burst={}", profile.burst_length));
+    }
+    if let Some(seed) = profile.seed {
+        options.push(format!("seed={}", seed));
+    }
+    options.join(" ")
+}
+
+fn format_steps(out: &mut String, steps: &[MacroStep], depth: usize) {
+    for step in steps {
+        let indent = "    ".repeat(depth);
+        let _ = match step {
+            MacroStep::Tap { key } => writeln!(out, "{}tap {}", indent, format_key(key)),
+            MacroStep::KeyDown { key } => writeln!(out, "{}down {}", indent, format_key(key)),
+            MacroStep::KeyUp { key } => writeln!(out, "{}up {}", indent, format_key(key)),
+            MacroStep::Chord { keys } => {
+                let keys: Vec<String> = keys.iter().map(|key| format_key(key)).collect();
+                writeln!(out, "{}chord {}", indent, keys.join(" "))
+            }
+            MacroStep::Wait { ms } => writeln!(out, "{}wait {}ms", indent, ms),
+            MacroStep::MouseMove { dx, dy } => writeln!(out, "{}move {} {}", indent, dx, dy),
+            MacroStep::MouseClick { button } => {
+                let button = match button {
+                    MouseButton::Left => "left",
+                    MouseButton::Middle => "middle",
+                    MouseButton::Right => "right",
+                };
+                writeln!(out, "{}click {}", indent, button)
+            }
+            MacroStep::Scroll { dx: 0, dy } => writeln!(out, "{}scroll {}", indent, dy),
+            MacroStep::Scroll { dx, dy } => writeln!(out, "{}scroll {} {}", indent, dx, dy),
+            MacroStep::Type { text, profile: None } => writeln!(out, "{}type {}", indent, format_string(text)),
+            MacroStep::Type { text, profile: Some(profile) } => {
+                writeln!(out, "{}type {} {}", indent, format_string(text), format_typing_profile(profile))
+            }
+            MacroStep::Repeat { count, steps } => {
+                let _ = writeln!(out, "{}repeat {} {{", indent, count);
+                format_steps(out, steps, depth + 1);
+                writeln!(out, "{}}}", indent)
+            }
+        };
+    }
+}
+
+/// Writes a macro in the text format, so that `parse_macro` reads it back unchanged.
+pub fn format_macro(m: &Macro) -> String {
+    let mut out = String::new();
+    format_steps(&mut out, &m.steps, 0);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(source: &str) -> Vec<MacroStep> {
+        parse_macro("test".to_string(), source).unwrap().steps
+    }
+
+    fn error_position(source: &str) -> (usize, usize) {
+        match parse_macro("test".to_string(), source) {
+            Err(AppError::MacroSyntax { line, column, .. }) => (line, column),
+            other => panic!("expected a syntax error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_parse_example() {
+        let steps = parse("# save\ntap Ctrl+S\nwait 200ms\nrepeat 5 { click left; move 10 0 }\ntype \"hello\"\n");
+        assert_eq!(steps, vec![
+            MacroStep::Tap { key: "Ctrl+S".to_string() },
+            MacroStep::Wait { ms: 200 },
+            MacroStep::Repeat {
+                count: 5,
+                steps: vec![
+                    MacroStep::MouseClick { button: MouseButton::Left },
+                    MacroStep::MouseMove { dx: 10, dy: 0 },
+                ],
+            },
+            MacroStep::Type { text: "hello".to_string(), profile: None },
+        ]);
+    }
+
+    #[test]
+    fn test_parse_other_commands() {
+        let steps = parse("down Shift; up Shift\nchord Control \";\"\nwait 1.5s; wait 20\nscroll -1\nscroll 2 3");
+        assert_eq!(steps, vec![
+            MacroStep::KeyDown { key: "Shift".to_string() },
+            MacroStep::KeyUp { key: "Shift".to_string() },
+            MacroStep::Chord { keys: vec!["Control".to_string(), ";".to_string()] },
+            MacroStep::Wait { ms: 1500 },
+            MacroStep::Wait { ms: 20 },
+            MacroStep::Scroll { dx: 0, dy: -1 },
+            MacroStep::Scroll { dx: 2, dy: 3 },
+        ]);
+    }
+
+    #[test]
+    fn test_parse_typing_profile() {
+        let steps = parse("type \"hi\" delay=80ms jitter=0.1 seed=7\ntype \"yo\" humanize=off");
+        assert_eq!(steps, vec![
+            MacroStep::Type {
+                text: "hi".to_string(),
+                profile: Some(TypingProfile { humanize: true, delay_ms: 80, jitter: 0.1, seed: Some(7), ..TypingProfile::default() }),
+            },
+            MacroStep::Type {
+                text: "yo".to_string(),
+                profile: Some(TypingProfile { humanize: false, ..TypingProfile::default() }),
+            },
+        ]);
+        assert_eq!(error_position("type \"hi\" delay=soon"), (1, 11));
+        assert_eq!(error_position("type \"hi\" speed=2"), (1, 11));
+    }
+
+    #[test]
+    fn test_error_positions() {
+        assert_eq!(error_position("tap A\n  jump 3"), (2, 3));
+        assert_eq!(error_position("wait soon"), (1, 6));
+        assert_eq!(error_position("click left\nclick up"), (2, 7));
+        assert_eq!(error_position("repeat 2 {\n tap A\n"), (2, 7));
+        assert_eq!(error_position("type \"open"), (1, 6));
+        assert_eq!(error_position("move 1 2 3"), (1, 10));
+        assert_eq!(error_position("tap"), (1, 4));
+        assert_eq!(error_position("tap A\nrepeat 3 { }"), (2, 1));
+    }
+
+    #[test]
+    fn test_format_round_trip() {
+        let source = "tap Ctrl+S\nchord Shift \";\"\nwait 200ms\nrepeat 5 {\n    click right\n    scroll 1 -2\n}\ntype \"say \\\"hi\\\"\\n\"\ntype \"ok\" humanize=on delay=80ms burst=4\n";
+        let parsed = parse_macro("test".to_string(), source).unwrap();
+        assert_eq!(format_macro(&parsed), source);
+        assert_eq!(parse_macro("test".to_string(), &format_macro(&parsed)).unwrap(), parsed);
+    }
+}
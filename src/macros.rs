@@ -0,0 +1,366 @@
+use std::time::Duration;
+
+use serde::{Serialize, Deserialize};
+
+use crate::{
+    cadence::TypingProfile,
+    constants::{MIN_PLAYBACK_SPEED, MAX_PLAYBACK_SPEED},
+};
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+pub enum MouseButton {
+    Left,
+    Middle,
+    Right,
+}
+
+impl MouseButton {
+    /// The `selected_keys` name of this button, as produced by the mouse capture buttons.
+    pub fn key_name(&self) -> &'static str {
+        match self {
+            MouseButton::Left => "BTN_LEFT",
+            MouseButton::Middle => "BTN_MIDDLE",
+            MouseButton::Right => "BTN_RIGHT",
+        }
+    }
+}
+
+/// One step of a macro. Key names use the `selected_keys` format, so `Tap` also
+/// accepts chords like "Ctrl+C".
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum MacroStep {
+    KeyDown { key: String },
+    KeyUp { key: String },
+    Tap { key: String },
+    /// Press all keys together, then release them in reverse order.
+    Chord { keys: Vec<String> },
+    MouseMove { dx: i32, dy: i32 },
+    MouseClick { button: MouseButton },
+    /// Positive `dy` scrolls up, positive `dx` scrolls right.
+    Scroll {
+        #[serde(default)]
+        dx: i32,
+        dy: i32,
+    },
+    Wait { ms: u64 },
+    /// Type text with the keys of the configured keyboard layout, at the cadence of
+    /// `profile` or of the global typing profile when unset.
+    Type {
+        text: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        profile: Option<TypingProfile>,
+    },
+    Repeat { count: u32, steps: Vec<MacroStep> },
+}
+
+/// Step kinds offered by the timeline editor, in dropdown order. Repeats aren't offered,
+/// since the timeline can't edit their nested steps; they come from macro files.
+pub const STEP_KINDS: [&str; 9] = ["Tap", "Key Down", "Key Up", "Chord", "Click", "Move", "Scroll", "Wait", "Type"];
+
+/// A value of a step edited inline in the timeline editor.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StepField {
+    /// The key of key steps, the space-separated keys of a chord or the button of a click.
+    Key,
+    Ms,
+    Text,
+    Dx,
+    Dy,
+    Count,
+}
+
+impl MacroStep {
+    /// A new step of `STEP_KINDS[index]` with placeholder values.
+    pub fn from_kind(index: usize) -> Option<Self> {
+        let key = || "Space".to_string();
+        Some(match index {
+            0 => MacroStep::Tap { key: key() },
+            1 => MacroStep::KeyDown { key: key() },
+            2 => MacroStep::KeyUp { key: key() },
+            3 => MacroStep::Chord { keys: vec!["Control".to_string(), "C".to_string()] },
+            4 => MacroStep::MouseClick { button: MouseButton::Left },
+            5 => MacroStep::MouseMove { dx: 0, dy: 0 },
+            6 => MacroStep::Scroll { dx: 0, dy: -1 },
+            7 => MacroStep::Wait { ms: 100 },
+            8 => MacroStep::Type { text: String::new(), profile: None },
+            _ => return None,
+        })
+    }
+
+    /// The `STEP_KINDS` label of this step, or "Repeat".
+    pub fn kind(&self) -> &'static str {
+        let index = match self {
+            MacroStep::Tap { .. } => 0,
+            MacroStep::KeyDown { .. } => 1,
+            MacroStep::KeyUp { .. } => 2,
+            MacroStep::Chord { .. } => 3,
+            MacroStep::MouseClick { .. } => 4,
+            MacroStep::MouseMove { .. } => 5,
+            MacroStep::Scroll { .. } => 6,
+            MacroStep::Wait { .. } => 7,
+            MacroStep::Type { .. } => 8,
+            MacroStep::Repeat { .. } => return "Repeat",
+        };
+        STEP_KINDS[index]
+    }
+
+    /// Sets `field` from text input. Returns false and leaves the step unchanged when the
+    /// step has no such field or `value` doesn't parse; an empty number counts as 0.
+    pub fn set_field(&mut self, field: StepField, value: &str) -> bool {
+        fn number<T: std::str::FromStr + Default>(value: &str) -> Option<T> {
+            let value = value.trim();
+            if value.is_empty() { Some(T::default()) } else { value.parse().ok() }
+        }
+
+        match (self, field) {
+            (MacroStep::KeyDown { key } | MacroStep::KeyUp { key } | MacroStep::Tap { key }, StepField::Key) => {
+                *key = value.trim().to_string();
+            }
+            // Empty keys are kept while typing so the separator survives until the next key
+            (MacroStep::Chord { keys }, StepField::Key) => *keys = value.split(' ').map(str::to_string).collect(),
+            (MacroStep::MouseClick { button }, StepField::Key) => {
+                *button = match value {
+                    "Left" => MouseButton::Left,
+                    "Middle" => MouseButton::Middle,
+                    "Right" => MouseButton::Right,
+                    _ => return false,
+                };
+            }
+            (MacroStep::Wait { ms }, StepField::Ms) => match number(value) {
+                Some(value) => *ms = value,
+                None => return false,
+            },
+            (MacroStep::Type { text, .. }, StepField::Text) => *text = value.to_string(),
+            (MacroStep::MouseMove { dx, .. } | MacroStep::Scroll { dx, .. }, StepField::Dx) => match number(value) {
+                Some(value) => *dx = value,
+                None => return false,
+            },
+            (MacroStep::MouseMove { dy, .. } | MacroStep::Scroll { dy, .. }, StepField::Dy) => match number(value) {
+                Some(value) => *dy = value,
+                None => return false,
+            },
+            (MacroStep::Repeat { count, .. }, StepField::Count) => match number(value) {
+                Some(value) => *count = value,
+                None => return false,
+            },
+            _ => return false,
+        }
+        true
+    }
+
+    fn collect_keys(&self, keys: &mut Vec<String>) {
+        match self {
+            MacroStep::KeyDown { key } | MacroStep::KeyUp { key } | MacroStep::Tap { key } => keys.push(key.clone()),
+            MacroStep::Chord { keys: chord } => keys.extend(chord.iter().cloned()),
+            MacroStep::MouseClick { button } => keys.push(button.key_name().to_string()),
+            MacroStep::Repeat { steps, .. } => steps.iter().for_each(|step| step.collect_keys(keys)),
+            MacroStep::MouseMove { .. } | MacroStep::Scroll { .. } | MacroStep::Wait { .. } | MacroStep::Type { .. } => {}
+        }
+    }
+
+    pub(crate) fn collect_text(&self, text: &mut String) {
+        match self {
+            MacroStep::Type { text: typed, .. } => text.push_str(typed),
+            MacroStep::Repeat { steps, .. } => steps.iter().for_each(|step| step.collect_text(text)),
+            _ => {}
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct Macro {
+    pub name: String,
+    #[serde(default)]
+    pub steps: Vec<MacroStep>,
+}
+
+impl Macro {
+    /// Builds a macro that taps each key in order, waiting `delay_ms(index)` after each one.
+    pub fn from_keys(name: String, keys: &[String], delay_ms: impl Fn(usize) -> u64) -> Self {
+        let mut steps = Vec::new();
+        for (index, key) in keys.iter().enumerate() {
+            steps.push(MacroStep::Tap { key: key.clone() });
+            let ms = delay_ms(index);
+            if ms > 0 {
+                steps.push(MacroStep::Wait { ms });
+            }
+        }
+        Self { name, steps }
+    }
+
+    /// Moves the step at `from` to index `to`, shifting the steps in between.
+    pub fn move_step(&mut self, from: usize, to: usize) -> bool {
+        if from >= self.steps.len() || to >= self.steps.len() {
+            return false;
+        }
+        let step = self.steps.remove(from);
+        self.steps.insert(to, step);
+        true
+    }
+
+    /// All text typed by the macro; its keys come from the keyboard layout, not `referenced_keys`.
+    pub fn typed_text(&self) -> String {
+        let mut text = String::new();
+        self.steps.iter().for_each(|step| step.collect_text(&mut text));
+        text
+    }
+
+    /// Every key name used by the macro, nested steps included, in order of first use.
+    pub fn referenced_keys(&self) -> Vec<String> {
+        let mut keys = Vec::new();
+        self.steps.iter().for_each(|step| step.collect_keys(&mut keys));
+
+        let mut unique: Vec<String> = Vec::new();
+        for key in keys {
+            if !unique.contains(&key) {
+                unique.push(key);
+            }
+        }
+        unique
+    }
+}
+
+/// How `KeyBehaviorMode::Macro` plays the active macro.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct PlaybackOptions {
+    /// Speed multiplier for waits, clamped to `MIN_PLAYBACK_SPEED..=MAX_PLAYBACK_SPEED`.
+    pub speed: f64,
+    /// Waits longer than this are shortened to it before speed scaling.
+    pub max_idle_ms: Option<u64>,
+    /// Number of times to play the macro; 0 loops until stopped.
+    pub loop_count: u32,
+}
+
+impl Default for PlaybackOptions {
+    fn default() -> Self {
+        Self {
+            speed: 1.0,
+            max_idle_ms: None,
+            loop_count: 0,
+        }
+    }
+}
+
+impl PlaybackOptions {
+    pub fn clamped_speed(&self) -> f64 {
+        if self.speed.is_finite() {
+            self.speed.clamp(MIN_PLAYBACK_SPEED, MAX_PLAYBACK_SPEED)
+        } else {
+            1.0
+        }
+    }
+
+    /// How long a `Wait { ms }` step lasts during playback.
+    pub fn wait_duration(&self, ms: u64) -> Duration {
+        let ms = match self.max_idle_ms {
+            Some(limit) => ms.min(limit),
+            None => ms,
+        };
+        Duration::from_secs_f64(ms as f64 / 1000.0 / self.clamped_speed())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_macro_json_format() {
+        let json = r#"{
+            "name": "farm",
+            "steps": [
+                {"type": "tap", "key": "Ctrl+C"},
+                {"type": "wait", "ms": 200},
+                {"type": "repeat", "count": 3, "steps": [
+                    {"type": "mouse_click", "button": "Left"},
+                    {"type": "mouse_move", "dx": 10, "dy": 0},
+                    {"type": "scroll", "dy": -1}
+                ]}
+            ]
+        }"#;
+        let parsed: Macro = serde_json::from_str(json).unwrap();
+
+        assert_eq!(parsed.steps[0], MacroStep::Tap { key: "Ctrl+C".to_string() });
+        assert_eq!(parsed.steps[2], MacroStep::Repeat {
+            count: 3,
+            steps: vec![
+                MacroStep::MouseClick { button: MouseButton::Left },
+                MacroStep::MouseMove { dx: 10, dy: 0 },
+                MacroStep::Scroll { dx: 0, dy: -1 },
+            ],
+        });
+
+        let round_trip: Macro = serde_json::from_str(&serde_json::to_string(&parsed).unwrap()).unwrap();
+        assert_eq!(round_trip, parsed);
+    }
+
+    #[test]
+    fn test_referenced_keys() {
+        let m = Macro {
+            name: "keys".to_string(),
+            steps: vec![
+                MacroStep::KeyDown { key: "Shift".to_string() },
+                MacroStep::Repeat {
+                    count: 2,
+                    steps: vec![
+                        MacroStep::Chord { keys: vec!["W".to_string(), "Space".to_string()] },
+                        MacroStep::MouseClick { button: MouseButton::Right },
+                    ],
+                },
+                MacroStep::KeyUp { key: "Shift".to_string() },
+            ],
+        };
+        assert_eq!(m.referenced_keys(), vec!["Shift", "W", "Space", "BTN_RIGHT"]);
+    }
+
+    #[test]
+    fn test_move_step() {
+        let mut m = Macro::from_keys("keys".to_string(), &["A".to_string(), "B".to_string(), "C".to_string()], |_| 0);
+        assert!(m.move_step(0, 2));
+        assert_eq!(m.referenced_keys(), vec!["B", "C", "A"]);
+        assert!(m.move_step(2, 1));
+        assert_eq!(m.referenced_keys(), vec!["B", "A", "C"]);
+        assert!(!m.move_step(1, 3));
+    }
+
+    #[test]
+    fn test_set_step_field() {
+        let mut wait = MacroStep::from_kind(7).unwrap();
+        assert!(wait.set_field(StepField::Ms, "250"));
+        assert!(!wait.set_field(StepField::Ms, "soon"));
+        assert!(!wait.set_field(StepField::Key, "A"));
+        assert_eq!(wait, MacroStep::Wait { ms: 250 });
+        assert!(wait.set_field(StepField::Ms, ""));
+        assert_eq!(wait, MacroStep::Wait { ms: 0 });
+
+        let mut chord = MacroStep::from_kind(3).unwrap();
+        assert!(chord.set_field(StepField::Key, "Shift "));
+        assert_eq!(chord, MacroStep::Chord { keys: vec!["Shift".to_string(), String::new()] });
+
+        let mut scroll = MacroStep::Scroll { dx: 0, dy: 1 };
+        assert!(scroll.set_field(StepField::Dx, "-3"));
+        assert_eq!(scroll, MacroStep::Scroll { dx: -3, dy: 1 });
+
+        let mut click = MacroStep::from_kind(4).unwrap();
+        assert!(click.set_field(StepField::Key, "Right"));
+        assert_eq!(click.kind(), "Click");
+        assert_eq!(click, MacroStep::MouseClick { button: MouseButton::Right });
+
+        assert_eq!(MacroStep::from_kind(STEP_KINDS.len()), None);
+        assert_eq!(MacroStep::Repeat { count: 2, steps: Vec::new() }.kind(), "Repeat");
+    }
+
+    #[test]
+    fn test_playback_wait_duration() {
+        let options = PlaybackOptions { speed: 2.0, max_idle_ms: Some(1000), loop_count: 1 };
+        assert_eq!(options.wait_duration(200), Duration::from_millis(100));
+        assert_eq!(options.wait_duration(5000), Duration::from_millis(500));
+
+        let slow = PlaybackOptions { speed: 0.01, ..PlaybackOptions::default() };
+        assert_eq!(slow.wait_duration(100), Duration::from_millis(400));
+        let fast = PlaybackOptions { speed: 100.0, ..PlaybackOptions::default() };
+        assert_eq!(fast.wait_duration(100), Duration::from_millis(10));
+    }
+}
@@ -6,6 +6,15 @@ mod ui;
 mod constants;
 mod error;
 mod logging;
+mod macros;
+mod macro_dsl;
+mod recorder;
+mod script;
+mod typing;
+mod cadence;
+mod clipboard;
+mod evemu;
+mod xdotool;
 
 use log::LevelFilter;
 use crate::error::Result;
@@ -18,6 +27,7 @@ fn main() -> Result<()> {
     
     logging::init(log_level)?;
     log::info!("Starting Input Simulator");
+    typing::restore_spare_key_mapping();
     
     cosmic::app::run::<app::InputSimulatorApp>(ui::default_window_settings(), ())
         .map_err(|e| e.into())
@@ -0,0 +1,357 @@
+use std::{
+    fs::{self, OpenOptions},
+    io,
+    os::unix::fs::OpenOptionsExt,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    thread,
+    time::Duration,
+};
+
+use evdev_rs::{
+    Device,
+    DeviceWrapper,
+    InputEvent,
+    ReadFlag,
+    enums::{EventCode, EventType, EV_KEY, EV_REL},
+};
+
+use crate::{
+    constants::{RECORDER_POLL_MS, VIRTUAL_DEVICE_NAME},
+    error::{AppError, Result},
+    macros::{Macro, MacroStep},
+};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RecordedInput {
+    Key { key: String, pressed: bool },
+    Motion { dx: i32, dy: i32 },
+    Scroll { dx: i32, dy: i32 },
+}
+
+/// An input event with its kernel timestamp in microseconds.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RecordedEvent {
+    pub time_us: i64,
+    pub input: RecordedInput,
+}
+
+#[derive(Debug, Default)]
+struct RecorderState {
+    // Bumped on every start/stop so reader threads from an earlier recording exit
+    generation: u64,
+    recording: bool,
+    events: Vec<RecordedEvent>,
+}
+
+/// Records real keyboards and mice through evdev, skipping our own virtual device.
+#[derive(Debug, Default)]
+pub struct Recorder {
+    state: Arc<Mutex<RecorderState>>,
+}
+
+impl Recorder {
+    /// Starts reading every readable keyboard and mouse. Fails if none could be opened,
+    /// which usually means the user lacks access to /dev/input.
+    pub fn start(&self) -> Result<()> {
+        let generation = {
+            let mut state = self.state.lock().unwrap();
+            state.generation += 1;
+            state.recording = true;
+            state.events.clear();
+            state.generation
+        };
+
+        let mut opened = 0;
+        for path in input_device_paths()? {
+            match open_input_device(&path) {
+                Ok(device) if is_recordable(&device) => {
+                    log::info!("Recording from {} ({})", device.name().unwrap_or("unnamed"), path.display());
+                    let state = Arc::clone(&self.state);
+                    thread::spawn(move || read_device_events(device, state, generation));
+                    opened += 1;
+                }
+                Ok(_) => {}
+                Err(e) => log::debug!("Skipping {}: {}", path.display(), e),
+            }
+        }
+
+        if opened == 0 {
+            self.state.lock().unwrap().recording = false;
+            return Err(AppError::Device(
+                "No readable keyboard or mouse in /dev/input (is the user in the input group?)".to_string(),
+            ));
+        }
+        Ok(())
+    }
+
+    /// Stops recording and returns the captured events in time order.
+    pub fn stop(&self) -> Vec<RecordedEvent> {
+        let mut state = self.state.lock().unwrap();
+        state.generation += 1;
+        state.recording = false;
+        let mut events = std::mem::take(&mut state.events);
+        events.sort_by_key(|event| event.time_us);
+        log::info!("Recorded {} input events", events.len());
+        events
+    }
+}
+
+pub(crate) fn input_device_paths() -> Result<Vec<PathBuf>> {
+    let mut paths: Vec<PathBuf> = fs::read_dir("/dev/input")?
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| {
+            path.file_name()
+                .and_then(|name| name.to_str())
+                .is_some_and(|name| name.starts_with("event"))
+        })
+        .collect();
+    paths.sort();
+    Ok(paths)
+}
+
+pub(crate) fn open_input_device(path: &Path) -> io::Result<Device> {
+    let file = OpenOptions::new()
+        .read(true)
+        .custom_flags(libc::O_NONBLOCK)
+        .open(path)?;
+    Device::new_from_file(file)
+}
+
+pub(crate) fn is_own_device(device: &Device) -> bool {
+    device.name() == Some(VIRTUAL_DEVICE_NAME)
+}
+
+fn is_recordable(device: &Device) -> bool {
+    !is_own_device(device)
+        && (device.has_event_type(&EventType::EV_KEY) || device.has_event_type(&EventType::EV_REL))
+}
+
+fn read_device_events(device: Device, state: Arc<Mutex<RecorderState>>, generation: u64) {
+    loop {
+        if state.lock().unwrap().generation != generation {
+            return;
+        }
+        match device.next_event(ReadFlag::NORMAL) {
+            Ok((_, event)) => {
+                if let Some(input) = recorded_input(&event) {
+                    let time_us = event.time.tv_sec as i64 * 1_000_000 + event.time.tv_usec as i64;
+                    let mut state = state.lock().unwrap();
+                    if state.generation == generation {
+                        state.events.push(RecordedEvent { time_us, input });
+                    }
+                }
+            }
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => {
+                thread::sleep(Duration::from_millis(RECORDER_POLL_MS));
+            }
+            Err(e) => {
+                log::warn!("Stopped reading {}: {}", device.name().unwrap_or("input device"), e);
+                return;
+            }
+        }
+    }
+}
+
+/// Name used for a key in macros: the `selected_keys` name when there is one,
+/// otherwise the evdev name (e.g. "KEY_RIGHTCTRL").
+pub(crate) fn recorded_key_name(key: EV_KEY) -> String {
+    crate::utils::key_utils::evkey_to_key_name(key)
+        .map(str::to_string)
+        .unwrap_or_else(|| format!("{:?}", key))
+}
+
+pub(crate) fn recorded_input(event: &InputEvent) -> Option<RecordedInput> {
+    match event.event_code {
+        // Value 2 is autorepeat; the press and release already cover how long the key was held
+        EventCode::EV_KEY(key) if event.value == 0 || event.value == 1 => {
+            // Touchpad and tablet tool "buttons" aren't clicks
+            let is_tool_button = format!("{:?}", key).starts_with("BTN_")
+                && !matches!(key, EV_KEY::BTN_LEFT | EV_KEY::BTN_RIGHT | EV_KEY::BTN_MIDDLE | EV_KEY::BTN_SIDE | EV_KEY::BTN_EXTRA);
+            if is_tool_button {
+                return None;
+            }
+            Some(RecordedInput::Key { key: recorded_key_name(key), pressed: event.value == 1 })
+        }
+        EventCode::EV_REL(EV_REL::REL_X) => Some(RecordedInput::Motion { dx: event.value, dy: 0 }),
+        EventCode::EV_REL(EV_REL::REL_Y) => Some(RecordedInput::Motion { dx: 0, dy: event.value }),
+        EventCode::EV_REL(EV_REL::REL_HWHEEL) => Some(RecordedInput::Scroll { dx: event.value, dy: 0 }),
+        EventCode::EV_REL(EV_REL::REL_WHEEL) => Some(RecordedInput::Scroll { dx: 0, dy: event.value }),
+        _ => None,
+    }
+}
+
+/// Converts recorded events into macro steps with the original timing.
+///
+/// Key events for `ignored_keys` (the hotkey that started and stopped the recording) are
+/// dropped, releases of keys pressed before recording started are skipped, and keys
+/// still down at the end are released. Presses at the very end that were never released
+/// are dropped instead, as they hold the stop hotkey's modifiers.
+pub fn events_to_steps(events: &[RecordedEvent], ignored_keys: &[String]) -> Vec<MacroStep> {
+    let mut steps: Vec<MacroStep> = Vec::new();
+    let mut pressed: Vec<String> = Vec::new();
+    let mut clock_us: Option<i64> = None;
+
+    for event in events {
+        if let RecordedInput::Key { key, pressed: is_press } = &event.input {
+            if ignored_keys.contains(key) || (!is_press && !pressed.contains(key)) {
+                continue;
+            }
+        }
+
+        // Waits are whole milliseconds; the remainder carries over to the next gap.
+        let start_us = *clock_us.get_or_insert(event.time_us);
+        let wait_ms = (event.time_us - start_us).max(0) / 1000;
+        let waited = wait_ms > 0;
+        if waited {
+            steps.push(MacroStep::Wait { ms: wait_ms as u64 });
+            clock_us = Some(start_us + wait_ms * 1000);
+        }
+
+        match &event.input {
+            RecordedInput::Key { key, pressed: true } => {
+                pressed.push(key.clone());
+                steps.push(MacroStep::KeyDown { key: key.clone() });
+            }
+            RecordedInput::Key { key, pressed: false } => {
+                pressed.retain(|k| k != key);
+                // A press and release in the same millisecond is a tap
+                let is_tap = !waited && matches!(steps.last(), Some(MacroStep::KeyDown { key: down }) if down == key);
+                if is_tap {
+                    steps.pop();
+                    steps.push(MacroStep::Tap { key: key.clone() });
+                } else {
+                    steps.push(MacroStep::KeyUp { key: key.clone() });
+                }
+            }
+            // Motion and scroll from the same report arrive as separate axis events
+            RecordedInput::Motion { dx, dy } => match steps.last_mut() {
+                Some(MacroStep::MouseMove { dx: last_dx, dy: last_dy }) if !waited => {
+                    *last_dx += dx;
+                    *last_dy += dy;
+                }
+                _ => steps.push(MacroStep::MouseMove { dx: *dx, dy: *dy }),
+            },
+            RecordedInput::Scroll { dx, dy } => match steps.last_mut() {
+                Some(MacroStep::Scroll { dx: last_dx, dy: last_dy }) if !waited => {
+                    *last_dx += dx;
+                    *last_dy += dy;
+                }
+                _ => steps.push(MacroStep::Scroll { dx: *dx, dy: *dy }),
+            },
+        }
+    }
+
+    // Along with the wait before them, which only led up to the stop hotkey
+    while let Some(MacroStep::KeyDown { key }) = steps.last() {
+        let key = key.clone();
+        pressed.retain(|k| *k != key);
+        steps.pop();
+        if matches!(steps.last(), Some(MacroStep::Wait { .. })) {
+            steps.pop();
+        }
+    }
+
+    for key in pressed.into_iter().rev() {
+        steps.push(MacroStep::KeyUp { key });
+    }
+    steps
+}
+
+pub fn events_to_macro(name: String, events: &[RecordedEvent], ignored_keys: &[String]) -> Macro {
+    Macro { name, steps: events_to_steps(events, ignored_keys) }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(time_ms: i64, key: &str, pressed: bool) -> RecordedEvent {
+        RecordedEvent { time_us: time_ms * 1000, input: RecordedInput::Key { key: key.to_string(), pressed } }
+    }
+
+    fn motion(time_ms: i64, dx: i32, dy: i32) -> RecordedEvent {
+        RecordedEvent { time_us: time_ms * 1000, input: RecordedInput::Motion { dx, dy } }
+    }
+
+    #[test]
+    fn test_events_to_steps_keeps_timing() {
+        let events = vec![
+            key(1000, "A", true),
+            key(1080, "A", false),
+            key(1200, "B", true),
+            key(1200, "B", false),
+        ];
+        assert_eq!(events_to_steps(&events, &[]), vec![
+            MacroStep::KeyDown { key: "A".to_string() },
+            MacroStep::Wait { ms: 80 },
+            MacroStep::KeyUp { key: "A".to_string() },
+            MacroStep::Wait { ms: 120 },
+            MacroStep::Tap { key: "B".to_string() },
+        ]);
+    }
+
+    #[test]
+    fn test_events_to_steps_merges_motion_frames() {
+        let events = vec![
+            motion(0, 3, 0),
+            motion(0, 0, -2),
+            motion(10, 1, 1),
+        ];
+        assert_eq!(events_to_steps(&events, &[]), vec![
+            MacroStep::MouseMove { dx: 3, dy: -2 },
+            MacroStep::Wait { ms: 10 },
+            MacroStep::MouseMove { dx: 1, dy: 1 },
+        ]);
+    }
+
+    #[test]
+    fn test_events_to_steps_drops_hotkey_and_orphans() {
+        let events = vec![
+            key(0, "F8", false),
+            key(5, "Shift", false),
+            key(20, "Shift", true),
+            key(40, "BTN_LEFT", true),
+            key(40, "BTN_LEFT", false),
+            key(90, "F8", true),
+        ];
+        assert_eq!(events_to_steps(&events, &["F8".to_string()]), vec![
+            MacroStep::KeyDown { key: "Shift".to_string() },
+            MacroStep::Wait { ms: 20 },
+            MacroStep::Tap { key: "BTN_LEFT".to_string() },
+            MacroStep::KeyUp { key: "Shift".to_string() },
+        ]);
+    }
+
+    #[test]
+    fn test_events_to_steps_drops_stop_hotkey_modifiers() {
+        // Started and stopped with Ctrl+F8
+        let events = vec![
+            key(0, "F8", false),
+            key(2, "Control", false),
+            key(100, "A", true),
+            key(100, "A", false),
+            key(300, "Control", true),
+            key(320, "F8", true),
+        ];
+        assert_eq!(events_to_steps(&events, &["F8".to_string()]), vec![MacroStep::Tap { key: "A".to_string() }]);
+    }
+
+    #[test]
+    fn test_events_to_steps_carries_sub_millisecond_remainders() {
+        let events = vec![
+            RecordedEvent { time_us: 0, input: RecordedInput::Motion { dx: 1, dy: 0 } },
+            RecordedEvent { time_us: 1_500, input: RecordedInput::Motion { dx: 1, dy: 0 } },
+            RecordedEvent { time_us: 3_000, input: RecordedInput::Motion { dx: 1, dy: 0 } },
+        ];
+        let waits: u64 = events_to_steps(&events, &[])
+            .iter()
+            .map(|step| match step {
+                MacroStep::Wait { ms } => *ms,
+                _ => 0,
+            })
+            .sum();
+        assert_eq!(waits, 3);
+    }
+}
@@ -0,0 +1,324 @@
+//! Rhai scripts for `KeyBehaviorMode::Script`. The script runs once per interval on the
+//! simulation thread and can call:
+//!
+//! - `press(key)`, `release(key)`, `tap(key)`: keys use the `selected_keys` names, chords included
+//! - `move(dx, dy)`, `click("left" | "middle" | "right")`, `sleep(ms)`
+//! - `type_text(text)`: type with the configured keyboard layout, Unicode fallback and typing profile
+//! - `is_key_down(key)`: whether the key is held physically or by the script
+//! - `cursor_position()`: `[x, y]` of the pointer
+//! - `random(min, max)`: a random integer in `min..=max`, seeded by `AppData::random_seed`
+//! - `stop()`: end the run
+//!
+//! `print` writes to the log, and the `state` object map keeps values between runs, e.g.
+//! `state.count = (state.count ?? 0) + 1;`.
+//!
+//! `is_key_down` and `cursor_position` ask X11 through device_query. On a pure Wayland
+//! session they only see the script's own keys, and the pointer is always at `[0, 0]`.
+//!
+//! Stopping the simulation cancels the script at its next operation and releases every key
+//! it still holds. Pausing takes effect between runs of the script.
+
+use std::{
+    cell::RefCell,
+    rc::Rc,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use device_query::{DeviceQuery, DeviceState};
+use evdev_rs::{enums::EV_REL, TimeVal, UInputDevice};
+use rand::{rngs::StdRng, Rng};
+use rhai::{Array, Dynamic, Engine, EvalAltResult, Map, Scope};
+
+use crate::{
+    error::{AppError, Result},
+    macros::MouseButton,
+    simulator::{
+        map_simulation_key, pause_point, press_keys, release_keys, sleep_while_running, tap_key, write_rel_events,
+        SimulationKey,
+    },
+    typing::TextTyper,
+    utils::random::create_rng,
+};
+
+type ScriptResult<T> = std::result::Result<T, Box<EvalAltResult>>;
+
+// Device and key state shared by the host functions
+struct ScriptHost {
+    device: UInputDevice,
+    timeval: TimeVal,
+    held: Vec<SimulationKey>,
+    device_state: DeviceState,
+    rng: StdRng,
+    typer: TextTyper,
+}
+
+impl ScriptHost {
+    fn press(&mut self, key: SimulationKey) -> Result<()> {
+        press_keys(&self.device, std::slice::from_ref(&key), &self.timeval)?;
+        if !self.held.contains(&key) {
+            self.held.push(key);
+        }
+        Ok(())
+    }
+
+    fn release(&mut self, key: SimulationKey) -> Result<()> {
+        release_keys(&self.device, std::slice::from_ref(&key), &self.timeval)?;
+        self.held.retain(|held| *held != key);
+        Ok(())
+    }
+
+    fn release_held(&mut self) -> Result<()> {
+        let held = std::mem::take(&mut self.held);
+        release_keys(&self.device, &held, &self.timeval)
+    }
+
+    fn is_key_down(&self, name: &str) -> bool {
+        let physically_down = crate::utils::key_utils::key_to_device_keycode(name)
+            .is_some_and(|keycode| self.device_state.get_keys().contains(&keycode));
+        physically_down || map_simulation_key(&name.to_string()).is_some_and(|key| self.held.contains(&key))
+    }
+}
+
+fn script_key(name: &str) -> ScriptResult<SimulationKey> {
+    map_simulation_key(&name.to_string()).ok_or_else(|| format!("Unknown key: {}", name).into())
+}
+
+fn script_button(name: &str) -> ScriptResult<SimulationKey> {
+    let button = match name.to_lowercase().as_str() {
+        "left" => MouseButton::Left,
+        "middle" => MouseButton::Middle,
+        "right" => MouseButton::Right,
+        _ => return Err(format!("Unknown mouse button: {}", name).into()),
+    };
+    script_key(button.key_name())
+}
+
+fn host_error(e: AppError) -> Box<EvalAltResult> {
+    e.to_string().into()
+}
+
+fn build_engine(host: &Rc<RefCell<ScriptHost>>, running: &Arc<Mutex<bool>>) -> Engine {
+    let mut engine = Engine::new();
+
+    // Cancels the script as soon as the run is stopped
+    let progress_running = Arc::clone(running);
+    engine.on_progress(move |_| {
+        if *progress_running.lock().unwrap() {
+            None
+        } else {
+            Some(Dynamic::UNIT)
+        }
+    });
+
+    let h = Rc::clone(host);
+    engine.register_fn("press", move |key: &str| -> ScriptResult<()> {
+        h.borrow_mut().press(script_key(key)?).map_err(host_error)
+    });
+    let h = Rc::clone(host);
+    engine.register_fn("release", move |key: &str| -> ScriptResult<()> {
+        h.borrow_mut().release(script_key(key)?).map_err(host_error)
+    });
+    let h = Rc::clone(host);
+    engine.register_fn("tap", move |key: &str| -> ScriptResult<()> {
+        let host = h.borrow();
+        tap_key(&host.device, &script_key(key)?, &host.timeval).map_err(host_error)
+    });
+    let h = Rc::clone(host);
+    engine.register_fn("click", move |button: &str| -> ScriptResult<()> {
+        let host = h.borrow();
+        tap_key(&host.device, &script_button(button)?, &host.timeval).map_err(host_error)
+    });
+    let h = Rc::clone(host);
+    engine.register_fn("move", move |dx: i64, dy: i64| -> ScriptResult<()> {
+        let host = h.borrow();
+        write_rel_events(&host.device, &[(EV_REL::REL_X, dx as i32), (EV_REL::REL_Y, dy as i32)], &host.timeval)
+            .map_err(host_error)
+    });
+
+    let h = Rc::clone(host);
+    let type_running = Arc::clone(running);
+    engine.register_fn("type_text", move |text: &str| -> ScriptResult<()> {
+        let host = &mut *h.borrow_mut();
+        let chars = host.typer.plan(text);
+        host.typer.type_chars(&host.device, &type_running, &chars, &host.timeval)
+            .map(|_| ())
+            .map_err(host_error)
+    });
+
+    let sleep_running = Arc::clone(running);
+    engine.register_fn("sleep", move |ms: i64| {
+        sleep_while_running(&sleep_running, Duration::from_millis(ms.max(0) as u64));
+    });
+    let stop_running = Arc::clone(running);
+    engine.register_fn("stop", move || {
+        log::info!("Script requested stop");
+        *stop_running.lock().unwrap() = false;
+    });
+
+    let h = Rc::clone(host);
+    engine.register_fn("is_key_down", move |key: &str| h.borrow().is_key_down(key));
+    let h = Rc::clone(host);
+    engine.register_fn("cursor_position", move || -> Array {
+        let (x, y) = h.borrow().device_state.get_mouse().coords;
+        vec![Dynamic::from(x as i64), Dynamic::from(y as i64)]
+    });
+    let h = Rc::clone(host);
+    engine.register_fn("random", move |min: i64, max: i64| -> ScriptResult<i64> {
+        if min > max {
+            return Err(format!("random({}, {}): min is larger than max", min, max).into());
+        }
+        Ok(h.borrow_mut().rng.random_range(min..=max))
+    });
+
+    engine.on_print(|message| log::info!("Script: {}", message));
+
+    engine
+}
+
+/// Checks a script for syntax errors without running it.
+pub fn check_script(source: &str) -> Result<()> {
+    Engine::new()
+        .compile(source)
+        .map(|_| ())
+        .map_err(|e| AppError::Script(e.to_string()))
+}
+
+/// Runs `source` once per interval until the run is stopped or the script fails.
+pub fn run_script(
+    device: UInputDevice,
+    running: &Arc<Mutex<bool>>,
+    paused: &Arc<Mutex<bool>>,
+    interval_ms: &Arc<Mutex<u64>>,
+    source: &str,
+    seed: Option<u64>,
+    typer: TextTyper,
+) -> Result<()> {
+    let host = Rc::new(RefCell::new(ScriptHost {
+        device,
+        timeval: TimeVal::new(0, 0),
+        held: Vec::new(),
+        device_state: DeviceState::new(),
+        rng: create_rng(seed),
+        typer,
+    }));
+    let engine = build_engine(&host, running);
+
+    let result = engine
+        .compile(source)
+        .map_err(|e| AppError::Script(e.to_string()))
+        .and_then(|ast| {
+            let mut scope = Scope::new();
+            scope.push("state", Map::new());
+            loop {
+                {
+                    let host = host.borrow();
+                    if !pause_point(&host.device, running, paused, &host.held, &host.timeval)? {
+                        return Ok(());
+                    }
+                }
+                let run = engine.run_ast_with_scope(&mut scope, &ast);
+                // Drop the run's own variables; only `state` carries over
+                scope.rewind(1);
+                match run {
+                    Ok(()) => {}
+                    Err(e) if matches!(*e, EvalAltResult::ErrorTerminated(..)) => return Ok(()),
+                    Err(e) => return Err(AppError::Script(e.to_string())),
+                }
+                let interval = *interval_ms.lock().unwrap();
+                if !sleep_while_running(running, Duration::from_millis(interval)) {
+                    return Ok(());
+                }
+            }
+        });
+
+    host.borrow_mut().release_held()?;
+    if result.is_err() {
+        *running.lock().unwrap() = false;
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    use evdev_rs::{enums::{EventCode, EV_KEY}, UninitDevice};
+
+    use crate::{
+        cadence::TypingProfile,
+        config::UnicodeFallback,
+        evemu::{finish_trace, parse_evemu, start_trace},
+        typing::LayoutKeymap,
+    };
+
+    // A virtual keyboard for the script, or None without access to /dev/uinput
+    fn test_device() -> Option<UInputDevice> {
+        let device = UninitDevice::new()?;
+        device.set_name("script test");
+        device.enable(EventCode::EV_KEY(EV_KEY::KEY_A)).ok()?;
+        UInputDevice::create_from_device(&device).ok()
+    }
+
+    fn typer() -> TextTyper {
+        TextTyper::new(LayoutKeymap::default(), UnicodeFallback::default(), TypingProfile::default())
+    }
+
+    fn flag(value: bool) -> Arc<Mutex<bool>> {
+        Arc::new(Mutex::new(value))
+    }
+
+    #[test]
+    fn test_compile_error_line() {
+        let error = check_script("tap(\"A\");\nlet x = ;\n").unwrap_err();
+        assert!(matches!(&error, AppError::Script(message) if message.contains("line 2")), "{}", error);
+    }
+
+    #[test]
+    fn test_press_release_trace() {
+        let Some(device) = test_device() else {
+            eprintln!("/dev/uinput not available, skipping the script trace test");
+            return;
+        };
+        let running = flag(true);
+        let path = std::env::temp_dir().join(format!("script-trace-{}.evemu", std::process::id()));
+
+        start_trace(path.to_str().unwrap()).unwrap();
+        let source = "press(\"A\");\nrelease(\"A\");\nstop();\n";
+        run_script(device, &running, &flag(false), &Arc::new(Mutex::new(0)), source, None, typer()).unwrap();
+        finish_trace().unwrap();
+        let trace = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let keys: Vec<(u16, i32)> = parse_evemu(&trace).unwrap()
+            .iter()
+            .filter(|event| event.event_type == 1)
+            .map(|event| (event.code, event.value))
+            .collect();
+        assert_eq!(keys, vec![(EV_KEY::KEY_A as u16, 1), (EV_KEY::KEY_A as u16, 0)]);
+        assert!(!*running.lock().unwrap());
+    }
+
+    #[test]
+    fn test_stop_running_script() {
+        let Some(device) = test_device() else {
+            eprintln!("/dev/uinput not available, skipping the script stop test");
+            return;
+        };
+        let running = flag(true);
+        let stopper = {
+            let running = Arc::clone(&running);
+            thread::spawn(move || {
+                thread::sleep(Duration::from_millis(100));
+                *running.lock().unwrap() = false;
+            })
+        };
+
+        // Never returns on its own, so only the running flag ends it
+        let source = "press(\"A\");\nloop { sleep(5); }\n";
+        run_script(device, &running, &flag(false), &Arc::new(Mutex::new(0)), source, None, typer()).unwrap();
+        stopper.join().unwrap();
+        assert!(!*running.lock().unwrap());
+    }
+}
@@ -1,7 +1,7 @@
 use std::{
     sync::{Arc, Mutex},
     thread,
-    time::Duration,
+    time::{Duration, Instant},
 };
 
 use evdev_rs::{
@@ -14,17 +14,89 @@ use evdev_rs::{
 };
 
 use crate::{
-    config::{KeyBehaviorMode, ModifierBehaviorMode, HoldBehaviorMode},
+    cadence::TypingProfile,
+    config::{AppData, KeyBehaviorMode, KeyChord, KeyOverride, KeyboardLayout, ModifierBehaviorMode, HoldBehaviorMode, UnicodeFallback},
     constants::{
-        SIMULATION_HOLD_DELAY_MS, 
+        SIMULATION_HOLD_DELAY_MS,
+        SCHEDULE_POLL_MS,
         MAX_RETRIES,
         RETRY_DELAY_MS,
         MAX_DEVICE_INIT_RETRIES,
         DEVICE_INIT_RETRY_DELAY_MS,
+        PASTE_START_DELAY_MS,
+        SYNTHETIC_ECHO_MS,
+        VIRTUAL_DEVICE_NAME,
     },
-    error::{SimulatorError, Result},
+    error::{AppError, SimulatorError, Result},
+    macros::{Macro, MacroStep, PlaybackOptions},
+    utils::random::{create_rng, pick_weighted_index},
+    utils::schedule::{KeySchedule, ScheduledAction},
+    typing::{LayoutKeymap, TextTyper, TypeAction, TypedChar},
 };
 
+/// Event codes of one `selected_keys` entry; a chord lists its modifiers before the key.
+pub type SimulationKey = Vec<EventCode>;
+
+/// Settings for `KeyBehaviorMode::Random`, with `weights` aligned to the simulation keys.
+#[derive(Debug, Clone, Default)]
+pub struct RandomSelection {
+    pub weights: Vec<u32>,
+    pub no_repeat: bool,
+    pub seed: Option<u64>,
+}
+
+/// Behavior settings captured from AppData when a run starts.
+#[derive(Debug, Clone)]
+pub struct SimulationSettings {
+    pub modifier_behavior: ModifierBehaviorMode,
+    pub hold_behavior: HoldBehaviorMode,
+    pub random_selection: RandomSelection,
+    /// Per-key settings for `KeyBehaviorMode::PerKey`, aligned to the simulation keys.
+    pub key_overrides: Vec<KeyOverride>,
+    /// Delay after each step of `KeyBehaviorMode::Sequence`, aligned to the simulation keys.
+    pub step_delays: Vec<u64>,
+    /// Steps of the active macro for `KeyBehaviorMode::Macro`.
+    pub macro_steps: Vec<MacroStep>,
+    /// Index of the step the first loop of the macro starts at.
+    pub macro_start_step: usize,
+    pub macro_playback: PlaybackOptions,
+    /// Source of `AppData::script_file` for `KeyBehaviorMode::Script`; None if it can't be read.
+    pub script_source: Option<String>,
+    /// Layout, fallback and cadence of typed text in macros and scripts.
+    pub keyboard_layout: KeyboardLayout,
+    pub unicode_fallback: UnicodeFallback,
+    pub typing_profile: TypingProfile,
+    /// Writes the emitted events to this file as an evemu recording when set.
+    pub trace_file: String,
+    /// Started by a hold hotkey: the run lasts until it's released, whatever the macro loop count.
+    pub run_while_held: bool,
+}
+
+impl SimulationSettings {
+    pub fn from_app_data(app_data: &AppData) -> Self {
+        Self {
+            modifier_behavior: app_data.modifier_behavior,
+            hold_behavior: app_data.hold_behavior,
+            random_selection: resolve_random_selection(app_data),
+            key_overrides: resolve_key_overrides(app_data),
+            step_delays: resolve_step_delays(app_data),
+            macro_steps: app_data.active_macro()
+                .map(|m| m.steps.clone())
+                .unwrap_or_default(),
+            macro_start_step: app_data.timeline.play_from.unwrap_or(0),
+            macro_playback: app_data.macro_playback,
+            script_source: (app_data.key_behavior == KeyBehaviorMode::Script)
+                .then(|| read_script_source(app_data).ok())
+                .flatten(),
+            keyboard_layout: app_data.keyboard_layout.clone(),
+            unicode_fallback: app_data.unicode_fallback,
+            typing_profile: app_data.typing_profile,
+            trace_file: app_data.evemu_trace_file.trim().to_string(),
+            run_while_held: false,
+        }
+    }
+}
+
 fn retry<T, F>(mut operation: F, max_retries: u32, delay_ms: u64, log_fn: impl Fn(usize)) -> Result<T>
 where
     F: FnMut() -> Result<T>,
@@ -46,7 +118,7 @@ where
 }
 
 fn write_event_with_retry(device: &UInputDevice, event: &InputEvent) -> Result<()> {
-    retry(
+    let written = retry(
         || {
             device.write_event(event)
                 .map_err(|e| SimulatorError::KeySimulation(format!("Failed event: {:?}", e)).into())
@@ -56,23 +128,172 @@ fn write_event_with_retry(device: &UInputDevice, event: &InputEvent) -> Result<(
         |attempt| {
             log::debug!("Write event attempt {} failed, retrying...", attempt);
         },
-    )
-    .map_err(|e| e)
+    );
+    if written.is_ok() {
+        crate::evemu::trace_event(event);
+    }
+    written
+}
+
+// Keys the virtual device holds or let go of lately, for hotkey listeners that can't tell
+// which device a key event came from
+struct SyntheticKey {
+    key: EV_KEY,
+    held: bool,
+    at: Instant,
+}
+
+impl SyntheticKey {
+    // A key let go of longer ago was either seen pressed already or never will be
+    fn is_observable(&self) -> bool {
+        self.held || self.at.elapsed() <= Duration::from_millis(SYNTHETIC_ECHO_MS)
+    }
+}
+
+static SYNTHETIC_KEYS: Mutex<Vec<SyntheticKey>> = Mutex::new(Vec::new());
+
+fn note_synthetic_key(key: EV_KEY, pressed: bool) {
+    let mut synthetic = SYNTHETIC_KEYS.lock().unwrap();
+    synthetic.retain(|entry| entry.key != key && entry.is_observable());
+    synthetic.push(SyntheticKey { key, held: pressed, at: Instant::now() });
+}
+
+// Marks every key the virtual device holds as let go once a run ends, however it ends: the
+// device goes away with the run, and so do its keys
+struct ReleaseSyntheticKeys;
+
+impl Drop for ReleaseSyntheticKeys {
+    fn drop(&mut self) {
+        let mut synthetic = SYNTHETIC_KEYS.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        let now = Instant::now();
+        for entry in synthetic.iter_mut().filter(|entry| entry.held) {
+            entry.held = false;
+            entry.at = now;
+        }
+    }
+}
+
+/// Whether a press of a key `matches` accepts is the virtual device's own: it holds the key,
+/// or let go of it within `SYNTHETIC_ECHO_MS`. A let-go key counts once, so a real press
+/// right after still counts.
+pub(crate) fn take_synthetic_press(matches: impl Fn(EV_KEY) -> bool) -> bool {
+    take_synthetic_chord(|keys| keys.iter().any(|&key| matches(key)))
+}
+
+/// Whether the keys the virtual device holds or let go of within `SYNTHETIC_ECHO_MS` make
+/// up a chord `is_chord` accepts by themselves. Let-go keys the chord needs are used up.
+pub(crate) fn take_synthetic_chord(is_chord: impl Fn(&[EV_KEY]) -> bool) -> bool {
+    let mut synthetic = SYNTHETIC_KEYS.lock().unwrap();
+    synthetic.retain(SyntheticKey::is_observable);
+    let keys: Vec<EV_KEY> = synthetic.iter().map(|entry| entry.key).collect();
+    if !is_chord(&keys) {
+        return false;
+    }
+    let is_needed = |key: EV_KEY| {
+        let rest: Vec<EV_KEY> = keys.iter().copied().filter(|&other| other != key).collect();
+        !is_chord(&rest)
+    };
+    synthetic.retain(|entry| entry.held || !is_needed(entry.key));
+    true
 }
 
 fn write_key_events(device: &UInputDevice, keys: &[EventCode], value: i32, timeval: &TimeVal) -> Result<()> {
     for &key in keys {
         write_event_with_retry(device, &InputEvent::new(timeval, &key, value))?;
+        if let EventCode::EV_KEY(key) = key {
+            note_synthetic_key(key, value == 1);
+        }
     }
     // Always sync after key events
     write_event_with_retry(device, &InputEvent::new(timeval, &EventCode::EV_SYN(EV_SYN::SYN_REPORT), 0))?;
     Ok(())
 }
 
+pub(crate) fn write_rel_events(device: &UInputDevice, axes: &[(EV_REL, i32)], timeval: &TimeVal) -> Result<()> {
+    for &(axis, value) in axes.iter().filter(|(_, value)| *value != 0) {
+        write_event_with_retry(device, &InputEvent::new(timeval, &EventCode::EV_REL(axis), value))?;
+    }
+    write_event_with_retry(device, &InputEvent::new(timeval, &EventCode::EV_SYN(EV_SYN::SYN_REPORT), 0))?;
+    Ok(())
+}
+
+// Sleeps for `duration` in short steps; returns false if the run was stopped meanwhile
+pub(crate) fn sleep_while_running(running: &Arc<Mutex<bool>>, duration: Duration) -> bool {
+    let deadline = Instant::now() + duration;
+    loop {
+        if !*running.lock().unwrap() {
+            return false;
+        }
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        if remaining.is_zero() {
+            return true;
+        }
+        thread::sleep(remaining.min(Duration::from_millis(SCHEDULE_POLL_MS)));
+    }
+}
+
+// Blocks while the run is paused; returns false if it was stopped meanwhile
+fn wait_while_paused(running: &Arc<Mutex<bool>>, paused: &Arc<Mutex<bool>>) -> bool {
+    while *paused.lock().unwrap() {
+        if !*running.lock().unwrap() {
+            return false;
+        }
+        thread::sleep(Duration::from_millis(SCHEDULE_POLL_MS));
+    }
+    *running.lock().unwrap()
+}
+
+/// Where a run can be paused: waits out a pause with `held` released and presses them again
+/// on resume, so the run goes on from here. Returns false once the run is stopped.
+pub(crate) fn pause_point(
+    device: &UInputDevice,
+    running: &Arc<Mutex<bool>>,
+    paused: &Arc<Mutex<bool>>,
+    held: &[SimulationKey],
+    timeval: &TimeVal,
+) -> Result<bool> {
+    if !*paused.lock().unwrap() {
+        return Ok(*running.lock().unwrap());
+    }
+    release_keys(device, held, timeval)?;
+    log::info!("Simulation paused");
+    if !wait_while_paused(running, paused) {
+        return Ok(false);
+    }
+    log::info!("Simulation resumed");
+    press_keys(device, held, timeval)?;
+    Ok(true)
+}
+
+fn flatten_keys(keys: &[SimulationKey]) -> Vec<EventCode> {
+    keys.iter().flatten().copied().collect()
+}
+
+// Presses every code of the given entries in a single report
+pub(crate) fn press_keys(device: &UInputDevice, keys: &[SimulationKey], timeval: &TimeVal) -> Result<()> {
+    write_key_events(device, &flatten_keys(keys), 1, timeval)
+}
+
+// Releases in reverse order so chord keys go up before their modifiers
+pub(crate) fn release_keys(device: &UInputDevice, keys: &[SimulationKey], timeval: &TimeVal) -> Result<()> {
+    let mut codes = flatten_keys(keys);
+    codes.reverse();
+    write_key_events(device, &codes, 0, timeval)
+}
+
+pub(crate) fn tap_key(device: &UInputDevice, key: &SimulationKey, timeval: &TimeVal) -> Result<()> {
+    press_keys(device, std::slice::from_ref(key), timeval)?;
+    release_keys(device, std::slice::from_ref(key), timeval)
+}
+
+fn is_modifier_key(key: &SimulationKey) -> bool {
+    matches!(key.as_slice(), [code] if crate::utils::key_utils::is_modifier_evcode(code))
+}
+
 // Creates and configures a virtual input device with the specified key capabilities
-fn setup_device(selected_keys: &Arc<Mutex<Vec<EventCode>>>) -> Result<UInputDevice> {
+fn setup_device(selected_keys: &Arc<Mutex<Vec<SimulationKey>>>) -> Result<UInputDevice> {
     let device = UninitDevice::new().unwrap();
-    device.set_name("input_simulator");
+    device.set_name(VIRTUAL_DEVICE_NAME);
 
     {
         let keys = selected_keys.lock().unwrap();
@@ -83,8 +304,10 @@ fn setup_device(selected_keys: &Arc<Mutex<Vec<EventCode>>>) -> Result<UInputDevi
         device.enable(EventCode::EV_KEY(EV_KEY::BTN_MIDDLE)).unwrap();
         device.enable(EventCode::EV_REL(EV_REL::REL_X)).unwrap();
         device.enable(EventCode::EV_REL(EV_REL::REL_Y)).unwrap();
+        device.enable(EventCode::EV_REL(EV_REL::REL_WHEEL)).unwrap();
+        device.enable(EventCode::EV_REL(EV_REL::REL_HWHEEL)).unwrap();
 
-        for &key in keys.iter() {
+        for key in flatten_keys(&keys) {
             device.enable(key).unwrap();
         }
     }
@@ -93,7 +316,7 @@ fn setup_device(selected_keys: &Arc<Mutex<Vec<EventCode>>>) -> Result<UInputDevi
     Ok(uinput_device)
 }
 
-fn setup_device_with_retry(selected_keys: &Arc<Mutex<Vec<EventCode>>>) -> Result<UInputDevice> {
+fn setup_device_with_retry(selected_keys: &Arc<Mutex<Vec<SimulationKey>>>) -> Result<UInputDevice> {
     retry(
         || setup_device(selected_keys),
         MAX_DEVICE_INIT_RETRIES,
@@ -105,45 +328,356 @@ fn setup_device_with_retry(selected_keys: &Arc<Mutex<Vec<EventCode>>>) -> Result
     .map_err(|e| SimulatorError::DeviceInitialization(format!("Failed after {} retries: {:?}", MAX_DEVICE_INIT_RETRIES, e)).into())
 }
 
-// Initialize simulation keys
+pub(crate) fn map_simulation_key(raw: &String) -> Option<SimulationKey> {
+    let chord = raw.parse::<KeyChord>().ok()?;
+    let key = crate::utils::key_utils::raw_key_to_device_keycode(&chord.key)
+        .and_then(crate::utils::key_utils::keycode_to_evkey)
+        .or_else(|| crate::utils::key_utils::evdev_name_to_evkey(&chord.key))?;
+
+    let mut codes: SimulationKey = crate::utils::key_utils::modifier_evkeys(&chord.modifiers)
+        .into_iter()
+        .map(EventCode::EV_KEY)
+        .collect();
+    codes.push(EventCode::EV_KEY(key));
+    Some(codes)
+}
+
+// Typing is skipped rather than failing the run when the layout can't be loaded
+fn load_layout_keymap(layout: &KeyboardLayout) -> LayoutKeymap {
+    LayoutKeymap::new(layout).unwrap_or_else(|e| {
+        log::error!("{}", e);
+        LayoutKeymap::default()
+    })
+}
+
+/// Reads `AppData::script_file`; a run can't start without it.
+pub(crate) fn read_script_source(app_data: &AppData) -> Result<String> {
+    let path = app_data.script_file.trim();
+    if path.is_empty() {
+        return Err(AppError::Script("no script file set".to_string()));
+    }
+    std::fs::read_to_string(path)
+        .map_err(|e| AppError::Script(format!("failed to read script {}: {}", path, e)))
+}
+
+// Initialize simulation keys; returns whether there is anything to simulate.
+// In macro mode the keys are the ones the active macro uses, so the device can emit them.
+// Scripts pick keys at runtime, so every known key is enabled.
 pub fn initialize_simulation_keys(
     app_data: &crate::config::AppData,
-    selected_keys: &mut Vec<evdev_rs::enums::EventCode>,
+    selected_keys: &mut Vec<SimulationKey>,
     key_behavior: &mut crate::config::KeyBehaviorMode,
-) {
+) -> bool {
     selected_keys.clear();
     *key_behavior = app_data.key_behavior;
 
-    log::debug!("Initializing simulation with keys: {:?}", app_data.selected_keys);
+    let raw_keys = if app_data.key_behavior == KeyBehaviorMode::Macro {
+        app_data.active_macro()
+            .map(Macro::referenced_keys)
+            .unwrap_or_default()
+    } else if app_data.key_behavior == KeyBehaviorMode::Script {
+        let mut names: Vec<String> = crate::utils::key_utils::KEY_MAPPINGS
+            .keys()
+            .map(|name| name.to_string())
+            .collect();
+        names.sort();
+        names
+    } else {
+        app_data.selected_keys.clone()
+    };
 
-    for raw in &app_data.selected_keys {
-        if let Some(device_key) = crate::utils::key_utils::raw_key_to_device_keycode(raw) {
-            if let Some(ev_key) = crate::utils::key_utils::keycode_to_evkey(device_key) {
-                selected_keys.push(evdev_rs::enums::EventCode::EV_KEY(ev_key));
-                log::debug!("Added key: {:?}", ev_key);
-            }
+    log::debug!("Initializing simulation with keys: {:?}", raw_keys);
+
+    for raw in &raw_keys {
+        if let Some(key) = map_simulation_key(raw) {
+            log::debug!("Added key: {:?}", key);
+            selected_keys.push(key);
         } else {
             log::warn!("Failed to map key: {}", raw);
         }
     }
 
+    // Typed text needs the layout's keys enabled on the device too
+    let typing_keys = match app_data.key_behavior {
+        KeyBehaviorMode::Macro => {
+            let text = app_data.active_macro().map(Macro::typed_text).unwrap_or_default();
+            if text.is_empty() {
+                Vec::new()
+            } else {
+                load_layout_keymap(&app_data.keyboard_layout)
+                    .plan_text(&text, app_data.unicode_fallback)
+                    .iter()
+                    .map(TypeAction::key)
+                    .collect()
+            }
+        }
+        KeyBehaviorMode::Script => load_layout_keymap(&app_data.keyboard_layout).all_keys(app_data.unicode_fallback),
+        _ => Vec::new(),
+    };
+    for key in typing_keys {
+        if !selected_keys.contains(&key) {
+            selected_keys.push(key);
+        }
+    }
+
+    if app_data.key_behavior == KeyBehaviorMode::Macro {
+        let step_count = app_data.active_macro().map_or(0, |m| m.steps.len());
+        if step_count == 0 {
+            log::warn!("No macro with steps selected for simulation");
+        } else {
+            log::info!("Simulation initialized with a {} step macro", step_count);
+        }
+        return step_count > 0;
+    }
+
+    if app_data.key_behavior == KeyBehaviorMode::Script {
+        if let Err(e) = read_script_source(app_data) {
+            log::warn!("Can't run the script: {}", e);
+            return false;
+        }
+        return true;
+    }
+
     if selected_keys.is_empty() {
         log::warn!("No valid keys initialized for simulation");
     } else {
         log::info!("Simulation initialized with {} keys", selected_keys.len());
     }
+    !selected_keys.is_empty()
+}
+
+// Resolves random selection settings, skipping the same unmappable keys as initialize_simulation_keys
+fn resolve_random_selection(app_data: &AppData) -> RandomSelection {
+    RandomSelection {
+        weights: app_data.selected_keys
+            .iter()
+            .filter(|raw| map_simulation_key(raw).is_some())
+            .map(|raw| app_data.key_weight(raw))
+            .collect(),
+        no_repeat: app_data.random_no_repeat,
+        seed: app_data.random_seed,
+    }
+}
+
+fn resolve_key_overrides(app_data: &AppData) -> Vec<KeyOverride> {
+    app_data.selected_keys
+        .iter()
+        .filter(|raw| map_simulation_key(raw).is_some())
+        .map(|raw| app_data.key_override(raw).clamped())
+        .collect()
+}
+
+fn resolve_step_delays(app_data: &AppData) -> Vec<u64> {
+    app_data.selected_keys
+        .iter()
+        .enumerate()
+        .filter(|(_, raw)| map_simulation_key(raw).is_some())
+        .map(|(index, _)| app_data.step_delay(index))
+        .collect()
+}
+
+// A macro step with its keys resolved to event codes
+#[derive(Debug, Clone)]
+enum CompiledStep {
+    KeyDown(SimulationKey),
+    KeyUp(SimulationKey),
+    Tap(SimulationKey),
+    Chord(Vec<SimulationKey>),
+    /// Typed with its own profile, or the typer's when unset.
+    Type(Vec<TypedChar>, Option<TypingProfile>),
+    MouseMove(i32, i32),
+    Scroll(i32, i32),
+    Wait(Duration),
+    Repeat(u32, Vec<CompiledStep>),
+}
+
+fn compile_macro_key(raw: &String) -> Option<SimulationKey> {
+    let key = map_simulation_key(raw);
+    if key.is_none() {
+        log::warn!("Skipping unmapped macro key: {}", raw);
+    }
+    key
+}
+
+fn compile_macro_steps(steps: &[MacroStep], playback: &PlaybackOptions, typer: &TextTyper) -> Vec<CompiledStep> {
+    steps.iter().filter_map(|step| match step {
+        MacroStep::KeyDown { key } => compile_macro_key(key).map(CompiledStep::KeyDown),
+        MacroStep::KeyUp { key } => compile_macro_key(key).map(CompiledStep::KeyUp),
+        MacroStep::Tap { key } => compile_macro_key(key).map(CompiledStep::Tap),
+        MacroStep::Chord { keys } => Some(CompiledStep::Chord(keys.iter().filter_map(compile_macro_key).collect())),
+        MacroStep::Type { text, profile } => Some(CompiledStep::Type(typer.plan(text), *profile)),
+        MacroStep::MouseClick { button } => compile_macro_key(&button.key_name().to_string()).map(CompiledStep::Tap),
+        MacroStep::MouseMove { dx, dy } => Some(CompiledStep::MouseMove(*dx, *dy)),
+        MacroStep::Scroll { dx, dy } => Some(CompiledStep::Scroll(*dx, *dy)),
+        MacroStep::Wait { ms } => Some(CompiledStep::Wait(playback.wait_duration(*ms))),
+        MacroStep::Repeat { count, steps } => Some(CompiledStep::Repeat(*count, compile_macro_steps(steps, playback, typer))),
+    }).collect()
+}
+
+// Plays compiled macro steps, remembering keys left down so they can be released on stop.
+// A pause takes effect before the next step, which is where playback resumes.
+struct MacroRunner<'a> {
+    device: &'a UInputDevice,
+    running: &'a Arc<Mutex<bool>>,
+    paused: &'a Arc<Mutex<bool>>,
+    timeval: &'a TimeVal,
+    typer: TextTyper,
+    held: Vec<SimulationKey>,
+}
+
+impl<'a> MacroRunner<'a> {
+    fn new(
+        device: &'a UInputDevice,
+        running: &'a Arc<Mutex<bool>>,
+        paused: &'a Arc<Mutex<bool>>,
+        timeval: &'a TimeVal,
+        typer: TextTyper,
+    ) -> Self {
+        Self { device, running, paused, timeval, typer, held: Vec::new() }
+    }
+
+    // Returns false once the run has been stopped
+    fn run(&mut self, steps: &[CompiledStep]) -> Result<bool> {
+        for step in steps {
+            if !pause_point(self.device, self.running, self.paused, &self.held, self.timeval)? {
+                return Ok(false);
+            }
+            match step {
+                CompiledStep::KeyDown(key) => {
+                    press_keys(self.device, std::slice::from_ref(key), self.timeval)?;
+                    if !self.held.contains(key) {
+                        self.held.push(key.clone());
+                    }
+                }
+                CompiledStep::KeyUp(key) => {
+                    release_keys(self.device, std::slice::from_ref(key), self.timeval)?;
+                    self.held.retain(|held| held != key);
+                }
+                CompiledStep::Tap(key) => tap_key(self.device, key, self.timeval)?,
+                CompiledStep::Chord(keys) => {
+                    press_keys(self.device, keys, self.timeval)?;
+                    release_keys(self.device, keys, self.timeval)?;
+                }
+                CompiledStep::Type(chars, profile) => {
+                    let typed = match profile {
+                        Some(profile) => self.typer.type_chars_with(*profile, self.device, self.running, chars, self.timeval)?,
+                        None => self.typer.type_chars(self.device, self.running, chars, self.timeval)?,
+                    };
+                    if !typed {
+                        return Ok(false);
+                    }
+                }
+                CompiledStep::MouseMove(dx, dy) => {
+                    write_rel_events(self.device, &[(EV_REL::REL_X, *dx), (EV_REL::REL_Y, *dy)], self.timeval)?;
+                }
+                CompiledStep::Scroll(dx, dy) => {
+                    write_rel_events(self.device, &[(EV_REL::REL_HWHEEL, *dx), (EV_REL::REL_WHEEL, *dy)], self.timeval)?;
+                }
+                CompiledStep::Wait(duration) => {
+                    if !sleep_while_running(self.running, *duration) {
+                        return Ok(false);
+                    }
+                }
+                CompiledStep::Repeat(count, steps) => {
+                    for _ in 0..*count {
+                        // A body without steps or waits would otherwise never see a stop
+                        if !pause_point(self.device, self.running, self.paused, &self.held, self.timeval)?
+                            || !self.run(steps)?
+                        {
+                            return Ok(false);
+                        }
+                    }
+                }
+            }
+        }
+        Ok(true)
+    }
+
+    fn release_held(&mut self) -> Result<()> {
+        let held = std::mem::take(&mut self.held);
+        release_keys(self.device, &held, self.timeval)
+    }
+}
+
+fn write_scheduled_action(device: &UInputDevice, keys: &[SimulationKey], action: ScheduledAction, timeval: &TimeVal) -> Result<()> {
+    match action {
+        ScheduledAction::Press(index) => press_keys(device, &keys_at(keys, &[index]), timeval),
+        ScheduledAction::Release(index) => release_keys(device, &keys_at(keys, &[index]), timeval),
+    }
+}
+
+fn keys_at(keys: &[SimulationKey], indices: &[usize]) -> Vec<SimulationKey> {
+    indices.iter().filter_map(|&index| keys.get(index).cloned()).collect()
+}
+
+/// Types `text` once on a virtual device of its own, for the paste hotkey.
+/// Clearing `running` stops it early.
+pub fn type_text_once(
+    running: &Arc<Mutex<bool>>,
+    text: &str,
+    layout: &KeyboardLayout,
+    fallback: UnicodeFallback,
+    profile: TypingProfile,
+) -> Result<()> {
+    let mut typer = TextTyper::new(load_layout_keymap(layout), fallback, profile);
+    let chars = typer.plan(text);
+
+    let mut keys: Vec<SimulationKey> = Vec::new();
+    for key in chars.iter().flat_map(|typed| typed.actions.iter().map(TypeAction::key)) {
+        if !keys.contains(&key) {
+            keys.push(key);
+        }
+    }
+    let uinput_device = setup_device_with_retry(&Arc::new(Mutex::new(keys)))?;
+    let _release_synthetic = ReleaseSyntheticKeys;
+    let timeval = TimeVal::new(0, 0);
+
+    // Input from a brand-new device is dropped until the compositor has picked it up
+    if !sleep_while_running(running, Duration::from_millis(PASTE_START_DELAY_MS)) {
+        return Ok(());
+    }
+    log::info!("Typing {} characters", chars.len());
+    typer.type_chars(&uinput_device, running, &chars, &timeval)?;
+    Ok(())
 }
 
-// Main simulation loop that handles both click and hold modes
+/// Runs the simulation until `running` is cleared, tracing the emitted events if asked to.
+/// While `paused` is set the run waits with its keys released, then continues where it was.
 pub fn simulate_keys(
     running: Arc<Mutex<bool>>,
+    paused: Arc<Mutex<bool>>,
+    interval_ms: Arc<Mutex<u64>>,
+    selected_keys: Arc<Mutex<Vec<SimulationKey>>>,
+    key_behavior: Arc<Mutex<KeyBehaviorMode>>,
+    settings: SimulationSettings,
+) -> Result<()> {
+    let trace_file = settings.trace_file.clone();
+    if !trace_file.is_empty() {
+        if let Err(e) = crate::evemu::start_trace(&trace_file) {
+            log::error!("Failed to create evemu trace {}: {}", trace_file, e);
+        }
+    }
+
+    let result = run_simulation(running, paused, interval_ms, selected_keys, key_behavior, settings);
+
+    if let Err(e) = crate::evemu::finish_trace() {
+        log::error!("Failed to write evemu trace {}: {}", trace_file, e);
+    }
+    result
+}
+
+// Main simulation loop that handles click, hold, random and per-key modes
+fn run_simulation(
+    running: Arc<Mutex<bool>>,
+    paused: Arc<Mutex<bool>>,
     interval_ms: Arc<Mutex<u64>>,
-    selected_keys: Arc<Mutex<Vec<EventCode>>>,
+    selected_keys: Arc<Mutex<Vec<SimulationKey>>>,
     key_behavior: Arc<Mutex<KeyBehaviorMode>>,
-    modifier_behavior: ModifierBehaviorMode,
-    hold_behavior: HoldBehaviorMode,
+    settings: SimulationSettings,
 ) -> Result<()> {
+    let SimulationSettings { modifier_behavior, hold_behavior, random_selection, key_overrides, step_delays, macro_steps, macro_start_step, macro_playback, script_source, keyboard_layout, unicode_fallback, typing_profile, run_while_held, .. } = settings;
     let uinput_device = setup_device_with_retry(&selected_keys)?;
+    let _release_synthetic = ReleaseSyntheticKeys;
     let timeval = TimeVal::new(0, 0);
     
     // Combine acquisitions for keys and mode.
@@ -160,6 +694,10 @@ pub fn simulate_keys(
     // Initial sync
     write_event_with_retry(&uinput_device, &InputEvent::new(&timeval, &EventCode::EV_SYN(EV_SYN::SYN_REPORT), 0))?;
 
+    // Releasing keys that are already up is a no-op for the input core, so a stop during a
+    // pause can end with the usual release
+    let keep_running = |held: &[SimulationKey]| pause_point(&uinput_device, &running, &paused, held, &timeval);
+
     match mode {
         KeyBehaviorMode::Hold => {
             thread::sleep(Duration::from_millis(SIMULATION_HOLD_DELAY_MS));
@@ -168,73 +706,267 @@ pub fn simulate_keys(
             match hold_behavior {
                 HoldBehaviorMode::Continuous => {
                     // Press all keys and hold forever
-                    write_key_events(&uinput_device, &keys, 1, &timeval)?;
+                    press_keys(&uinput_device, &keys, &timeval)?;
 
-                    while *running.lock().unwrap() {
+                    while keep_running(&keys)? {
                         write_key_events(&uinput_device, &[], 0, &timeval)?;
                     }
 
                     // Release keys
-                    write_key_events(&uinput_device, &keys, 0, &timeval)?;
+                    release_keys(&uinput_device, &keys, &timeval)?;
                 },
                 HoldBehaviorMode::Cycle => {
-                    // Cycle through each key, holding for interval_ms
-                    while *running.lock().unwrap() {
+                    // Cycle through each key, holding for interval_ms; a pause keeps the position
+                    while keep_running(&[])? {
                         let interval = *interval_ms.lock().unwrap();
                         if keys.is_empty() {
                             thread::sleep(Duration::from_millis(interval));
                             continue;
                         }
                         for key in &keys {
-                            if !*running.lock().unwrap() {
+                            if !keep_running(&[])? {
                                 break;
                             }
-                            write_key_events(&uinput_device, &[*key], 1, &timeval)?;
+                            press_keys(&uinput_device, std::slice::from_ref(key), &timeval)?;
                             thread::sleep(Duration::from_millis(interval));
-                            write_key_events(&uinput_device, &[*key], 0, &timeval)?;
+                            release_keys(&uinput_device, std::slice::from_ref(key), &timeval)?;
                         }
                     }
                 }
             }
         },
         KeyBehaviorMode::Click => {
-            if modifier_behavior == ModifierBehaviorMode::Click {
+            // Chords are never split: only bare modifier keys count as modifiers here
+            if modifier_behavior == ModifierBehaviorMode::Latch {
+                let (mod_keys, non_mod_keys): (Vec<SimulationKey>, Vec<SimulationKey>) =
+                    keys.iter().cloned().partition(is_modifier_key);
+
+                // Latch modifiers for the whole run
+                press_keys(&uinput_device, &mod_keys, &timeval)?;
+
+                while keep_running(&mod_keys)? {
+                    let interval = *interval_ms.lock().unwrap();
+
+                    for nm in &non_mod_keys {
+                        tap_key(&uinput_device, nm, &timeval)?;
+                    }
+
+                    thread::sleep(Duration::from_millis(interval));
+                }
+
+                release_keys(&uinput_device, &mod_keys, &timeval)?;
+            } else if modifier_behavior == ModifierBehaviorMode::Click {
                 // Separate modifier and non-modifier keys
-                let (mod_keys, non_mod_keys): (Vec<EventCode>, Vec<EventCode>) = 
-                    keys.iter().cloned().partition(|k| crate::utils::key_utils::is_modifier_evcode(k));
+                let (mod_keys, non_mod_keys): (Vec<SimulationKey>, Vec<SimulationKey>) = 
+                    keys.iter().cloned().partition(is_modifier_key);
 
-                while *running.lock().unwrap() {
+                while keep_running(&[])? {
                     let interval = *interval_ms.lock().unwrap();
 
                     // For each key sequence
                     for m in &mod_keys {
                         // Press and release modifier key first
-                        write_key_events(&uinput_device, &[*m], 1, &timeval)?;
-                        write_key_events(&uinput_device, &[*m], 0, &timeval)?;
+                        tap_key(&uinput_device, m, &timeval)?;
                     }
 
                     // Then handle non-modifier keys
                     for nm in &non_mod_keys {
-                        write_key_events(&uinput_device, &[*nm], 1, &timeval)?;
-                        write_key_events(&uinput_device, &[*nm], 0, &timeval)?;
+                        tap_key(&uinput_device, nm, &timeval)?;
                     }
 
                     thread::sleep(Duration::from_millis(interval));
                 }
             } else {
-                while *running.lock().unwrap() {
+                while keep_running(&[])? {
                     let interval = *interval_ms.lock().unwrap();
 
                     // Press keys
-                    write_key_events(&uinput_device, &keys, 1, &timeval)?;
+                    press_keys(&uinput_device, &keys, &timeval)?;
 
                     // Release keys
-                    write_key_events(&uinput_device, &keys, 0, &timeval)?;
+                    release_keys(&uinput_device, &keys, &timeval)?;
                     thread::sleep(Duration::from_millis(interval));
                 }
             }
+        },
+        KeyBehaviorMode::Random => {
+            log::info!("Random selection: {:?}", random_selection);
+            let mut rng = create_rng(random_selection.seed);
+            let mut last_index = None;
+
+            while keep_running(&[])? {
+                let interval = *interval_ms.lock().unwrap();
+                let exclude = if random_selection.no_repeat { last_index } else { None };
+
+                if let Some(index) = pick_weighted_index(&random_selection.weights, exclude, &mut rng) {
+                    if let Some(key) = keys.get(index) {
+                        tap_key(&uinput_device, key, &timeval)?;
+                    }
+                    last_index = Some(index);
+                }
+
+                thread::sleep(Duration::from_millis(interval));
+            }
+        },
+        KeyBehaviorMode::PerKey => {
+            log::info!("Per-key overrides: {:?}", key_overrides);
+            let mut schedule = KeySchedule::new(&key_overrides, Instant::now());
+            let held_keys = keys_at(&keys, schedule.held_keys());
+
+            press_keys(&uinput_device, &held_keys, &timeval)?;
+
+            loop {
+                let checked_at = Instant::now();
+                if !keep_running(&[held_keys.clone(), keys_at(&keys, &schedule.pressed_keys())].concat())? {
+                    break;
+                }
+                // A pause lasts at least one poll. Moving the timetable past it keeps keys pressed
+                // before it down for the rest of their press time, and presses don't all come due.
+                let paused_for = checked_at.elapsed();
+                if paused_for >= Duration::from_millis(SCHEDULE_POLL_MS) {
+                    schedule.delay(paused_for);
+                }
+
+                for action in schedule.due_actions(Instant::now()) {
+                    write_scheduled_action(&uinput_device, &keys, action, &timeval)?;
+                }
+
+                // Wake for the next due action, but keep checking whether the run was stopped.
+                let poll = Duration::from_millis(SCHEDULE_POLL_MS);
+                let wait = schedule.next_due()
+                    .map(|due| due.saturating_duration_since(Instant::now()).min(poll))
+                    .unwrap_or(poll);
+                thread::sleep(wait);
+            }
+
+            // Release everything still down
+            let pressed_keys = keys_at(&keys, &schedule.pressed_keys());
+            release_keys(&uinput_device, &pressed_keys, &timeval)?;
+            release_keys(&uinput_device, &held_keys, &timeval)?;
+        },
+        KeyBehaviorMode::Sequence => {
+            log::info!("Sequence step delays: {:?}", step_delays);
+
+            while keep_running(&[])? {
+                let interval = *interval_ms.lock().unwrap();
+
+                // Play the whole sequence once per tick; a pause keeps the step
+                for (index, key) in keys.iter().enumerate() {
+                    if !keep_running(&[])? {
+                        break;
+                    }
+                    tap_key(&uinput_device, key, &timeval)?;
+                    thread::sleep(Duration::from_millis(step_delays.get(index).copied().unwrap_or(0)));
+                }
+
+                thread::sleep(Duration::from_millis(interval));
+            }
+        },
+        KeyBehaviorMode::Macro => {
+            let mut typed_text = String::new();
+            macro_steps.iter().for_each(|step| step.collect_text(&mut typed_text));
+            let keymap = if typed_text.is_empty() {
+                LayoutKeymap::default()
+            } else {
+                load_layout_keymap(&keyboard_layout)
+            };
+            let typer = TextTyper::new(keymap, unicode_fallback, typing_profile);
+            let steps = compile_macro_steps(&macro_steps, &macro_playback, &typer);
+            // Compiled separately since steps that fail to compile are left out
+            let first_steps = compile_macro_steps(
+                &macro_steps[macro_start_step.min(macro_steps.len())..],
+                &macro_playback,
+                &typer,
+            );
+            if macro_start_step > 0 {
+                log::info!("Starting the macro at step {}", macro_start_step + 1);
+            }
+            let mut runner = MacroRunner::new(&uinput_device, &running, &paused, &timeval, typer);
+
+            let result: Result<()> = (|| {
+                let mut loops = 0;
+                let mut loop_steps = &first_steps;
+                while runner.run(loop_steps)? {
+                    loop_steps = &steps;
+                    loops += 1;
+                    if !run_while_held && macro_playback.loop_count > 0 && loops >= macro_playback.loop_count {
+                        log::info!("Macro finished after {} loops", loops);
+                        *running.lock().unwrap() = false;
+                        break;
+                    }
+                    let interval = *interval_ms.lock().unwrap();
+                    if !sleep_while_running(&running, Duration::from_millis(interval)) {
+                        break;
+                    }
+                }
+                Ok(())
+            })();
+
+            // Keys left down by the macro are released even if playback failed
+            runner.release_held()?;
+            result?;
+        }
+        KeyBehaviorMode::Script => {
+            let typer = TextTyper::new(load_layout_keymap(&keyboard_layout), unicode_fallback, typing_profile);
+            // Read for this run's settings, after the start was checked
+            let script_source = script_source
+                .ok_or_else(|| AppError::Script("the script file can't be read".to_string()))?;
+            crate::script::run_script(uinput_device, &running, &paused, &interval_ms, &script_source, random_selection.seed, typer)?;
         }
     }
 
     Ok(())
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The tests share the synthetic key state, and a run ending lets go of every key
+    static SYNTHETIC_TEST: Mutex<()> = Mutex::new(());
+
+    #[test]
+    fn test_synthetic_press_follows_held_keys() {
+        let _serial = SYNTHETIC_TEST.lock().unwrap();
+        let is_key = |key: EV_KEY| move |written: EV_KEY| written == key;
+        note_synthetic_key(EV_KEY::KEY_F22, true);
+        assert!(take_synthetic_press(is_key(EV_KEY::KEY_F22)));
+        assert!(take_synthetic_press(is_key(EV_KEY::KEY_F22)));
+        assert!(!take_synthetic_press(is_key(EV_KEY::KEY_F21)));
+
+        // A press reported just after the release is the late echo, but only once
+        note_synthetic_key(EV_KEY::KEY_F22, false);
+        assert!(take_synthetic_press(is_key(EV_KEY::KEY_F22)));
+        assert!(!take_synthetic_press(is_key(EV_KEY::KEY_F22)));
+    }
+
+    #[test]
+    fn test_missed_tap_keeps_real_press() {
+        let _serial = SYNTHETIC_TEST.lock().unwrap();
+        note_synthetic_key(EV_KEY::KEY_F23, true);
+        note_synthetic_key(EV_KEY::KEY_F23, false);
+        thread::sleep(Duration::from_millis(SYNTHETIC_ECHO_MS + 20));
+        assert!(!take_synthetic_press(|written| written == EV_KEY::KEY_F23));
+    }
+
+    #[test]
+    fn test_synthetic_chord_needs_every_key() {
+        let _serial = SYNTHETIC_TEST.lock().unwrap();
+        let is_chord = |held: &[EV_KEY]| held.contains(&EV_KEY::KEY_RIGHTMETA) && held.contains(&EV_KEY::KEY_F20);
+        note_synthetic_key(EV_KEY::KEY_F20, true);
+        assert!(!take_synthetic_chord(is_chord));
+        note_synthetic_key(EV_KEY::KEY_RIGHTMETA, true);
+        assert!(take_synthetic_chord(is_chord));
+        note_synthetic_key(EV_KEY::KEY_F20, false);
+        note_synthetic_key(EV_KEY::KEY_RIGHTMETA, false);
+    }
+
+    #[test]
+    fn test_ended_run_lets_go_of_keys() {
+        let _serial = SYNTHETIC_TEST.lock().unwrap();
+        note_synthetic_key(EV_KEY::KEY_F19, true);
+        // A run that returned early with the key still down
+        drop(ReleaseSyntheticKeys);
+        thread::sleep(Duration::from_millis(SYNTHETIC_ECHO_MS + 20));
+        assert!(!take_synthetic_press(|written| written == EV_KEY::KEY_F19));
+    }
+}
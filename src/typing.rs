@@ -0,0 +1,416 @@
+use std::{
+    collections::HashMap,
+    path::PathBuf,
+    process::Command,
+    sync::{Arc, Mutex},
+    thread,
+    time::Duration,
+};
+
+use evdev_rs::{
+    enums::{int_to_ev_key, EventCode, EV_KEY},
+    TimeVal,
+    UInputDevice,
+};
+use xkbcommon::xkb;
+
+use crate::{
+    cadence::{Cadence, TypingProfile},
+    config::{KeyboardLayout, UnicodeFallback},
+    constants::{REMAP_SETTLE_MS, TYPE_KEY_DELAY_MS},
+    error::{AppError, Result},
+    simulator::{sleep_while_running, tap_key, SimulationKey},
+};
+
+// Keycodes above this can't be used by X11 clients, so characters are typed with lower ones
+const MAX_XKB_KEYCODE: u32 = 255;
+// xkb keycodes are evdev codes shifted by 8
+const EVDEV_OFFSET: u32 = 8;
+// Remapped to characters the layout lacks; rarely bound to anything
+const SPARE_KEY: EV_KEY = EV_KEY::KEY_F24;
+
+/// One step of typing text.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TypeAction {
+    /// Tap a key of the layout, including the keys of a hex entry sequence.
+    Tap(SimulationKey),
+    /// Type a character through the temporarily remapped spare key.
+    Remap(char),
+}
+
+impl TypeAction {
+    /// The keys this action presses, for enabling them on the virtual device.
+    pub fn key(&self) -> SimulationKey {
+        match self {
+            TypeAction::Tap(key) => key.clone(),
+            TypeAction::Remap(_) => vec![EventCode::EV_KEY(SPARE_KEY)],
+        }
+    }
+}
+
+/// The actions that type one character of the text.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TypedChar {
+    pub c: char,
+    pub actions: Vec<TypeAction>,
+}
+
+/// The keys that type each character on a keyboard layout, read from its xkb keymap.
+#[derive(Debug, Clone, Default)]
+pub struct LayoutKeymap {
+    keys: HashMap<char, SimulationKey>,
+}
+
+impl LayoutKeymap {
+    pub fn new(layout: &KeyboardLayout) -> Result<Self> {
+        let context = xkb::Context::new(xkb::CONTEXT_NO_FLAGS);
+        let keymap = xkb::Keymap::new_from_names(
+            &context,
+            "",
+            "",
+            &layout.layout,
+            &layout.variant,
+            None,
+            xkb::KEYMAP_COMPILE_NO_FLAGS,
+        )
+        .ok_or_else(|| AppError::Device(format!("Failed to load keyboard layout {}", layout)))?;
+
+        let shift = keymap.mod_get_index(xkb::MOD_NAME_SHIFT);
+        let altgr = keymap.mod_get_index(xkb::MOD_NAME_ISO_LEVEL3_SHIFT);
+        let mut state = xkb::State::new(&keymap);
+        let mut keys = HashMap::new();
+
+        // Plain keys first, so a character is typed with the fewest modifiers
+        for (use_shift, use_altgr) in [(false, false), (true, false), (false, true), (true, true)] {
+            if use_altgr && altgr == xkb::MOD_INVALID {
+                continue;
+            }
+            let mut mask = 0;
+            if use_shift {
+                mask |= 1 << shift;
+            }
+            if use_altgr {
+                mask |= 1 << altgr;
+            }
+            state.update_mask(mask, 0, 0, 0, 0, 0);
+
+            let first = keymap.min_keycode().raw().max(EVDEV_OFFSET);
+            let last = keymap.max_keycode().raw().min(MAX_XKB_KEYCODE);
+            for raw in first..=last {
+                let Some(c) = char::from_u32(state.key_get_utf32(raw.into())).filter(|&c| c != '\0') else {
+                    continue;
+                };
+                let Some(key) = int_to_ev_key(raw - EVDEV_OFFSET) else {
+                    continue;
+                };
+                keys.entry(c).or_insert_with(|| {
+                    let mut codes = Vec::new();
+                    if use_shift {
+                        codes.push(EventCode::EV_KEY(EV_KEY::KEY_LEFTSHIFT));
+                    }
+                    if use_altgr {
+                        codes.push(EventCode::EV_KEY(EV_KEY::KEY_RIGHTALT));
+                    }
+                    codes.push(EventCode::EV_KEY(key));
+                    codes
+                });
+            }
+        }
+
+        // Return types a carriage return
+        if let Some(enter) = keys.get(&'\r').cloned() {
+            keys.entry('\n').or_insert(enter);
+        }
+
+        log::info!("Loaded keyboard layout {} with {} characters", layout, keys.len());
+        Ok(Self { keys })
+    }
+
+    pub fn key_for_char(&self, c: char) -> Option<&SimulationKey> {
+        self.keys.get(&c)
+    }
+
+    /// Ctrl+Shift+U, the hex digits of `c` and Space, if the layout has the digits.
+    fn hex_entry_keys(&self, c: char) -> Option<Vec<SimulationKey>> {
+        let mut keys = vec![hex_entry_start_key()];
+        for digit in format!("{:x}", c as u32).chars() {
+            keys.push(self.key_for_char(digit)?.clone());
+        }
+        keys.push(self.key_for_char(' ')?.clone());
+        Some(keys)
+    }
+
+    /// How to type `text`: layout keys where possible, `fallback` for everything else.
+    /// Characters that can't be typed are left out.
+    pub fn plan_chars(&self, text: &str, fallback: UnicodeFallback) -> Vec<TypedChar> {
+        text.chars()
+            .filter_map(|c| {
+                if let Some(key) = self.key_for_char(c) {
+                    return Some(vec![TypeAction::Tap(key.clone())]);
+                }
+                match fallback {
+                    UnicodeFallback::HexEntry if !c.is_control() => {
+                        let keys = self.hex_entry_keys(c);
+                        if keys.is_none() {
+                            log::warn!("Skipping {:?}, the layout can't type hex digits", c);
+                        }
+                        keys.map(|keys| keys.into_iter().map(TypeAction::Tap).collect())
+                    }
+                    UnicodeFallback::RemapKey if !c.is_control() => Some(vec![TypeAction::Remap(c)]),
+                    _ => {
+                        log::warn!("Skipping character not on the keyboard layout: {:?}", c);
+                        None
+                    }
+                }
+                .map(|actions| TypedChar { c, actions })
+            })
+            .collect()
+    }
+
+    /// `plan_chars` as one list of actions.
+    pub fn plan_text(&self, text: &str, fallback: UnicodeFallback) -> Vec<TypeAction> {
+        self.plan_chars(text, fallback)
+            .into_iter()
+            .flat_map(|typed| typed.actions)
+            .collect()
+    }
+
+    /// Every key typing may use with `fallback`, for enabling them on the virtual device.
+    pub fn all_keys(&self, fallback: UnicodeFallback) -> Vec<SimulationKey> {
+        let mut keys: Vec<SimulationKey> = Vec::new();
+        let extra = match fallback {
+            UnicodeFallback::Skip => None,
+            UnicodeFallback::HexEntry => Some(hex_entry_start_key()),
+            UnicodeFallback::RemapKey => Some(TypeAction::Remap(' ').key()),
+        };
+        for key in self.keys.values().cloned().chain(extra) {
+            if !keys.contains(&key) {
+                keys.push(key);
+            }
+        }
+        keys
+    }
+}
+
+fn hex_entry_start_key() -> SimulationKey {
+    [EV_KEY::KEY_LEFTCTRL, EV_KEY::KEY_LEFTSHIFT, EV_KEY::KEY_U]
+        .into_iter()
+        .map(EventCode::EV_KEY)
+        .collect()
+}
+
+// Where the spare key's own mapping is kept while it is remapped, so a run that never got to
+// restore it (a crash or kill) is undone on the next launch. The runtime dir goes away with
+// the session, like the remapping.
+fn saved_mapping_path() -> PathBuf {
+    std::env::var_os("XDG_RUNTIME_DIR")
+        .map(PathBuf::from)
+        .unwrap_or_else(std::env::temp_dir)
+        .join("input_simulator-spare-key.xmodmap")
+}
+
+/// Restores the spare key mapping a previous run left remapped, if any.
+pub fn restore_spare_key_mapping() {
+    let path = saved_mapping_path();
+    let Ok(original) = std::fs::read_to_string(&path) else {
+        return;
+    };
+    log::warn!("Restoring the spare key mapping a previous run left behind");
+    match run_xmodmap(original.trim()) {
+        Ok(()) => {
+            let _ = std::fs::remove_file(&path);
+        }
+        // Kept for the next remap, which restores it from the file as well
+        Err(e) => log::error!("Failed to restore the spare key mapping: {}", e),
+    }
+}
+
+// Binds the spare key to characters with xmodmap and restores its mapping when dropped
+struct KeyRemapper {
+    original: String,
+}
+
+impl KeyRemapper {
+    fn new() -> Result<Self> {
+        // A mapping left behind by an earlier run is the real one, the key holds a character now
+        if let Ok(original) = std::fs::read_to_string(saved_mapping_path()) {
+            return Ok(Self { original: original.trim().to_string() });
+        }
+        let keycode = SPARE_KEY as u32 + EVDEV_OFFSET;
+        let output = Command::new("xmodmap").arg("-pke").output()?;
+        let original = parse_xmodmap_mapping(&String::from_utf8_lossy(&output.stdout), keycode)
+            .unwrap_or_else(|| format!("keycode {} =", keycode));
+        std::fs::write(saved_mapping_path(), &original)?;
+        Ok(Self { original })
+    }
+
+    fn remap(&self, c: char) -> Result<()> {
+        let keycode = SPARE_KEY as u32 + EVDEV_OFFSET;
+        run_xmodmap(&format!("keycode {} = U{:04X}", keycode, c as u32))?;
+        // Clients pick up the new mapping asynchronously
+        thread::sleep(Duration::from_millis(REMAP_SETTLE_MS));
+        Ok(())
+    }
+}
+
+impl Drop for KeyRemapper {
+    fn drop(&mut self) {
+        // Let the last remapped character arrive before the key changes back
+        thread::sleep(Duration::from_millis(REMAP_SETTLE_MS));
+        match run_xmodmap(&self.original) {
+            Ok(()) => {
+                let _ = std::fs::remove_file(saved_mapping_path());
+            }
+            Err(e) => log::error!("Failed to restore the spare key mapping: {}", e),
+        }
+    }
+}
+
+fn run_xmodmap(expression: &str) -> Result<()> {
+    let status = Command::new("xmodmap").arg("-e").arg(expression).status()?;
+    if !status.success() {
+        return Err(AppError::Device(format!("xmodmap -e \"{}\" failed with {}", expression, status)));
+    }
+    Ok(())
+}
+
+// Finds the "keycode N = ..." line for `keycode` in `xmodmap -pke` output
+fn parse_xmodmap_mapping(output: &str, keycode: u32) -> Option<String> {
+    let prefix = format!("keycode {} =", keycode);
+    output.lines()
+        .map(str::trim)
+        .find(|line| {
+            line.strip_prefix("keycode")
+                .and_then(|rest| rest.split('=').next())
+                .and_then(|code| code.trim().parse::<u32>().ok())
+                == Some(keycode)
+        })
+        .map(|line| {
+            let symbols = line.split_once('=').map_or("", |(_, symbols)| symbols.trim());
+            format!("{} {}", prefix, symbols).trim_end().to_string()
+        })
+}
+
+/// Types text with a layout, a Unicode fallback and the cadence of a typing profile.
+#[derive(Debug)]
+pub struct TextTyper {
+    keymap: LayoutKeymap,
+    fallback: UnicodeFallback,
+    cadence: Cadence,
+}
+
+impl TextTyper {
+    pub fn new(keymap: LayoutKeymap, fallback: UnicodeFallback, profile: TypingProfile) -> Self {
+        Self { keymap, fallback, cadence: Cadence::new(profile) }
+    }
+
+    pub fn plan(&self, text: &str) -> Vec<TypedChar> {
+        self.keymap.plan_chars(text, self.fallback)
+    }
+
+    /// Types `chars` one after another; returns false if the run was stopped meanwhile.
+    pub fn type_chars(&mut self, device: &UInputDevice, running: &Arc<Mutex<bool>>, chars: &[TypedChar], timeval: &TimeVal) -> Result<bool> {
+        let mut remapper: Option<KeyRemapper> = None;
+
+        for typed in chars {
+            for (index, action) in typed.actions.iter().enumerate() {
+                if let TypeAction::Remap(c) = action {
+                    if remapper.is_none() {
+                        remapper = Some(KeyRemapper::new()?);
+                    }
+                    if let Some(remapper) = &remapper {
+                        remapper.remap(*c)?;
+                    }
+                }
+                tap_key(device, &action.key(), timeval)?;
+                // Keys of a hex entry follow each other at the fixed pace
+                if index + 1 < typed.actions.len()
+                    && !sleep_while_running(running, Duration::from_millis(TYPE_KEY_DELAY_MS))
+                {
+                    return Ok(false);
+                }
+            }
+            if !sleep_while_running(running, self.cadence.delay_after(typed.c)) {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+
+    /// Like `type_chars`, at the cadence of `profile` instead of the typer's own.
+    pub fn type_chars_with(&mut self, profile: TypingProfile, device: &UInputDevice, running: &Arc<Mutex<bool>>, chars: &[TypedChar], timeval: &TimeVal) -> Result<bool> {
+        let own = std::mem::replace(&mut self.cadence, Cadence::new(profile));
+        let typed = self.type_chars(device, running, chars, timeval);
+        self.cadence = own;
+        typed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(codes: &[EV_KEY]) -> SimulationKey {
+        codes.iter().map(|&code| EventCode::EV_KEY(code)).collect()
+    }
+
+    // The keymap of `layout`, or None on a machine without xkeyboard-config data
+    fn keymap(layout: &str) -> Option<LayoutKeymap> {
+        let keymap = LayoutKeymap::new(&KeyboardLayout { layout: layout.to_string(), variant: String::new() }).ok();
+        if keymap.is_none() {
+            eprintln!("xkb layout {} not available, skipping the test", layout);
+        }
+        keymap
+    }
+
+    #[test]
+    fn test_us_layout() {
+        let Some(keymap) = keymap("us") else {
+            return;
+        };
+
+        assert_eq!(keymap.key_for_char('a'), Some(&key(&[EV_KEY::KEY_A])));
+        assert_eq!(keymap.key_for_char('A'), Some(&key(&[EV_KEY::KEY_LEFTSHIFT, EV_KEY::KEY_A])));
+        assert_eq!(keymap.key_for_char('@'), Some(&key(&[EV_KEY::KEY_LEFTSHIFT, EV_KEY::KEY_2])));
+        assert_eq!(keymap.key_for_char('\n'), Some(&key(&[EV_KEY::KEY_ENTER])));
+        assert_eq!(keymap.plan_text("hi ø", UnicodeFallback::Skip).len(), 3);
+    }
+
+    #[test]
+    fn test_unicode_fallbacks() {
+        let Some(keymap) = keymap("us") else {
+            return;
+        };
+
+        assert_eq!(keymap.plan_text("a😀", UnicodeFallback::HexEntry), vec![
+            TypeAction::Tap(key(&[EV_KEY::KEY_A])),
+            TypeAction::Tap(key(&[EV_KEY::KEY_LEFTCTRL, EV_KEY::KEY_LEFTSHIFT, EV_KEY::KEY_U])),
+            TypeAction::Tap(key(&[EV_KEY::KEY_1])),
+            TypeAction::Tap(key(&[EV_KEY::KEY_F])),
+            TypeAction::Tap(key(&[EV_KEY::KEY_6])),
+            TypeAction::Tap(key(&[EV_KEY::KEY_0])),
+            TypeAction::Tap(key(&[EV_KEY::KEY_0])),
+            TypeAction::Tap(key(&[EV_KEY::KEY_SPACE])),
+        ]);
+        assert_eq!(keymap.plan_text("中", UnicodeFallback::RemapKey), vec![TypeAction::Remap('中')]);
+    }
+
+    #[test]
+    fn test_parse_xmodmap_mapping() {
+        let output = "keycode 201 = XF86TouchpadOff NoSymbol XF86TouchpadOff\nkeycode 202 =\nkeycode  20 = minus underscore\n";
+        assert_eq!(parse_xmodmap_mapping(output, 201).as_deref(), Some("keycode 201 = XF86TouchpadOff NoSymbol XF86TouchpadOff"));
+        assert_eq!(parse_xmodmap_mapping(output, 202).as_deref(), Some("keycode 202 ="));
+        assert_eq!(parse_xmodmap_mapping(output, 20).as_deref(), Some("keycode 20 = minus underscore"));
+        assert_eq!(parse_xmodmap_mapping(output, 9), None);
+    }
+
+    #[test]
+    fn test_altgr_characters() {
+        let Some(keymap) = keymap("no") else {
+            return;
+        };
+
+        assert_eq!(keymap.key_for_char('ø'), Some(&key(&[EV_KEY::KEY_SEMICOLON])));
+        assert_eq!(keymap.key_for_char('@'), Some(&key(&[EV_KEY::KEY_RIGHTALT, EV_KEY::KEY_2])));
+    }
+}
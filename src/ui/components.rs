@@ -1,13 +1,14 @@
 use cosmic::{
     iced::Length,
-    widget::{button, Column, Container, Dropdown, MouseArea, Row, Slider, TextInput, Text},
+    widget::{button, checkbox, scrollable, Column, Container, Dropdown, MouseArea, Row, Slider, Space, TextInput, Text},
     Element,
 };
 use crate::{
     app::{Message, KeyEvent},
-    config::{AppData, KeyBehaviorMode, ModifierBehaviorMode, HoldBehaviorMode},
+    config::{AppData, GlobalHotkey, HotkeyAction, HotkeyActivation, HotkeyBackend, HotkeyModifiers, KeyBehaviorMode, ModifierBehaviorMode, ModifierSide, HoldBehaviorMode, RecordingState, TempHotkeyState, UnicodeFallback},
+    macros::{MacroStep, MouseButton, StepField, STEP_KINDS},
     utils::handle_scroll_value,
-    constants::{MIN_INTERVAL_MS, MAX_INTERVAL_MS},
+    constants::{DEFAULT_RELEASE_DELAY_MS, MIN_INTERVAL_MS, MAX_INTERVAL_MS, MIN_PLAYBACK_SPEED, MAX_PLAYBACK_SPEED},
 };
 
 pub fn interval_controls(interval: f64, app_data: &AppData) -> Column<'static, Message> {
@@ -99,13 +100,18 @@ where
 }
 
 pub fn build_key_behavior_dropdown(current_mode: KeyBehaviorMode) -> Dropdown<'static, &'static str, Message, Message> {
-    const KEY_BEHAVIORS: [&str; 2] = ["Click", "Hold"];
+    const KEY_BEHAVIORS: [&str; 7] = ["Click", "Hold", "Random", "Per Key", "Sequence", "Macro", "Script"];
     build_generic_dropdown(
         &KEY_BEHAVIORS,
         current_mode,
         |index| match index {
             0 => Message::UpdateKeyBehaviorMode(KeyBehaviorMode::Click),
             1 => Message::UpdateKeyBehaviorMode(KeyBehaviorMode::Hold),
+            2 => Message::UpdateKeyBehaviorMode(KeyBehaviorMode::Random),
+            3 => Message::UpdateKeyBehaviorMode(KeyBehaviorMode::PerKey),
+            4 => Message::UpdateKeyBehaviorMode(KeyBehaviorMode::Sequence),
+            5 => Message::UpdateKeyBehaviorMode(KeyBehaviorMode::Macro),
+            6 => Message::UpdateKeyBehaviorMode(KeyBehaviorMode::Script),
             _ => Message::Noop,
         },
     )
@@ -125,32 +131,48 @@ pub fn build_hold_behavior_dropdown(current_mode: HoldBehaviorMode) -> Dropdown<
 }
 
 pub fn build_modifier_behavior_dropdown(current_mode: ModifierBehaviorMode) -> Dropdown<'static, &'static str, Message, Message> {
-    const MODIFIER_BEHAVIOR_MODES: [&str; 2] = ["Click", "Hold"];
+    const MODIFIER_BEHAVIOR_MODES: [&str; 3] = ["Click", "Hold", "Latch"];
     build_generic_dropdown(
         &MODIFIER_BEHAVIOR_MODES,
         current_mode,
         |index| match index {
             0 => Message::UpdateModifierBehaviorMode(ModifierBehaviorMode::Click),
             1 => Message::UpdateModifierBehaviorMode(ModifierBehaviorMode::Hold),
+            2 => Message::UpdateModifierBehaviorMode(ModifierBehaviorMode::Latch),
             _ => Message::Noop,
         },
     )
 }
 
-pub fn format_hotkey_text(
-    ctrl: bool, 
-    alt: bool, 
-    shift: bool, 
-    super_key: bool, 
-    key: Option<&str>,
-) -> String {
+pub fn format_hotkey_text(modifiers: &HotkeyModifiers, key: Option<&str>) -> String {
     let mut parts = Vec::new();
-    if ctrl { parts.push("Ctrl"); }
-    if alt { parts.push("Alt"); }
-    if shift { parts.push("Shift"); }
-    if super_key { parts.push("Super"); }
-    if let Some(k) = key { parts.push(k); }
-    parts.join("+")
+    for ((set, side), name) in modifiers.sides().into_iter().zip(["Ctrl", "Alt", "Shift", "Super"]) {
+        if !set {
+            continue;
+        }
+        parts.push(match side {
+            ModifierSide::Either => name.to_string(),
+            ModifierSide::Left => format!("Left {}", name),
+            ModifierSide::Right => format!("Right {}", name),
+        });
+    }
+    if let Some(k) = key { parts.push(k.to_string()); }
+    let text = parts.join("+");
+    if modifiers.exact { format!("{} (exact)", text) } else { text }
+}
+
+// Matching options for the hotkey being captured.
+pub fn build_hotkey_match_options(temp_hotkey: &TempHotkeyState) -> Row<'static, Message> {
+    Row::new()
+        .push(
+            checkbox("Exact modifiers", temp_hotkey.modifiers.exact)
+                .on_toggle(Message::ToggleExactHotkey)
+        )
+        .push(
+            checkbox("Left/right modifiers", temp_hotkey.distinguish_sides)
+                .on_toggle(Message::ToggleHotkeySides)
+        )
+        .spacing(20)
 }
 
 pub fn build_start_button(is_running: bool) -> impl Into<Element<'static, Message>> {
@@ -165,12 +187,40 @@ pub fn build_start_button(is_running: bool) -> impl Into<Element<'static, Messag
         .class(class)
 }
 
+pub fn build_pause_button(is_paused: bool) -> impl Into<Element<'static, Message>> {
+    let label = if is_paused { "Resume" } else { "Pause" };
+    button::text(label)
+        .on_press(Message::TogglePaused)
+        .class(cosmic::theme::Button::Standard)
+}
+
+/// A warning when selected keys can trigger the global hotkey while the simulation runs.
+pub fn build_hotkey_overlap_warning(app_data: &AppData) -> Option<Element<'static, Message>> {
+    let overlaps = app_data.hotkey_overlaps();
+    if overlaps.is_empty() {
+        return None;
+    }
+    let keys = overlaps.iter().map(super::format_raw_key_for_display).collect::<Vec<_>>().join(", ");
+    Some(
+        Text::new(format!(
+            "Warning: a hotkey's key is among the selected keys ({}). Simulated presses are \
+             ignored, but holding its modifiers during a run can still trigger it.",
+            keys
+        ))
+        .size(14)
+        .into(),
+    )
+}
+
 pub fn build_selected_keys_text(keys: &[String]) -> Element<'static, Message> {
     let selected_count = keys.len();
     let keys_text = if keys.is_empty() {
         "No keys selected. Press 'Capture Keys' to begin.".to_string()
     } else {
-        keys.join(", ")
+        keys.iter()
+            .map(super::format_raw_key_for_display)
+            .collect::<Vec<_>>()
+            .join(", ")
     };
     
     // Create a owned string to avoid lifetime issues
@@ -193,3 +243,698 @@ pub fn build_selected_keys_text(keys: &[String]) -> Element<'static, Message> {
     .width(Length::Fill)
     .into()
 }
+
+pub fn build_key_weights_editor(app_data: &AppData) -> Element<'static, Message> {
+    let mut column = Column::new()
+        .push(Text::new("Key Weights:").size(16))
+        .spacing(5);
+
+    for key in &app_data.selected_keys {
+        let weight_key = key.clone();
+        column = column.push(
+            Row::new()
+                .push(Text::new(key.clone()).width(Length::Fill))
+                .push(
+                    TextInput::new("", app_data.key_weight(key).to_string())
+                        .on_input(move |value| Message::UpdateKeyWeight(weight_key.clone(), value))
+                        .padding(5)
+                        .width(Length::Fixed(60.0))
+                        .size(16)
+                )
+                .spacing(5)
+        );
+    }
+
+    cosmic::widget::container(column)
+        .width(Length::Fill)
+        .into()
+}
+
+pub fn build_random_controls(app_data: &AppData) -> Column<'static, Message> {
+    let seed_value = app_data.random_seed
+        .map(|seed| seed.to_string())
+        .unwrap_or_default();
+
+    Column::new()
+        .push(
+            checkbox("No repeat", app_data.random_no_repeat)
+                .on_toggle(Message::ToggleRandomNoRepeat)
+        )
+        .push(
+            Row::new()
+                .push(Text::new("Seed:").width(Length::Shrink))
+                .push(
+                    TextInput::new("Random", seed_value)
+                        .on_input(Message::UpdateRandomSeed)
+                        .padding(5)
+                        .width(Length::Fill)
+                        .size(16)
+                )
+                .spacing(5)
+        )
+        .spacing(5)
+}
+
+fn build_key_override_mode_dropdown(key: String, current_mode: KeyBehaviorMode) -> Dropdown<'static, &'static str, Message, Message> {
+    const KEY_OVERRIDE_MODES: [&str; 2] = ["Click", "Hold"];
+    let current_mode = if current_mode == KeyBehaviorMode::Hold {
+        KeyBehaviorMode::Hold
+    } else {
+        KeyBehaviorMode::Click
+    };
+    build_generic_dropdown(
+        &KEY_OVERRIDE_MODES,
+        current_mode,
+        move |index| match index {
+            0 => Message::UpdateKeyOverrideMode(key.clone(), KeyBehaviorMode::Click),
+            1 => Message::UpdateKeyOverrideMode(key.clone(), KeyBehaviorMode::Hold),
+            _ => Message::Noop,
+        },
+    )
+}
+
+pub fn build_key_overrides_editor(app_data: &AppData) -> Element<'static, Message> {
+    let mut column = Column::new()
+        .push(
+            Row::new()
+                .push(Text::new("Key").width(Length::Fill))
+                .push(Text::new("Mode").width(Length::Fixed(90.0)))
+                .push(Text::new("Every (ms)").width(Length::Fixed(70.0)))
+                .push(Text::new("Down (ms)").width(Length::Fixed(70.0)))
+                .spacing(5)
+        )
+        .spacing(5);
+
+    for key in &app_data.selected_keys {
+        let key_override = app_data.key_override(key);
+        let is_hold = key_override.mode == KeyBehaviorMode::Hold;
+        let interval_key = key.clone();
+        let duration_key = key.clone();
+
+        let mut interval_input = TextInput::new("", key_override.interval_ms.to_string())
+            .padding(5)
+            .width(Length::Fixed(70.0))
+            .size(16);
+        let mut duration_input = TextInput::new("", key_override.press_duration_ms.to_string())
+            .padding(5)
+            .width(Length::Fixed(70.0))
+            .size(16);
+        // Held keys stay down for the whole run, so timing doesn't apply.
+        if !is_hold {
+            interval_input = interval_input
+                .on_input(move |value| Message::UpdateKeyOverrideInterval(interval_key.clone(), value));
+            duration_input = duration_input
+                .on_input(move |value| Message::UpdateKeyOverridePressDuration(duration_key.clone(), value));
+        }
+
+        column = column.push(
+            Row::new()
+                .push(Text::new(key.clone()).width(Length::Fill))
+                .push(build_key_override_mode_dropdown(key.clone(), key_override.mode).width(Length::Fixed(90.0)))
+                .push(interval_input)
+                .push(duration_input)
+                .spacing(5)
+        );
+    }
+
+    cosmic::widget::container(column)
+        .width(Length::Fill)
+        .into()
+}
+
+pub fn build_sequence_delays_editor(app_data: &AppData) -> Element<'static, Message> {
+    let mut column = Column::new()
+        .push(Text::new("Delay after each step (ms):").size(16))
+        .spacing(5);
+
+    for (index, key) in app_data.selected_keys.iter().enumerate() {
+        column = column.push(
+            Row::new()
+                .push(Text::new(format!("{}. {}", index + 1, super::format_raw_key_for_display(key))).width(Length::Fill))
+                .push(
+                    TextInput::new("", app_data.step_delay(index).to_string())
+                        .on_input(move |value| Message::UpdateStepDelay(index, value))
+                        .padding(5)
+                        .width(Length::Fixed(60.0))
+                        .size(16)
+                )
+                .spacing(5)
+        );
+    }
+
+    cosmic::widget::container(column)
+        .width(Length::Fill)
+        .into()
+}
+
+pub fn build_macro_list(app_data: &AppData) -> Column<'static, Message> {
+    let mut column = Column::new()
+        .push(Text::new("Macros:"))
+        .spacing(5);
+
+    if app_data.macros.is_empty() {
+        column = column.push(Text::new("No macros yet.").size(14));
+    }
+
+    for m in &app_data.macros {
+        let is_active = app_data.active_macro.as_deref() == Some(m.name.as_str());
+        column = column.push(
+            button::text(format!("{} ({} steps)", m.name, m.steps.len()))
+                .on_press(Message::SelectMacro(m.name.clone()))
+                .class(if is_active { cosmic::theme::Button::Suggested } else { cosmic::theme::Button::Standard })
+                .width(Length::Fill)
+        );
+    }
+
+    column.push(
+        button::text("Save Keys as Macro")
+            .on_press(Message::SaveKeysAsMacro)
+            .class(cosmic::theme::Button::Text)
+    )
+}
+
+fn step_input(index: usize, field: StepField, value: String, width: Length) -> TextInput<'static, Message> {
+    TextInput::new("", value)
+        .on_input(move |value| Message::UpdateMacroStep(index, field, value))
+        .on_submit(|_| Message::CommitMacroStep)
+        .padding(5)
+        .width(width)
+        .size(14)
+}
+
+fn build_step_fields(index: usize, step: &MacroStep) -> Row<'static, Message> {
+    const MOUSE_BUTTONS: [&str; 3] = ["Left", "Middle", "Right"];
+    let row = Row::new().spacing(5);
+    match step {
+        MacroStep::KeyDown { key } | MacroStep::KeyUp { key } | MacroStep::Tap { key } => {
+            row.push(step_input(index, StepField::Key, key.clone(), Length::Fill))
+        }
+        MacroStep::Chord { keys } => row.push(step_input(index, StepField::Key, keys.join(" "), Length::Fill)),
+        MacroStep::MouseClick { button } => {
+            let selected = match button {
+                MouseButton::Left => 0,
+                MouseButton::Middle => 1,
+                MouseButton::Right => 2,
+            };
+            row.push(
+                Dropdown::new(&MOUSE_BUTTONS, Some(selected), move |choice| {
+                    Message::UpdateMacroStep(index, StepField::Key, MOUSE_BUTTONS[choice].to_string())
+                })
+                .width(Length::Fill)
+            )
+        }
+        MacroStep::MouseMove { dx, dy } | MacroStep::Scroll { dx, dy } => row
+            .push(step_input(index, StepField::Dx, dx.to_string(), Length::Fill))
+            .push(step_input(index, StepField::Dy, dy.to_string(), Length::Fill)),
+        MacroStep::Wait { ms } => row
+            .push(step_input(index, StepField::Ms, ms.to_string(), Length::Fill))
+            .push(Text::new("ms").size(14)),
+        MacroStep::Type { text, .. } => row.push(step_input(index, StepField::Text, text.clone(), Length::Fill)),
+        // Nested steps are edited in the macro file
+        MacroStep::Repeat { count, steps } => row
+            .push(step_input(index, StepField::Count, count.to_string(), Length::Fixed(45.0)))
+            .push(Text::new(format!("× {} steps", steps.len())).size(14)),
+    }
+}
+
+/// Steps of the active macro, with a drag handle, inline fields and actions for the selected step.
+pub fn build_macro_timeline(app_data: &AppData, is_running: bool) -> Column<'static, Message> {
+    let Some(active) = app_data.active_macro() else {
+        return Column::new();
+    };
+    let timeline = &app_data.timeline;
+
+    let mut steps = Column::new().spacing(2);
+    for (index, step) in active.steps.iter().enumerate() {
+        let is_selected = timeline.selected == Some(index);
+        let is_drop_target = timeline.dragging.is_some_and(|from| from != index) && timeline.drop_target == Some(index);
+
+        let handle = MouseArea::new(
+            Container::new(Text::new(if is_drop_target { "→" } else { "≡" }).size(16))
+                .padding([0, 4])
+        )
+        .on_press(Message::StartMacroStepDrag(index));
+
+        steps = steps.push(
+            MouseArea::new(
+                Row::new()
+                    .push(handle)
+                    .push(
+                        button::text(format!("{}. {}", index + 1, step.kind()))
+                            .on_press(Message::SelectMacroStep(index))
+                            .class(if is_selected { cosmic::theme::Button::Suggested } else { cosmic::theme::Button::Text })
+                            .width(Length::Fixed(95.0))
+                    )
+                    .push(build_step_fields(index, step))
+                    .spacing(3)
+            )
+            .on_mouse_enter(Message::HoverMacroStep(index))
+        );
+    }
+    if active.steps.is_empty() {
+        steps = steps.push(Text::new("No steps yet.").size(14));
+    }
+
+    let mut column = Column::new()
+        .push(Text::new(format!("Steps of {}:", active.name)))
+        .push(
+            MouseArea::new(scrollable(steps).height(Length::Fixed(240.0)))
+                .on_release(Message::DropMacroStep)
+                .on_mouse_exit(Message::CancelMacroStepDrag)
+        )
+        .push(
+            Row::new()
+                .push(Text::new("Insert:").width(Length::Shrink))
+                .push(Dropdown::new(&STEP_KINDS, None, Message::InsertMacroStep).width(Length::Fill))
+                .spacing(5)
+        )
+        .spacing(5);
+
+    if let Some(index) = timeline.selected.filter(|&index| index < active.steps.len()) {
+        let last = active.steps.len() - 1;
+        column = column.push(
+            Row::new()
+                .push(
+                    button::text("Up")
+                        .on_press_maybe((index > 0).then(|| Message::MoveMacroStep(index, index - 1)))
+                        .class(cosmic::theme::Button::Text)
+                )
+                .push(
+                    button::text("Down")
+                        .on_press_maybe((index < last).then(|| Message::MoveMacroStep(index, index + 1)))
+                        .class(cosmic::theme::Button::Text)
+                )
+                .push(
+                    button::text("Delete")
+                        .on_press(Message::DeleteMacroStep(index))
+                        .class(cosmic::theme::Button::Destructive)
+                )
+                .push(
+                    button::text("Play from here")
+                        .on_press_maybe((!is_running).then_some(Message::PlayMacroFromStep(index)))
+                        .class(cosmic::theme::Button::Suggested)
+                )
+                .spacing(5)
+        );
+    }
+    column
+}
+
+pub fn build_macro_file_controls(app_data: &AppData) -> Column<'static, Message> {
+    let has_path = !app_data.macro_file.trim().is_empty();
+
+    let mut column = Column::new()
+        .push(
+            TextInput::new("macro.txt", app_data.macro_file.clone())
+                .on_input(Message::UpdateMacroFile)
+                .padding(5)
+                .width(Length::Fill)
+                .size(16)
+        )
+        .push(
+            Row::new()
+                .push(
+                    button::text("Load")
+                        .on_press_maybe(has_path.then_some(Message::LoadMacroFile))
+                        .class(cosmic::theme::Button::Standard)
+                )
+                .push(
+                    button::text("Save")
+                        .on_press_maybe((has_path && app_data.active_macro().is_some()).then_some(Message::SaveMacroFile))
+                        .class(cosmic::theme::Button::Standard)
+                )
+                .spacing(5)
+        )
+        .push(
+            Row::new()
+                .push(
+                    button::text("Import evemu")
+                        .on_press_maybe(has_path.then_some(Message::ImportEvemuFile))
+                        .class(cosmic::theme::Button::Text)
+                )
+                .push(
+                    button::text("Export evemu")
+                        .on_press_maybe((has_path && app_data.active_macro().is_some()).then_some(Message::ExportEvemuFile))
+                        .class(cosmic::theme::Button::Text)
+                )
+                .push(
+                    button::text("Import xdotool")
+                        .on_press_maybe(has_path.then_some(Message::ImportXdotoolFile))
+                        .class(cosmic::theme::Button::Text)
+                )
+                .spacing(5)
+        )
+        .spacing(5);
+
+    if let Some(error) = &app_data.macro_file_error {
+        column = column.push(Text::new(error.clone()).size(14));
+    }
+    column
+}
+
+pub fn build_keyboard_layout_controls(app_data: &AppData) -> Column<'static, Message> {
+    Column::new()
+        .push(Text::new("Keyboard layout for typed text:"))
+        .push(
+            Row::new()
+                .push(
+                    TextInput::new("System default", app_data.keyboard_layout.layout.clone())
+                        .on_input(Message::UpdateKeyboardLayout)
+                        .padding(5)
+                        .width(Length::Fill)
+                        .size(16)
+                )
+                .push(
+                    TextInput::new("Variant", app_data.keyboard_layout.variant.clone())
+                        .on_input(Message::UpdateKeyboardVariant)
+                        .padding(5)
+                        .width(Length::Fill)
+                        .size(16)
+                )
+                .spacing(5)
+        )
+        .push(Text::new("Characters not on the layout:"))
+        .push(build_unicode_fallback_dropdown(app_data.unicode_fallback).width(Length::Fill))
+        .spacing(5)
+}
+
+fn build_unicode_fallback_dropdown(current: UnicodeFallback) -> Dropdown<'static, &'static str, Message, Message> {
+    const UNICODE_FALLBACKS: [&str; 3] = ["Skip", "Ctrl+Shift+U", "Remap Key"];
+    build_generic_dropdown(
+        &UNICODE_FALLBACKS,
+        current,
+        |index| match index {
+            0 => Message::UpdateUnicodeFallback(UnicodeFallback::Skip),
+            1 => Message::UpdateUnicodeFallback(UnicodeFallback::HexEntry),
+            2 => Message::UpdateUnicodeFallback(UnicodeFallback::RemapKey),
+            _ => Message::Noop,
+        },
+    )
+}
+
+// A labelled number input of the typing profile; blank shows the placeholder
+fn typing_number_row(label: &str, placeholder: &str, value: String, on_input: fn(String) -> Message) -> Row<'static, Message> {
+    Row::new()
+        .push(Text::new(label.to_string()).width(Length::Shrink))
+        .push(
+            TextInput::new(placeholder.to_string(), value)
+                .on_input(on_input)
+                .padding(5)
+                .width(Length::Fill)
+                .size(16)
+        )
+        .spacing(5)
+}
+
+pub fn build_typing_profile_controls(app_data: &AppData) -> Column<'static, Message> {
+    let profile = app_data.typing_profile;
+    let mut column = Column::new()
+        .push(checkbox("Humanize typing", profile.humanize).on_toggle(Message::ToggleHumanizeTyping))
+        .spacing(5);
+
+    if !profile.humanize {
+        return column;
+    }
+
+    let burst_value = if profile.burst_length == 0 {
+        String::new()
+    } else {
+        profile.burst_length.to_string()
+    };
+    let seed_value = profile.seed
+        .map(|seed| seed.to_string())
+        .unwrap_or_default();
+
+    column = column
+        .push(typing_number_row("Avg delay (ms):", "120", profile.delay_ms.to_string(), Message::UpdateTypingDelay))
+        .push(Text::new(format!("Variation: {:.0}%", profile.jitter * 100.0)).size(14))
+        .push(Slider::new(0.0..=1.0, profile.jitter, Message::SetTypingJitter).step(0.05))
+        .push(Text::new(format!("Letter pair variation: ±{:.0}%", profile.bigram_variation * 100.0)).size(14))
+        .push(Slider::new(0.0..=0.5, profile.bigram_variation, Message::SetBigramVariation).step(0.05))
+        .push(typing_number_row("After punctuation (ms):", "0", profile.punctuation_pause_ms.to_string(), Message::UpdatePunctuationPause))
+        .push(typing_number_row("After space (ms):", "0", profile.space_pause_ms.to_string(), Message::UpdateSpacePause))
+        .push(typing_number_row("Burst length:", "Off", burst_value, Message::UpdateBurstLength))
+        .push(typing_number_row("Seed:", "Random", seed_value, Message::UpdateTypingSeed));
+    column
+}
+
+pub fn build_type_text_controls(app_data: &AppData) -> Column<'static, Message> {
+    Column::new()
+        .push(
+            TextInput::new("Text to type", app_data.type_text_input.clone())
+                .on_input(Message::UpdateTypeText)
+                .padding(5)
+                .width(Length::Fill)
+                .size(16)
+        )
+        .push(
+            button::text("Save Text as Macro")
+                .on_press_maybe((!app_data.type_text_input.is_empty()).then_some(Message::SaveTextAsMacro))
+                .class(cosmic::theme::Button::Text)
+        )
+        .spacing(5)
+}
+
+pub fn build_script_controls(app_data: &AppData) -> Column<'static, Message> {
+    let mut column = Column::new()
+        .push(Text::new("Script file:"))
+        .push(
+            TextInput::new("script.rhai", app_data.script_file.clone())
+                .on_input(Message::UpdateScriptFile)
+                .padding(5)
+                .width(Length::Fill)
+                .size(16)
+        )
+        .push(
+            button::text("Check")
+                .on_press_maybe((!app_data.script_file.trim().is_empty()).then_some(Message::CheckScript))
+                .class(cosmic::theme::Button::Standard)
+        )
+        .spacing(5);
+
+    if let Some(status) = &app_data.script_status {
+        column = column.push(Text::new(status.clone()).size(14));
+    }
+    column
+}
+
+pub fn build_playback_controls(app_data: &AppData) -> Column<'static, Message> {
+    let playback = app_data.macro_playback;
+    let max_idle_value = playback.max_idle_ms
+        .map(|ms| ms.to_string())
+        .unwrap_or_default();
+    let loop_value = if playback.loop_count == 0 {
+        String::new()
+    } else {
+        playback.loop_count.to_string()
+    };
+
+    Column::new()
+        .push(Text::new(format!("Speed: {}x", playback.clamped_speed())).size(14))
+        .push(
+            Slider::new(
+                MIN_PLAYBACK_SPEED..=MAX_PLAYBACK_SPEED,
+                playback.clamped_speed(),
+                Message::SetPlaybackSpeed
+            )
+            .step(0.25)
+        )
+        .push(
+            Row::new()
+                .push(Text::new("Max idle (ms):").width(Length::Shrink))
+                .push(
+                    TextInput::new("Off", max_idle_value)
+                        .on_input(Message::UpdateMaxIdle)
+                        .padding(5)
+                        .width(Length::Fill)
+                        .size(16)
+                )
+                .spacing(5)
+        )
+        .push(
+            Row::new()
+                .push(Text::new("Loops:").width(Length::Shrink))
+                .push(
+                    TextInput::new("Forever", loop_value)
+                        .on_input(Message::UpdateLoopCount)
+                        .padding(5)
+                        .width(Length::Fill)
+                        .size(16)
+                )
+                .spacing(5)
+        )
+        .spacing(5)
+}
+
+pub fn build_trace_controls(app_data: &AppData) -> Column<'static, Message> {
+    Column::new()
+        .push(Text::new("Trace emitted events to (evemu):"))
+        .push(
+            TextInput::new("Off", app_data.evemu_trace_file.clone())
+                .on_input(Message::UpdateTraceFile)
+                .padding(5)
+                .width(Length::Fill)
+                .size(16)
+        )
+        .spacing(5)
+}
+
+pub fn build_hotkey_backend_controls(current: HotkeyBackend) -> Column<'static, Message> {
+    const HOTKEY_BACKENDS: [&str; 3] = ["X11", "evdev", "Portal"];
+    Column::new()
+        .push(Text::new("Hotkey listener:"))
+        .push(
+            build_generic_dropdown(
+                &HOTKEY_BACKENDS,
+                current,
+                |index| match index {
+                    0 => Message::UpdateHotkeyBackend(HotkeyBackend::DeviceQuery),
+                    1 => Message::UpdateHotkeyBackend(HotkeyBackend::Evdev),
+                    2 => Message::UpdateHotkeyBackend(HotkeyBackend::Portal),
+                    _ => Message::Noop,
+                },
+            )
+            .width(Length::Fill)
+        )
+        .spacing(5)
+}
+
+pub fn build_hotkey_activation_controls(activation: HotkeyActivation) -> Column<'static, Message> {
+    const HOTKEY_ACTIVATIONS: [&str; 3] = ["Toggle", "Hold", "Hold + Release Delay"];
+    let mut column = Column::new()
+        .push(Text::new("Hotkey activation:"))
+        .push(
+            build_generic_dropdown(
+                &HOTKEY_ACTIVATIONS,
+                activation,
+                |index| match index {
+                    0 => Message::UpdateHotkeyActivation(HotkeyActivation::Toggle),
+                    1 => Message::UpdateHotkeyActivation(HotkeyActivation::Hold),
+                    2 => Message::UpdateHotkeyActivation(HotkeyActivation::HoldWithDelay {
+                        release_delay_ms: DEFAULT_RELEASE_DELAY_MS,
+                    }),
+                    _ => Message::Noop,
+                },
+            )
+            .width(Length::Fill)
+        )
+        .spacing(5);
+
+    if let HotkeyActivation::HoldWithDelay { release_delay_ms } = activation {
+        column = column.push(
+            Row::new()
+                .push(Text::new("Stop after release (ms):").width(Length::Shrink))
+                .push(
+                    TextInput::new("0", release_delay_ms.to_string())
+                        .on_input(Message::UpdateReleaseDelay)
+                        .padding(5)
+                        .width(Length::Fill)
+                        .size(16)
+                )
+                .spacing(5)
+        );
+    }
+    column
+}
+
+pub fn build_action_hotkey_controls(action: HotkeyAction, keybind: Option<&GlobalHotkey>) -> Row<'static, Message> {
+    Row::new()
+        .push(Space::with_width(Length::Fill))
+        .push(action_hotkey_buttons(format!("{} Hotkey", action), action, keybind))
+}
+
+// A button that captures the hotkey of `action`, with a Clear button when it's set.
+fn action_hotkey_buttons(label: String, action: HotkeyAction, keybind: Option<&GlobalHotkey>) -> Row<'static, Message> {
+    let hotkey_text = keybind
+        .map(|hotkey| format_hotkey_text(&hotkey.modifiers, Some(&hotkey.key)))
+        .unwrap_or_else(|| "Off".to_string());
+
+    let mut row = Row::new()
+        .push(
+            button::text(format!("{}: {}", label, hotkey_text))
+                .on_press(Message::CaptureActionHotkey(action.clone()))
+                .class(cosmic::theme::Button::Text)
+        );
+    if keybind.is_some() {
+        row = row.push(
+            button::text("Clear")
+                .on_press(Message::ClearActionHotkey(action))
+                .class(cosmic::theme::Button::Text)
+        );
+    }
+    row
+}
+
+pub fn build_profile_controls(app_data: &AppData) -> Column<'static, Message> {
+    let mut column = Column::new()
+        .push(Text::new("Profiles:"))
+        .spacing(5);
+
+    if app_data.profiles.is_empty() {
+        column = column.push(Text::new("No profiles yet.").size(14));
+    }
+
+    for profile in &app_data.profiles {
+        let is_active = app_data.active_profile.as_deref() == Some(profile.name.as_str());
+        let action = HotkeyAction::Profile(profile.name.clone());
+        let keybind = app_data.action_keybind(&action);
+        column = column.push(
+            Row::new()
+                .push(
+                    button::text(profile.name.clone())
+                        .on_press(Message::LoadProfile(profile.name.clone()))
+                        .class(if is_active { cosmic::theme::Button::Suggested } else { cosmic::theme::Button::Standard })
+                        .width(Length::Fill)
+                )
+                .push(action_hotkey_buttons("Hotkey".to_string(), action, keybind))
+                .push(
+                    button::text("Delete")
+                        .on_press(Message::DeleteProfile(profile.name.clone()))
+                        .class(cosmic::theme::Button::Text)
+                )
+                .spacing(5)
+        );
+    }
+
+    column.push(
+        Row::new()
+            .push(
+                TextInput::new("Profile name", app_data.profile_name_input.clone())
+                    .on_input(Message::UpdateProfileName)
+                    .padding(5)
+                    .width(Length::Fill)
+                    .size(16)
+            )
+            .push(
+                button::text("Save Settings as Profile")
+                    .on_press_maybe((!app_data.profile_name_input.trim().is_empty()).then_some(Message::SaveProfile))
+                    .class(cosmic::theme::Button::Text)
+            )
+            .spacing(5)
+    )
+}
+
+pub fn build_record_controls(recording: RecordingState, hotkey_text: &str) -> Column<'static, Message> {
+    let (label, status) = match recording {
+        RecordingState::Off => ("Record Macro", None),
+        RecordingState::Armed => ("Cancel Recording", Some(format!("Press {} to start recording.", hotkey_text))),
+        RecordingState::Recording => ("Stop Recording", Some(format!("Recording... press {} to stop.", hotkey_text))),
+    };
+
+    let mut column = Column::new()
+        .push(
+            button::text(label)
+                .on_press(Message::ToggleRecording)
+                .class(if recording == RecordingState::Off {
+                    cosmic::theme::Button::Standard
+                } else {
+                    cosmic::theme::Button::Destructive
+                })
+        )
+        .spacing(5);
+
+    if let Some(status) = status {
+        column = column.push(Text::new(status).size(14));
+    }
+    column
+}
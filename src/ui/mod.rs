@@ -25,5 +25,7 @@ pub fn default_window_settings() -> Settings {
 }
 
 pub fn format_raw_key_for_display(key: &String) -> String {
-    key.clone()
+    key.parse::<crate::config::KeyChord>()
+        .map(|chord| chord.to_string())
+        .unwrap_or_else(|_| key.clone())
 }
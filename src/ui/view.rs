@@ -5,12 +5,13 @@ use cosmic::{
 };
 use crate::{
     app::Message,
-    config::{AppData, KeyBehaviorMode, HoldBehaviorMode},
+    config::{AppData, KeyBehaviorMode, HoldBehaviorMode, HotkeyTarget, HOTKEY_ACTIONS},
     ui::components,
 };
 
 pub struct View<'a> {
     is_running: bool,
+    is_paused: bool,
     interval: f64,
     app_data_guard: std::sync::MutexGuard<'a, AppData>,
     is_capturing: bool,
@@ -21,6 +22,7 @@ pub struct View<'a> {
 impl<'a> View<'a> {
     pub fn new(
         is_running: bool,
+        is_paused: bool,
         interval: f64,
         app_data_guard: std::sync::MutexGuard<'a, AppData>,
         is_capturing: bool,
@@ -31,6 +33,7 @@ impl<'a> View<'a> {
             is_running, is_capturing, is_capturing_hotkey);
         Self {
             is_running,
+            is_paused,
             interval,
             app_data_guard,
             is_capturing,
@@ -126,20 +129,38 @@ impl<'a> View<'a> {
             );
         } else {
             column = column
-                .push(components::build_selected_keys_text(&self.app_data_guard.selected_keys))
-                .push(Space::with_height(Length::Fill));
+                .push(components::build_selected_keys_text(&self.app_data_guard.selected_keys));
+            if let Some(warning) = components::build_hotkey_overlap_warning(&self.app_data_guard) {
+                column = column.push(warning);
+            }
+            if self.app_data_guard.key_behavior == KeyBehaviorMode::Random
+                && !self.app_data_guard.selected_keys.is_empty()
+            {
+                column = column.push(components::build_key_weights_editor(&self.app_data_guard));
+            } else if self.app_data_guard.key_behavior == KeyBehaviorMode::PerKey
+                && !self.app_data_guard.selected_keys.is_empty()
+            {
+                column = column.push(components::build_key_overrides_editor(&self.app_data_guard));
+            } else if self.app_data_guard.key_behavior == KeyBehaviorMode::Sequence
+                && !self.app_data_guard.selected_keys.is_empty()
+            {
+                column = column.push(components::build_sequence_delays_editor(&self.app_data_guard));
+            }
+            column = column.push(Space::with_height(Length::Fill));
         }
         if self.is_capturing_hotkey {
             let hotkey_text = components::format_hotkey_text(
-                self.app_data_guard.temp_hotkey.modifiers.ctrl,
-                self.app_data_guard.temp_hotkey.modifiers.alt,
-                self.app_data_guard.temp_hotkey.modifiers.shift,
-                self.app_data_guard.temp_hotkey.modifiers.super_key,
+                &self.app_data_guard.temp_hotkey.saved_modifiers(),
                 self.app_data_guard.temp_hotkey.key.as_deref(),
             );
             
+            let label = match &self.app_data_guard.temp_hotkey.target {
+                HotkeyTarget::Simulation => "New Global Hotkey".to_string(),
+                HotkeyTarget::Action(action) => format!("New {} Hotkey", action),
+            };
             column = column
-                .push(text::body(format!("New Global Hotkey: {}", hotkey_text)).size(16))
+                .push(text::body(format!("{}: {}", label, hotkey_text)).size(16))
+                .push(components::build_hotkey_match_options(&self.app_data_guard.temp_hotkey))
                 .push(
                     Row::new()
                         .push(
@@ -157,11 +178,16 @@ impl<'a> View<'a> {
         }
 
         if !self.is_capturing && !self.is_capturing_hotkey {
-            column = column.push(
-                Row::new()
-                    .push(Container::new(components::build_start_button(self.is_running)))
-                    .push(Space::with_width(Length::Fill))
-            );
+            let mut controls = Row::new()
+                .push(Container::new(components::build_start_button(self.is_running)))
+                .spacing(10);
+            if self.is_running {
+                controls = controls.push(Container::new(components::build_pause_button(self.is_paused)));
+                if self.is_paused {
+                    controls = controls.push(text::body("Paused"));
+                }
+            }
+            column = column.push(controls.push(Space::with_width(Length::Fill)));
         }
 
         column
@@ -197,8 +223,42 @@ impl<'a> View<'a> {
                     .spacing(5)
             );
             column = column.push(components::interval_controls(self.interval, &self.app_data_guard));
+        } else if self.app_data_guard.key_behavior == KeyBehaviorMode::Random {
+            column = column.push(components::build_random_controls(&self.app_data_guard));
+            column = column.push(components::interval_controls(self.interval, &self.app_data_guard));
+        } else if self.app_data_guard.key_behavior == KeyBehaviorMode::PerKey {
+            // New keys start as clicks at the global interval.
+            column = column.push(text::body("Default interval for keys without their own settings:"));
+            column = column.push(components::interval_controls(self.interval, &self.app_data_guard));
+        } else if self.app_data_guard.key_behavior == KeyBehaviorMode::Sequence {
+            column = column.push(components::interval_controls(self.interval, &self.app_data_guard));
+        } else if self.app_data_guard.key_behavior == KeyBehaviorMode::Macro {
+            column = column.push(components::build_macro_list(&self.app_data_guard));
+            column = column.push(components::build_macro_timeline(&self.app_data_guard, self.is_running));
+            column = column.push(components::build_type_text_controls(&self.app_data_guard));
+            column = column.push(components::build_macro_file_controls(&self.app_data_guard));
+            column = column.push(components::build_keyboard_layout_controls(&self.app_data_guard));
+            column = column.push(components::build_typing_profile_controls(&self.app_data_guard));
+            column = column.push(components::build_playback_controls(&self.app_data_guard));
+            column = column.push(components::build_record_controls(
+                self.app_data_guard.recording,
+                &components::format_hotkey_text(
+                    &self.app_data_guard.global_keybind.modifiers,
+                    Some(&self.app_data_guard.global_keybind.key),
+                ),
+            ));
+            column = column.push(components::interval_controls(self.interval, &self.app_data_guard));
+        } else if self.app_data_guard.key_behavior == KeyBehaviorMode::Script {
+            column = column.push(components::build_script_controls(&self.app_data_guard));
+            column = column.push(components::build_keyboard_layout_controls(&self.app_data_guard));
+            column = column.push(components::build_typing_profile_controls(&self.app_data_guard));
+            column = column.push(components::interval_controls(self.interval, &self.app_data_guard));
         }
         
+        column = column.push(components::build_trace_controls(&self.app_data_guard));
+        column = column.push(components::build_hotkey_backend_controls(self.app_data_guard.hotkey_backend));
+        column = column.push(components::build_hotkey_activation_controls(self.app_data_guard.global_keybind.activation));
+        column = column.push(components::build_profile_controls(&self.app_data_guard));
         column = column.push(Space::with_height(Length::Fill));
         if !self.is_capturing_hotkey {
             column = column.push(
@@ -206,16 +266,17 @@ impl<'a> View<'a> {
                     .push(Space::with_width(Length::Fill))
                     .push(
                         button::text(format!("Global Hotkey: {}", components::format_hotkey_text(
-                            self.app_data_guard.global_keybind.modifiers.ctrl,
-                            self.app_data_guard.global_keybind.modifiers.alt,
-                            self.app_data_guard.global_keybind.modifiers.shift,
-                            self.app_data_guard.global_keybind.modifiers.super_key,
+                            &self.app_data_guard.global_keybind.modifiers,
                             Some(&self.app_data_guard.global_keybind.key)
                         )))
                             .on_press(Message::CaptureGlobalHotkey)
                             .class(cosmic::theme::Button::Text)
                     )
             );
+            for action in HOTKEY_ACTIONS {
+                let keybind = self.app_data_guard.action_keybind(&action);
+                column = column.push(components::build_action_hotkey_controls(action, keybind));
+            }
         }
         
         column
@@ -0,0 +1,339 @@
+//! Global hotkeys read straight from /dev/input, so they fire whatever window has focus,
+//! pure Wayland ones included. Every device with keys is read on its own thread, which
+//! blocks until the device has input or the listener stops, and the listener tracks which
+//! keys are down across all of them, modifiers included.
+//! Needs read access to the input devices (usually the `input` group).
+
+use std::{
+    collections::HashMap,
+    io,
+    os::fd::{AsRawFd, FromRawFd, OwnedFd, RawFd},
+    path::PathBuf,
+    sync::Arc,
+    thread,
+    time::{Duration, Instant},
+};
+
+use smol::channel;
+
+use evdev_rs::{
+    enums::{EventCode, EventType, EV_KEY},
+    Device,
+    DeviceWrapper,
+    ReadFlag,
+    ReadStatus,
+};
+
+use crate::{
+    config::{GlobalHotkey, HotkeyAction, HotkeyModifiers, HotkeySettings},
+    constants::EVDEV_RESCAN_MS,
+    recorder::{input_device_paths, is_own_device, open_input_device},
+    utils::key_utils::{evdev_name_to_evkey, hotkey_keycode, keycode_to_evkey, normalize_key, KEY_MAPPINGS},
+};
+
+use super::{
+    hotkey::{fire_on_press, next_event, set_listener_held, HotkeyTrigger},
+    watch,
+};
+
+enum DeviceEvent {
+    Key { device: usize, key: EV_KEY, pressed: bool },
+    Closed { device: usize },
+}
+
+/// The evdev key of a hotkey, from its `selected_keys` name or a raw name like "KEY_F13".
+pub(crate) fn hotkey_evkey(hotkey: &GlobalHotkey) -> Option<EV_KEY> {
+    let key = hotkey.key.trim();
+    evdev_name_to_evkey(key).or_else(|| KEY_MAPPINGS.get(normalize_key(key).as_str()).map(|&(_, ev_key)| ev_key))
+}
+
+/// Whether `key` is down and the modifiers held match `modifiers`.
+pub(crate) fn is_evdev_hotkey_active(down: &[EV_KEY], key: EV_KEY, modifiers: &HotkeyModifiers) -> bool {
+    // The hotkey itself only counts as a held modifier the hotkey also requires
+    let held = |set: bool, left: EV_KEY, right: EV_KEY| {
+        let is_down = |modifier: EV_KEY| down.contains(&modifier) && (set || modifier != key);
+        (is_down(left), is_down(right))
+    };
+    down.contains(&key)
+        && modifiers.matches([
+            held(modifiers.ctrl, EV_KEY::KEY_LEFTCTRL, EV_KEY::KEY_RIGHTCTRL),
+            held(modifiers.alt, EV_KEY::KEY_LEFTALT, EV_KEY::KEY_RIGHTALT),
+            held(modifiers.shift, EV_KEY::KEY_LEFTSHIFT, EV_KEY::KEY_RIGHTSHIFT),
+            held(modifiers.super_key, EV_KEY::KEY_LEFTMETA, EV_KEY::KEY_RIGHTMETA),
+        ])
+}
+
+// Wakes the readers of a listener once it returns, whichever way it does: dropping the write
+// end of the pipe hangs up the read end they wait on next to their device
+struct StopReaders {
+    hang_up: Arc<OwnedFd>,
+    _write: OwnedFd,
+}
+
+impl StopReaders {
+    fn new() -> io::Result<Self> {
+        let mut fds = [0; 2];
+        // SAFETY: pipe2 fills `fds` with two new descriptors, owned by nothing else
+        if unsafe { libc::pipe2(fds.as_mut_ptr(), libc::O_CLOEXEC) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let (read, write) = unsafe { (OwnedFd::from_raw_fd(fds[0]), OwnedFd::from_raw_fd(fds[1])) };
+        Ok(Self { hang_up: Arc::new(read), _write: write })
+    }
+}
+
+// Blocks until `device` has input or `stop` hangs up; false once the reader should stop
+fn wait_for_input(device: RawFd, stop: RawFd) -> bool {
+    let mut fds = [
+        libc::pollfd { fd: device, events: libc::POLLIN, revents: 0 },
+        libc::pollfd { fd: stop, events: libc::POLLIN, revents: 0 },
+    ];
+    loop {
+        // SAFETY: `fds` outlives the call and both descriptors stay open during it
+        let ready = unsafe { libc::poll(fds.as_mut_ptr(), fds.len() as libc::nfds_t, -1) };
+        if ready >= 0 {
+            return fds[1].revents == 0;
+        }
+        if io::Error::last_os_error().kind() != io::ErrorKind::Interrupted {
+            return false;
+        }
+    }
+}
+
+// The device is non-blocking so every event libevdev buffered is read before waiting again
+fn read_keys(device: Device, id: usize, events: channel::Sender<DeviceEvent>, stop: Arc<OwnedFd>) {
+    let mut flag = ReadFlag::NORMAL;
+    loop {
+        match device.next_event(flag) {
+            Ok((status, event)) => {
+                // After dropped events libevdev replays the current key state in sync mode
+                flag = if matches!(status, ReadStatus::Sync) { ReadFlag::SYNC } else { ReadFlag::NORMAL };
+                if let EventCode::EV_KEY(key) = event.event_code {
+                    // Value 2 is autorepeat of a key already down
+                    if event.value != 2
+                        && events.send_blocking(DeviceEvent::Key { device: id, key, pressed: event.value == 1 }).is_err()
+                    {
+                        break;
+                    }
+                }
+            }
+            // The end of a sync replay, or nothing left to read
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => {
+                if flag == ReadFlag::SYNC {
+                    flag = ReadFlag::NORMAL;
+                } else if !wait_for_input(device.file().as_raw_fd(), stop.as_raw_fd()) {
+                    break;
+                }
+            }
+            Err(e) => {
+                log::warn!("Stopped reading hotkeys from {}: {}", device.name().unwrap_or("input device"), e);
+                let _ = events.send_blocking(DeviceEvent::Closed { device: id });
+                break;
+            }
+        }
+    }
+}
+
+fn spawn_reader(device: Device, id: usize, events: channel::Sender<DeviceEvent>, stop: &StopReaders) -> thread::JoinHandle<()> {
+    let stop = Arc::clone(&stop.hang_up);
+    thread::spawn(move || read_keys(device, id, events, stop))
+}
+
+// Starts reading devices with keys that aren't read yet; returns how many are read now
+fn open_new_devices(
+    opened: &mut HashMap<PathBuf, usize>,
+    next_id: &mut usize,
+    events: &channel::Sender<DeviceEvent>,
+    stop: &StopReaders,
+) -> usize {
+    let paths = match input_device_paths() {
+        Ok(paths) => paths,
+        Err(e) => {
+            log::error!("Failed to list /dev/input: {}", e);
+            return opened.len();
+        }
+    };
+
+    for path in paths.into_iter().filter(|path| !opened.contains_key(path)) {
+        match open_input_device(&path) {
+            Ok(device) if device.has_event_type(&EventType::EV_KEY) && !is_own_device(&device) => {
+                log::info!("Reading hotkeys from {} ({})", device.name().unwrap_or("unnamed"), path.display());
+                let id = *next_id;
+                *next_id += 1;
+                spawn_reader(device, id, events.clone(), stop);
+                opened.insert(path, id);
+            }
+            Ok(_) => {}
+            Err(e) => log::debug!("Skipping {}: {}", path.display(), e),
+        }
+    }
+    opened.len()
+}
+
+/// Listens until the hotkey settings change.
+pub(super) fn listen_evdev(
+    settings: &HotkeySettings,
+    changes: &mut watch::Receiver<HotkeySettings>,
+    trigger: &mut HotkeyTrigger<'_>,
+    on_action: &dyn Fn(HotkeyAction),
+) {
+    let hotkey = hotkey_evkey(&settings.hotkey).or_else(|| keycode_to_evkey(hotkey_keycode(&settings.hotkey)));
+    let actions: Vec<(HotkeyAction, EV_KEY, HotkeyModifiers)> = settings.actions
+        .iter()
+        .filter_map(|(action, hotkey)| hotkey_evkey(hotkey).map(|key| (action.clone(), key, hotkey.modifiers)))
+        .collect();
+
+    let stop = match StopReaders::new() {
+        Ok(stop) => stop,
+        Err(e) => {
+            log::error!("Failed to create the evdev hotkey stop pipe: {}", e);
+            smol::block_on(changes.changed());
+            return;
+        }
+    };
+    let (sender, events) = channel::unbounded();
+    let rescan_interval = Duration::from_millis(EVDEV_RESCAN_MS);
+    let mut opened: HashMap<PathBuf, usize> = HashMap::new();
+    let mut next_id = 0;
+    let mut last_scan: Option<Instant> = None;
+    // Keys down per device, so a key held on two keyboards stays down until both release it
+    let mut down: Vec<(usize, EV_KEY)> = Vec::new();
+    set_listener_held(0);
+    let mut actions_pressed = vec![false; actions.len()];
+
+    loop {
+        if last_scan.is_none_or(|scan| scan.elapsed() >= rescan_interval) {
+            let count = open_new_devices(&mut opened, &mut next_id, &sender, &stop);
+            if count == 0 && last_scan.is_none() {
+                log::error!("No readable keyboard in /dev/input for hotkeys (is the user in the input group?)");
+            }
+            last_scan = Some(Instant::now());
+        }
+
+        let timeout = trigger.timeout().map_or(rescan_interval, |due| due.min(rescan_interval));
+        match next_event(&events, changes, Some(timeout)) {
+            Some(DeviceEvent::Key { device, key, pressed }) => {
+                down.retain(|&entry| entry != (device, key));
+                if pressed {
+                    down.push((device, key));
+                }
+                // Before the actions fire, so a paste waits for this very press to end
+                set_listener_held(down.len());
+            }
+            Some(DeviceEvent::Closed { device }) => {
+                down.retain(|&(id, _)| id != device);
+                set_listener_held(down.len());
+                opened.retain(|_, id| *id != device);
+                continue;
+            }
+            None if changes.has_changed() => return,
+            None => {
+                trigger.fire_due();
+                continue;
+            }
+        }
+
+        let keys: Vec<EV_KEY> = down.iter().map(|&(_, key)| key).collect();
+        trigger.update(hotkey.is_some_and(|key| is_evdev_hotkey_active(&keys, key, &settings.hotkey.modifiers)));
+
+        for ((action, key, modifiers), was_pressed) in actions.iter().zip(&mut actions_pressed) {
+            fire_on_press(is_evdev_hotkey_active(&keys, *key, modifiers), was_pressed, &|| on_action(action.clone()));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use evdev_rs::{
+        enums::EV_SYN,
+        InputEvent,
+        TimeVal,
+        UInputDevice,
+        UninitDevice,
+    };
+    use smol::{future, Timer};
+
+    use crate::config::ModifierSide;
+
+    #[test]
+    fn test_evdev_hotkey_matching() {
+        let ctrl = HotkeyModifiers { ctrl: true, ..HotkeyModifiers::default() };
+        assert!(is_evdev_hotkey_active(&[EV_KEY::KEY_F8], EV_KEY::KEY_F8, &HotkeyModifiers::default()));
+        assert!(!is_evdev_hotkey_active(&[EV_KEY::KEY_F8], EV_KEY::KEY_F8, &ctrl));
+        assert!(is_evdev_hotkey_active(&[EV_KEY::KEY_RIGHTCTRL, EV_KEY::KEY_F8], EV_KEY::KEY_F8, &ctrl));
+        assert!(!is_evdev_hotkey_active(&[EV_KEY::KEY_LEFTCTRL], EV_KEY::KEY_F8, &ctrl));
+        assert!(is_evdev_hotkey_active(&[EV_KEY::KEY_LEFTCTRL, EV_KEY::KEY_LEFTSHIFT, EV_KEY::KEY_F8], EV_KEY::KEY_F8, &ctrl));
+
+        let exact = HotkeyModifiers { exact: true, ..ctrl };
+        assert!(!is_evdev_hotkey_active(&[EV_KEY::KEY_LEFTCTRL, EV_KEY::KEY_LEFTSHIFT, EV_KEY::KEY_F8], EV_KEY::KEY_F8, &exact));
+        let right_ctrl = HotkeyModifiers { ctrl_side: ModifierSide::Right, ..ctrl };
+        assert!(!is_evdev_hotkey_active(&[EV_KEY::KEY_LEFTCTRL, EV_KEY::KEY_F8], EV_KEY::KEY_F8, &right_ctrl));
+        assert!(is_evdev_hotkey_active(&[EV_KEY::KEY_RIGHTCTRL, EV_KEY::KEY_F8], EV_KEY::KEY_F8, &right_ctrl));
+
+        // A modifier as the hotkey key still matches exactly
+        let exact_none = HotkeyModifiers { exact: true, ..HotkeyModifiers::default() };
+        assert!(is_evdev_hotkey_active(&[EV_KEY::KEY_RIGHTCTRL], EV_KEY::KEY_RIGHTCTRL, &exact_none));
+        assert!(is_evdev_hotkey_active(&[EV_KEY::KEY_RIGHTCTRL], EV_KEY::KEY_RIGHTCTRL, &exact));
+    }
+
+    // A virtual keyboard and its event node opened for reading, or None without access to
+    // /dev/uinput or its node
+    fn test_keyboard() -> Option<(UInputDevice, Device)> {
+        let device = UninitDevice::new()?;
+        device.set_name("hotkey test");
+        device.enable(EventCode::EV_KEY(EV_KEY::KEY_F13)).ok()?;
+        let keyboard = UInputDevice::create_from_device(&device).ok()?;
+        let path = PathBuf::from(keyboard.devnode()?);
+        // udev may need a moment to give the new node its permissions
+        for _ in 0..50 {
+            if let Ok(reader) = open_input_device(&path) {
+                return Some((keyboard, reader));
+            }
+            thread::sleep(Duration::from_millis(20));
+        }
+        None
+    }
+
+    #[test]
+    fn test_restarted_readers_stop() {
+        let timeval = TimeVal::new(0, 0);
+        for _ in 0..3 {
+            let Some((keyboard, reader)) = test_keyboard() else {
+                eprintln!("No readable virtual keyboard, skipping the evdev reader test");
+                return;
+            };
+            let stop = StopReaders::new().unwrap();
+            let (sender, events) = channel::unbounded();
+            let handle = spawn_reader(reader, 0, sender, &stop);
+
+            for (code, value) in [(EventCode::EV_KEY(EV_KEY::KEY_F13), 1), (EventCode::EV_SYN(EV_SYN::SYN_REPORT), 0)] {
+                keyboard.write_event(&InputEvent::new(&timeval, &code, value)).unwrap();
+            }
+            let received = smol::block_on(future::or(
+                async { events.recv().await.ok() },
+                async {
+                    Timer::after(Duration::from_secs(1)).await;
+                    None
+                },
+            ));
+            assert!(matches!(received, Some(DeviceEvent::Key { key: EV_KEY::KEY_F13, pressed: true, .. })));
+
+            // A listener restart drops its stop pipe, which must end the blocked reader
+            drop(stop);
+            let deadline = Instant::now() + Duration::from_secs(1);
+            while !handle.is_finished() && Instant::now() < deadline {
+                thread::sleep(Duration::from_millis(10));
+            }
+            assert!(handle.is_finished());
+        }
+    }
+
+    #[test]
+    fn test_hotkey_evkey() {
+        let hotkey = |key: &str| GlobalHotkey { key: key.to_string(), ..GlobalHotkey::default() };
+        assert_eq!(hotkey_evkey(&hotkey("F8")), Some(EV_KEY::KEY_F8));
+        assert_eq!(hotkey_evkey(&hotkey("KEY_F13")), Some(EV_KEY::KEY_F13));
+        assert_eq!(hotkey_evkey(&hotkey("BTN_SIDE")), Some(EV_KEY::BTN_SIDE));
+    }
+}
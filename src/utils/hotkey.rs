@@ -1,73 +1,252 @@
 use std::{
-    sync::{Arc, Mutex},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex, OnceLock,
+    },
     thread,
     time::{Duration, Instant},
 };
 
-use evdev_rs::enums::EventCode;
-use device_query::{DeviceQuery, DeviceState, Keycode};
+use device_query::{DeviceEvents, DeviceEventsHandler, DeviceQuery, DeviceState, Keycode};
+use smol::{channel, future, Timer};
 use crate::{
-    config::GlobalHotkey,
+    config::{GlobalHotkey, HotkeyAction, HotkeyActivation, HotkeyBackend, HotkeySettings},
     constants::{LISTENER_SLEEP_MS},
+    simulator::take_synthetic_press,
+    utils::key_utils::{hotkey_keycode, key_to_device_keycode, normalize_key, KEY_MAPPINGS},
 };
 
-use crate::utils::persistence::save_app_data;
+use super::watch;
 
 // New helper function extracting hotkey matching logic.
-fn is_hotkey_active(keys: &Vec<device_query::Keycode>, hotkey: device_query::Keycode, global_keybind: &crate::config::GlobalHotkey) -> bool {
-    // Check primary hotkey
-    let key_pressed = keys.contains(&hotkey);
-    // Check modifiers: only required if flagged true.
-    let ctrl_match = !global_keybind.modifiers.ctrl || (keys.contains(&device_query::Keycode::LControl) || keys.contains(&device_query::Keycode::RControl));
-    let alt_match = !global_keybind.modifiers.alt || (keys.contains(&device_query::Keycode::LAlt) || keys.contains(&device_query::Keycode::RAlt));
-    let shift_match = !global_keybind.modifiers.shift || (keys.contains(&device_query::Keycode::LShift) || keys.contains(&device_query::Keycode::RShift));
-    let super_match = !global_keybind.modifiers.super_key || (keys.contains(&device_query::Keycode::LMeta) || keys.contains(&device_query::Keycode::RMeta));
-    key_pressed && ctrl_match && alt_match && shift_match && super_match
+fn is_hotkey_active(keys: &[device_query::Keycode], hotkey: device_query::Keycode, global_keybind: &crate::config::GlobalHotkey) -> bool {
+    use device_query::Keycode;
+    let modifiers = &global_keybind.modifiers;
+    // The hotkey itself only counts as a held modifier the hotkey also requires
+    let held = |set: bool, left: Keycode, right: Keycode| {
+        let down = |key: Keycode| keys.contains(&key) && (set || key != hotkey);
+        (down(left), down(right))
+    };
+    keys.contains(&hotkey)
+        && modifiers.matches([
+            held(modifiers.ctrl, Keycode::LControl, Keycode::RControl),
+            held(modifiers.alt, Keycode::LAlt, Keycode::RAlt),
+            held(modifiers.shift, Keycode::LShift, Keycode::RShift),
+            held(modifiers.super_key, Keycode::LMeta, Keycode::RMeta),
+        ])
+}
+
+/// A change of the global hotkey as its activation mode reports it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HotkeyEdge {
+    Pressed,
+    /// Reported late for `HotkeyActivation::HoldWithDelay`, and not at all if pressed again in time.
+    Released,
 }
 
 pub fn start_global_hotkey_listener(
     _running: Arc<Mutex<bool>>,
     _interval_ms: Arc<Mutex<u64>>,
-    _selected_keys: Arc<Mutex<Vec<EventCode>>>,
+    _selected_keys: Arc<Mutex<Vec<crate::simulator::SimulationKey>>>,
     _key_behavior: Arc<Mutex<crate::config::KeyBehaviorMode>>,
     previous_state: Arc<Mutex<bool>>,
     _last_toggle: Arc<Mutex<Option<Instant>>>,
-    app_data: Arc<Mutex<crate::config::AppData>>,
-    on_hotkey: Arc<dyn Fn() + Send + Sync>, // new callback parameter
+    mut settings: watch::Receiver<HotkeySettings>,
+    on_hotkey: Arc<dyn Fn(HotkeyEdge) + Send + Sync>,
+    on_action: Arc<dyn Fn(HotkeyAction) + Send + Sync>,
 ) {
-    thread::spawn(move || {
-        let device_state = DeviceState::new();
-        log::info!("Started global hotkey listener");
-
-        loop {
-            let keys: Vec<Keycode> = device_state.get_keys();
-                
-            // Cache hotkey configuration.
-            let (hotkey, global_keybind) = {
-                let mut app_data_guard = app_data.lock().unwrap();
-                if app_data_guard.global_keybind.key.is_empty() {
-                    app_data_guard.global_keybind = GlobalHotkey::default();
-                    if let Err(e) = save_app_data(&mut app_data_guard) {
-                        log::error!("Failed to save default config: {}", e);
-                    }
-                }
-                let hotkey = crate::utils::key_utils::validate_hotkey(&app_data_guard);
-                let global_keybind = app_data_guard.global_keybind.clone();
-                (hotkey, global_keybind)
-            };
-
-            // Use helper function for hotkey matching.
-            let is_hotkey_pressed = is_hotkey_active(&keys, hotkey, &global_keybind);
-
-            // Handle hotkey state.
-            let mut prev_state = previous_state.lock().unwrap();
-            if is_hotkey_pressed && !*prev_state {
-                (on_hotkey)();
+    thread::spawn(move || loop {
+        // Each backend resolves the hotkeys once and listens until the settings change
+        let current = settings.borrow_and_update();
+        log::info!("Started {} global hotkey listener", current.backend);
+        let mut trigger = HotkeyTrigger::new(&current.hotkey, &previous_state, &*on_hotkey);
+        match current.backend {
+            HotkeyBackend::DeviceQuery => listen_device_query(&current, &mut settings, &mut trigger, &*on_action),
+            HotkeyBackend::Evdev => super::evdev_hotkey::listen_evdev(&current, &mut settings, &mut trigger, &*on_action),
+            HotkeyBackend::Portal => super::portal_hotkey::listen_portal(&current, &mut settings, &mut trigger, &*on_action),
+        }
+    });
+}
+
+/// Turns whether the global hotkey is down into the edges its activation mode reports.
+pub(super) struct HotkeyTrigger<'a> {
+    activation: HotkeyActivation,
+    // Kept outside the listener, so a hotkey held while the settings change doesn't fire again
+    is_down: &'a Mutex<bool>,
+    release_at: Option<Instant>,
+    on_edge: &'a dyn Fn(HotkeyEdge),
+}
+
+impl<'a> HotkeyTrigger<'a> {
+    pub(super) fn new(hotkey: &GlobalHotkey, is_down: &'a Mutex<bool>, on_edge: &'a dyn Fn(HotkeyEdge)) -> Self {
+        Self { activation: hotkey.activation, is_down, release_at: None, on_edge }
+    }
+
+    pub(super) fn update(&mut self, is_pressed: bool) {
+        {
+            let mut is_down = self.is_down.lock().unwrap();
+            if *is_down == is_pressed {
+                return;
+            }
+            *is_down = is_pressed;
+        }
+
+        if is_pressed {
+            // Pressed again before a delayed release is due: the run just goes on
+            if self.release_at.take().is_none() {
+                (self.on_edge)(HotkeyEdge::Pressed);
             }
-            *prev_state = is_hotkey_pressed;
-            thread::sleep(Duration::from_millis(LISTENER_SLEEP_MS));
+        } else if let HotkeyActivation::HoldWithDelay { release_delay_ms } = self.activation {
+            self.release_at = Some(Instant::now() + Duration::from_millis(release_delay_ms));
+        } else {
+            (self.on_edge)(HotkeyEdge::Released);
         }
+    }
+
+    /// How long until a delayed release is due, if one is pending.
+    pub(super) fn timeout(&self) -> Option<Duration> {
+        self.release_at.map(|at| at.saturating_duration_since(Instant::now()))
+    }
+
+    /// Reports a delayed release once it's due.
+    pub(super) fn fire_due(&mut self) {
+        if self.release_at.is_some_and(|at| at <= Instant::now()) {
+            self.release_at = None;
+            (self.on_edge)(HotkeyEdge::Released);
+        }
+    }
+}
+
+impl Drop for HotkeyTrigger<'_> {
+    // A pending release isn't lost when the listener restarts
+    fn drop(&mut self) {
+        if self.release_at.take().is_some() {
+            (self.on_edge)(HotkeyEdge::Released);
+        }
+    }
+}
+
+/// Calls `callback` when a hotkey goes from released to pressed.
+pub(super) fn fire_on_press(is_pressed: bool, was_pressed: &mut bool, callback: &dyn Fn()) {
+    if is_pressed && !*was_pressed {
+        callback();
+    }
+    *was_pressed = is_pressed;
+}
+
+/// Blocks for the next of `events`; None once the settings change or `timeout` passes.
+pub(super) fn next_event<E>(
+    events: &channel::Receiver<E>,
+    settings: &mut watch::Receiver<HotkeySettings>,
+    timeout: Option<Duration>,
+) -> Option<E> {
+    smol::block_on(future::or(
+        async { events.recv().await.ok() },
+        async {
+            match timeout {
+                Some(timeout) => future::or(settings.changed(), async { Timer::after(timeout).await; }).await,
+                None => settings.changed().await,
+            }
+            None
+        },
+    ))
+}
+
+// device_query runs one event loop per process, so its handler is only created once. That
+// loop queries the X11 keymap every LISTENER_SLEEP_MS, as device_query has no way to wait
+// for X11 key events; the evdev and portal listeners wait for events instead.
+fn device_events() -> Option<&'static DeviceEventsHandler> {
+    static HANDLER: OnceLock<Option<DeviceEventsHandler>> = OnceLock::new();
+    HANDLER
+        .get_or_init(|| DeviceEventsHandler::new(Duration::from_millis(LISTENER_SLEEP_MS)))
+        .as_ref()
+}
+
+fn listen_device_query(
+    settings: &HotkeySettings,
+    changes: &mut watch::Receiver<HotkeySettings>,
+    trigger: &mut HotkeyTrigger<'_>,
+    on_action: &dyn Fn(HotkeyAction),
+) {
+    let Some(handler) = device_events() else {
+        log::error!("Failed to start the device_query event loop");
+        smol::block_on(changes.changed());
+        return;
+    };
+
+    let hotkey = hotkey_keycode(&settings.hotkey);
+    // Action hotkeys with a key device_query doesn't know are left out
+    let actions: Vec<(HotkeyAction, Keycode, &GlobalHotkey)> = settings.actions
+        .iter()
+        .filter_map(|(action, hotkey)| {
+            key_to_device_keycode(&normalize_key(&hotkey.key)).map(|keycode| (action.clone(), keycode, hotkey))
+        })
+        .collect();
+
+    let (sender, events) = channel::unbounded();
+    let release_sender = sender.clone();
+    let _down_guard = handler.on_key_down(move |key: &Keycode| {
+        let _ = sender.try_send((*key, true));
+    });
+    let _up_guard = handler.on_key_up(move |key: &Keycode| {
+        let _ = release_sender.try_send((*key, false));
     });
+
+    let mut keys = DeviceState::new().get_keys();
+    let mut actions_pressed = vec![false; actions.len()];
+    loop {
+        let Some((key, pressed)) = next_event(&events, changes, trigger.timeout()) else {
+            if changes.has_changed() {
+                return;
+            }
+            trigger.fire_due();
+            continue;
+        };
+        // X11 merges every keyboard, so our own presses are told apart by the keys the simulator
+        // holds. Releases always go through, as they only drop keys counted as held here.
+        let is_synthetic = pressed
+            && take_synthetic_press(|ev_key| KEY_MAPPINGS.values().any(|&(keycode, mapped)| keycode == key && mapped == ev_key));
+        if is_synthetic {
+            continue;
+        }
+        keys.retain(|&down| down != key);
+        if pressed {
+            keys.push(key);
+        }
+
+        trigger.update(is_hotkey_active(&keys, hotkey, &settings.hotkey));
+
+        for ((action, keycode, hotkey), was_pressed) in actions.iter().zip(&mut actions_pressed) {
+            fire_on_press(is_hotkey_active(&keys, *keycode, hotkey), was_pressed, &|| on_action(action.clone()));
+        }
+    }
+}
+
+// What the evdev or portal listener sees held: keys of the keyboards it reads, or shortcuts
+// the compositor hasn't reported released yet. X11 can be asked directly instead.
+static LISTENER_HELD: AtomicUsize = AtomicUsize::new(0);
+
+pub(super) fn set_listener_held(count: usize) {
+    LISTENER_HELD.store(count, Ordering::Relaxed);
+}
+
+/// Waits until no key is held, so typed text isn't combined with the hotkey's modifiers.
+/// Asks the `backend` hotkeys are read with, as it may be the only one that sees the keys.
+/// Returns false if keys are still down after `timeout`.
+pub fn wait_for_keys_released(backend: HotkeyBackend, timeout: Duration) -> bool {
+    let device_state = (backend == HotkeyBackend::DeviceQuery).then(DeviceState::new);
+    let is_held = || match &device_state {
+        Some(device_state) => !device_state.get_keys().is_empty(),
+        None => LISTENER_HELD.load(Ordering::Relaxed) > 0,
+    };
+    let deadline = Instant::now() + timeout;
+    while is_held() {
+        if Instant::now() >= deadline {
+            return false;
+        }
+        thread::sleep(Duration::from_millis(LISTENER_SLEEP_MS));
+    }
+    true
 }
 
 #[cfg(test)]
@@ -81,93 +260,140 @@ mod tests {
         app_data
     }
 
+    fn record_edges(activation: HotkeyActivation, presses: &[bool]) -> Vec<HotkeyEdge> {
+        let edges = Mutex::new(Vec::new());
+        let on_edge = |edge: HotkeyEdge| edges.lock().unwrap().push(edge);
+        let is_down = Mutex::new(false);
+        {
+            let hotkey = GlobalHotkey { activation, ..GlobalHotkey::default() };
+            let mut trigger = HotkeyTrigger::new(&hotkey, &is_down, &on_edge);
+            for &pressed in presses {
+                trigger.update(pressed);
+            }
+        }
+        edges.into_inner().unwrap()
+    }
+
+    #[test]
+    fn test_hotkey_trigger_edges() {
+        use HotkeyEdge::{Pressed, Released};
+        // Repeated states are one edge
+        assert_eq!(record_edges(HotkeyActivation::Toggle, &[true, true, false, true]), vec![Pressed, Released, Pressed]);
+        assert_eq!(record_edges(HotkeyActivation::Hold, &[true, false]), vec![Pressed, Released]);
+
+        // A press within the release delay continues the run; the pending release is
+        // reported when the trigger goes away
+        let delayed = HotkeyActivation::HoldWithDelay { release_delay_ms: 60_000 };
+        assert_eq!(record_edges(delayed, &[true, false, true, false]), vec![Pressed, Released]);
+    }
+
+    #[test]
+    fn test_hotkey_trigger_delayed_release() {
+        let edges = Mutex::new(Vec::new());
+        let on_edge = |edge: HotkeyEdge| edges.lock().unwrap().push(edge);
+        let is_down = Mutex::new(false);
+        let hotkey = GlobalHotkey { activation: HotkeyActivation::HoldWithDelay { release_delay_ms: 10 }, ..GlobalHotkey::default() };
+        let mut trigger = HotkeyTrigger::new(&hotkey, &is_down, &on_edge);
+
+        trigger.update(true);
+        trigger.update(false);
+        assert!(trigger.timeout().is_some());
+        trigger.fire_due();
+        assert_eq!(*edges.lock().unwrap(), vec![HotkeyEdge::Pressed]);
+
+        thread::sleep(Duration::from_millis(20));
+        trigger.fire_due();
+        assert_eq!(trigger.timeout(), None);
+        assert_eq!(*edges.lock().unwrap(), vec![HotkeyEdge::Pressed, HotkeyEdge::Released]);
+    }
+
     #[test]
     fn test_validate_hotkey() {
         // Test empty hotkey
         let app_data = create_test_app_data("");
-        assert_eq!(crate::utils::key_utils::validate_hotkey(&app_data), Keycode::F8);
+        assert_eq!(hotkey_keycode(&app_data.global_keybind), Keycode::F8);
 
         // Test valid named key with different cases
         let app_data = create_test_app_data("Named(F8)");
-        assert_eq!(crate::utils::key_utils::validate_hotkey(&app_data), Keycode::F8);
+        assert_eq!(hotkey_keycode(&app_data.global_keybind), Keycode::F8);
         let app_data = create_test_app_data("NAMED(F8)");
-        assert_eq!(crate::utils::key_utils::validate_hotkey(&app_data), Keycode::F8);
+        assert_eq!(hotkey_keycode(&app_data.global_keybind), Keycode::F8);
         let app_data = create_test_app_data("named(f8)");
-        assert_eq!(crate::utils::key_utils::validate_hotkey(&app_data), Keycode::F8);
+        assert_eq!(hotkey_keycode(&app_data.global_keybind), Keycode::F8);
 
         // Test single letters (should be handled by key_to_device_keycode)
         let app_data = create_test_app_data("A");
-        assert_eq!(crate::utils::key_utils::validate_hotkey(&app_data), Keycode::A);
+        assert_eq!(hotkey_keycode(&app_data.global_keybind), Keycode::A);
         let app_data = create_test_app_data("a");
-        assert_eq!(crate::utils::key_utils::validate_hotkey(&app_data), Keycode::A);
+        assert_eq!(hotkey_keycode(&app_data.global_keybind), Keycode::A);
 
         // Test invalid key format
         let app_data = create_test_app_data("INVALID_KEY");
-        assert_eq!(crate::utils::key_utils::validate_hotkey(&app_data), Keycode::F8);
+        assert_eq!(hotkey_keycode(&app_data.global_keybind), Keycode::F8);
 
         // Test valid key with KEY_ prefix in different cases
         let app_data = create_test_app_data("KEY_F9");
-        assert_eq!(crate::utils::key_utils::validate_hotkey(&app_data), Keycode::F9);
+        assert_eq!(hotkey_keycode(&app_data.global_keybind), Keycode::F9);
         let app_data = create_test_app_data("key_f9");
-        assert_eq!(crate::utils::key_utils::validate_hotkey(&app_data), Keycode::F9);
+        assert_eq!(hotkey_keycode(&app_data.global_keybind), Keycode::F9);
 
         // Test prefixed character keys
         let app_data = create_test_app_data("Key_A");
-        assert_eq!(crate::utils::key_utils::validate_hotkey(&app_data), Keycode::A);
+        assert_eq!(hotkey_keycode(&app_data.global_keybind), Keycode::A);
         let app_data = create_test_app_data("KEY_A");
-        assert_eq!(crate::utils::key_utils::validate_hotkey(&app_data), Keycode::A);
+        assert_eq!(hotkey_keycode(&app_data.global_keybind), Keycode::A);
 
         // Test invalid configurations
         let app_data = create_test_app_data(" ");
-        assert_eq!(crate::utils::key_utils::validate_hotkey(&app_data), Keycode::F8);
+        assert_eq!(hotkey_keycode(&app_data.global_keybind), Keycode::F8);
         let app_data = create_test_app_data("KEY_");
-        assert_eq!(crate::utils::key_utils::validate_hotkey(&app_data), Keycode::F8);
+        assert_eq!(hotkey_keycode(&app_data.global_keybind), Keycode::F8);
         let app_data = create_test_app_data("Named()");
-        assert_eq!(crate::utils::key_utils::validate_hotkey(&app_data), Keycode::F8);
+        assert_eq!(hotkey_keycode(&app_data.global_keybind), Keycode::F8);
 
         // Test special characters
         let app_data = create_test_app_data("#");
-        assert_eq!(crate::utils::key_utils::validate_hotkey(&app_data), Keycode::F8);
+        assert_eq!(hotkey_keycode(&app_data.global_keybind), Keycode::F8);
 
         // Test numbers (should be handled by key_to_device_keycode)
         let app_data = create_test_app_data("1");
-        assert_eq!(crate::utils::key_utils::validate_hotkey(&app_data), Keycode::Key1);
+        assert_eq!(hotkey_keycode(&app_data.global_keybind), Keycode::Key1);
 
         // Test Character format
         let app_data = create_test_app_data(r#"Character("K")"#);
-        assert_eq!(crate::utils::key_utils::validate_hotkey(&app_data), Keycode::K);
+        assert_eq!(hotkey_keycode(&app_data.global_keybind), Keycode::K);
         
         // Test numpad keys
         let app_data = create_test_app_data(r#"Character("KP4")"#);
-        assert_eq!(crate::utils::key_utils::validate_hotkey(&app_data), Keycode::Numpad4);
+        assert_eq!(hotkey_keycode(&app_data.global_keybind), Keycode::Numpad4);
         
         let app_data = create_test_app_data(r#"Character("KP0")"#);
-        assert_eq!(crate::utils::key_utils::validate_hotkey(&app_data), Keycode::Numpad0);
+        assert_eq!(hotkey_keycode(&app_data.global_keybind), Keycode::Numpad0);
         
         let app_data = create_test_app_data(r#"Character("KP9")"#);
-        assert_eq!(crate::utils::key_utils::validate_hotkey(&app_data), Keycode::Numpad9);
+        assert_eq!(hotkey_keycode(&app_data.global_keybind), Keycode::Numpad9);
     }
 
     #[test]
     fn test_hotkey_whitespace_handling() {
         let app_data = create_test_app_data("  F8  ");
-        assert_eq!(crate::utils::key_utils::validate_hotkey(&app_data), Keycode::F8);
+        assert_eq!(hotkey_keycode(&app_data.global_keybind), Keycode::F8);
         
         let app_data = create_test_app_data("  KEY_F8  ");
-        assert_eq!(crate::utils::key_utils::validate_hotkey(&app_data), Keycode::F8);
+        assert_eq!(hotkey_keycode(&app_data.global_keybind), Keycode::F8);
         
         let app_data = create_test_app_data("  Named(F8)  ");
-        assert_eq!(crate::utils::key_utils::validate_hotkey(&app_data), Keycode::F8);
+        assert_eq!(hotkey_keycode(&app_data.global_keybind), Keycode::F8);
     }
 
     #[test]
     fn test_character_key_handling() {
         // Test character keys with modifiers
         let app_data = create_test_app_data(r#"Character("K")"#);
-        assert_eq!(crate::utils::key_utils::validate_hotkey(&app_data), Keycode::K);
+        assert_eq!(hotkey_keycode(&app_data.global_keybind), Keycode::K);
         
         // Test lowercase character keys
         let app_data = create_test_app_data(r#"Character("k")"#);
-        assert_eq!(crate::utils::key_utils::validate_hotkey(&app_data), Keycode::K);
+        assert_eq!(hotkey_keycode(&app_data.global_keybind), Keycode::K);
     }
 }
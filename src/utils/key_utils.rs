@@ -181,6 +181,24 @@ pub fn key_to_device_keycode(key: &str) -> Option<Keycode> {
     None
 }
 
+/// Reverse of KEY_MAPPINGS. Where several names share a key (e.g. ";" and "ø"),
+/// the ASCII name wins so the result doesn't depend on map order.
+pub fn evkey_to_key_name(ev_key: EV_KEY) -> Option<&'static str> {
+    KEY_MAPPINGS
+        .iter()
+        .filter(|(_, (_, ev))| *ev == ev_key)
+        .map(|(name, _)| *name)
+        .min_by_key(|name| (!name.is_ascii(), *name))
+}
+
+/// Looks up a raw evdev key name such as "KEY_RIGHTCTRL", for keys KEY_MAPPINGS doesn't cover.
+pub fn evdev_name_to_evkey(name: &str) -> Option<EV_KEY> {
+    match evdev_rs::enums::EventCode::from_str(&evdev_rs::enums::EventType::EV_KEY, name)? {
+        evdev_rs::enums::EventCode::EV_KEY(ev_key) => Some(ev_key),
+        _ => None,
+    }
+}
+
 /// Converts a raw key string (from JSON) into a device Keycode.
 pub fn raw_key_to_device_keycode(raw: &String) -> Option<Keycode> {
     let key = normalize_key(raw);
@@ -193,11 +211,12 @@ fn fallback_hotkey() -> Keycode {
     Keycode::F8
 }
 
-pub fn validate_hotkey(app_data: &crate::config::AppData) -> Keycode {
-    log::debug!("Validating hotkey configuration: {}", app_data.global_keybind.key);
+/// The device_query key of a hotkey, falling back to the default hotkey when it has none.
+pub fn hotkey_keycode(hotkey: &crate::config::GlobalHotkey) -> Keycode {
+    log::debug!("Validating hotkey configuration: {}", hotkey.key);
     let default_hotkey = crate::config::GlobalHotkey::default();
     
-    let key_str = app_data.global_keybind.key.trim();
+    let key_str = hotkey.key.trim();
     if key_str.is_empty() {
         log::warn!("Empty or whitespace-only hotkey configured, falling back to default");
         return key_to_device_keycode(normalize_key(default_hotkey.key.as_str()).as_str())
@@ -215,10 +234,29 @@ pub fn validate_hotkey(app_data: &crate::config::AppData) -> Keycode {
         return keycode;
     }
     
-    log::warn!("Hotkey validation failed for '{}', falling back to default", app_data.global_keybind.key);
+    log::warn!("Hotkey validation failed for '{}', falling back to default", hotkey.key);
     fallback_hotkey()
 }
 
+/// Whether a normalized key name is a modifier key on its own.
+pub fn is_modifier_key_name(key: &str) -> bool {
+    matches!(key, "Control" | "Shift" | "Alt" | "AltGraph" | "Super")
+}
+
+/// The left-hand modifier keys pressed for a chord's modifiers, in Ctrl, Alt, Shift, Super order.
+pub fn modifier_evkeys(modifiers: &crate::config::HotkeyModifiers) -> Vec<EV_KEY> {
+    [
+        (modifiers.ctrl, EV_KEY::KEY_LEFTCTRL),
+        (modifiers.alt, EV_KEY::KEY_LEFTALT),
+        (modifiers.shift, EV_KEY::KEY_LEFTSHIFT),
+        (modifiers.super_key, EV_KEY::KEY_LEFTMETA),
+    ]
+    .into_iter()
+    .filter(|(set, _)| *set)
+    .map(|(_, key)| key)
+    .collect()
+}
+
 pub fn is_modifier_evcode(ec: &evdev_rs::enums::EventCode) -> bool {
     use evdev_rs::enums::EV_KEY;
     match ec {
@@ -1,7 +1,12 @@
 pub mod key_utils;
 mod scroll;
 mod hotkey;
+mod evdev_hotkey;
+mod portal_hotkey;
+pub mod watch;
 pub mod persistence;
+pub mod random;
+pub mod schedule;
 
 pub use scroll::handle_scroll_value;
-pub use hotkey::start_global_hotkey_listener;
+pub use hotkey::{start_global_hotkey_listener, wait_for_keys_released, HotkeyEdge};
@@ -0,0 +1,578 @@
+//! Global hotkeys registered through the XDG desktop portal's GlobalShortcuts interface,
+//! for sessions without access to /dev/input. The compositor owns the binding: it may ask
+//! the user to confirm or change the trigger, and reports presses and releases as
+//! `Activated`/`Deactivated` signals.
+
+use std::{
+    collections::HashMap,
+    sync::atomic::{AtomicU32, Ordering},
+};
+
+use smol::{future, stream::StreamExt, Timer};
+use zbus::{
+    proxy,
+    zvariant::{OwnedObjectPath, OwnedValue, Value},
+    Connection,
+};
+
+use crate::{
+    config::{GlobalHotkey, HotkeyAction, HotkeySettings},
+    error::{AppError, Result},
+    simulator::take_synthetic_chord,
+};
+
+use super::{
+    evdev_hotkey::{hotkey_evkey, is_evdev_hotkey_active},
+    hotkey::{fire_on_press, set_listener_held, HotkeyTrigger},
+    watch,
+};
+
+const TOGGLE_SHORTCUT: &str = "toggle";
+
+// Shortcut id and description of an action hotkey. A profile's id outlives renames; one
+// bound before getting an id falls back to its name, cut down to the characters ids allow.
+fn action_shortcut(action: &HotkeyAction, profile_ids: &HashMap<String, u32>) -> (String, String) {
+    let (id, description) = match action {
+        HotkeyAction::Paste => ("paste", "Type the clipboard text"),
+        HotkeyAction::Start => ("start", "Start or resume the simulation"),
+        HotkeyAction::Stop => ("stop", "Stop the simulation"),
+        HotkeyAction::PauseResume => ("pause", "Pause or resume the simulation"),
+        HotkeyAction::Profile(name) => {
+            let id = match profile_ids.get(name) {
+                Some(id) => format!("profile-{}", id),
+                None => {
+                    let name: String = name
+                        .chars()
+                        .map(|c| if c.is_ascii_alphanumeric() { c.to_ascii_lowercase() } else { '_' })
+                        .collect();
+                    format!("profile-named-{}", name)
+                }
+            };
+            return (id, format!("Run the {} profile", name));
+        }
+    };
+    (id.to_string(), description.to_string())
+}
+
+#[proxy(
+    interface = "org.freedesktop.portal.GlobalShortcuts",
+    default_service = "org.freedesktop.portal.Desktop",
+    default_path = "/org/freedesktop/portal/desktop",
+    gen_blocking = false
+)]
+trait GlobalShortcuts {
+    fn create_session(&self, options: HashMap<&str, Value<'_>>) -> zbus::Result<OwnedObjectPath>;
+
+    fn bind_shortcuts(
+        &self,
+        session_handle: &OwnedObjectPath,
+        shortcuts: &[(&str, HashMap<&str, Value<'_>>)],
+        parent_window: &str,
+        options: HashMap<&str, Value<'_>>,
+    ) -> zbus::Result<OwnedObjectPath>;
+
+    #[zbus(signal)]
+    fn activated(
+        &self,
+        session_handle: OwnedObjectPath,
+        shortcut_id: String,
+        timestamp: u64,
+        options: HashMap<String, OwnedValue>,
+    ) -> zbus::Result<()>;
+
+    #[zbus(signal)]
+    fn deactivated(
+        &self,
+        session_handle: OwnedObjectPath,
+        shortcut_id: String,
+        timestamp: u64,
+        options: HashMap<String, OwnedValue>,
+    ) -> zbus::Result<()>;
+}
+
+#[proxy(
+    interface = "org.freedesktop.portal.Request",
+    default_service = "org.freedesktop.portal.Desktop",
+    gen_blocking = false
+)]
+trait Request {
+    #[zbus(signal)]
+    fn response(&self, response: u32, results: HashMap<String, OwnedValue>) -> zbus::Result<()>;
+}
+
+#[proxy(
+    interface = "org.freedesktop.portal.Session",
+    default_service = "org.freedesktop.portal.Desktop",
+    gen_blocking = false
+)]
+trait Session {
+    fn close(&self) -> zbus::Result<()>;
+}
+
+/// A press or release of a bound shortcut, by shortcut id.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum ShortcutEvent {
+    Activated(String),
+    Deactivated(String),
+}
+
+/// The trigger suggested to the compositor, in the XDG shortcuts format (e.g. "CTRL+SHIFT+F8").
+/// The format has no modifier sides or exact matching; the compositor decides how it matches.
+pub(crate) fn preferred_trigger(hotkey: &GlobalHotkey) -> String {
+    let modifiers = [
+        (hotkey.modifiers.ctrl, "CTRL"),
+        (hotkey.modifiers.alt, "ALT"),
+        (hotkey.modifiers.shift, "SHIFT"),
+        (hotkey.modifiers.super_key, "LOGO"),
+    ];
+    let mut parts: Vec<String> = modifiers
+        .iter()
+        .filter(|(set, _)| *set)
+        .map(|(_, name)| name.to_string())
+        .collect();
+    parts.push(keysym_name(&super::key_utils::normalize_key(&hotkey.key)));
+    parts.join("+")
+}
+
+// xkb keysym names of the `selected_keys` names that differ
+fn keysym_name(key: &str) -> String {
+    let name = match key {
+        "Enter" => "Return",
+        "Space" => "space",
+        "Backspace" => "BackSpace",
+        "PageUp" => "Page_Up",
+        "PageDown" => "Page_Down",
+        "ArrowLeft" => "Left",
+        "ArrowRight" => "Right",
+        "ArrowUp" => "Up",
+        "ArrowDown" => "Down",
+        "CapsLock" => "Caps_Lock",
+        "-" => "minus",
+        "=" => "equal",
+        "," => "comma",
+        "." => "period",
+        "/" => "slash",
+        ";" => "semicolon",
+        "'" => "apostrophe",
+        "[" => "bracketleft",
+        "]" => "bracketright",
+        "`" => "grave",
+        _ => {
+            if let Some(digit) = key.strip_prefix("KP").filter(|digit| digit.len() == 1) {
+                return format!("KP_{}", digit);
+            }
+            // Letter keysyms are lowercase
+            return if key.chars().count() == 1 { key.to_lowercase() } else { key.to_string() };
+        }
+    };
+    name.to_string()
+}
+
+// Request handles need a token unique to this connection
+fn next_token() -> String {
+    static COUNTER: AtomicU32 = AtomicU32::new(0);
+    format!("inputsimulator{}", COUNTER.fetch_add(1, Ordering::Relaxed))
+}
+
+// Subscribes to the Response of the request a call with `token` will create. Subscribing
+// before the call means a fast response can't be missed.
+async fn request_responses(connection: &Connection, token: &str) -> Result<ResponseStream> {
+    let sender = connection
+        .unique_name()
+        .ok_or_else(|| AppError::Portal("not connected to a message bus".to_string()))?
+        .trim_start_matches(':')
+        .replace('.', "_");
+    let path = format!("/org/freedesktop/portal/desktop/request/{}/{}", sender, token);
+    let request = RequestProxy::builder(connection).path(path)?.build().await?;
+    Ok(request.receive_response().await?)
+}
+
+async fn response_results(mut responses: ResponseStream) -> Result<HashMap<String, OwnedValue>> {
+    let response = responses
+        .next()
+        .await
+        .ok_or_else(|| AppError::Portal("request closed without a response".to_string()))?;
+    let args = response.args()?;
+    match args.response {
+        0 => Ok(args.results),
+        1 => Err(AppError::Portal("cancelled by the user".to_string())),
+        _ => Err(AppError::Portal("request failed".to_string())),
+    }
+}
+
+// The session handle is a string in version 1 of the interface, an object path in some portals
+fn session_handle(results: &HashMap<String, OwnedValue>) -> Option<OwnedObjectPath> {
+    match &**results.get("session_handle")? {
+        Value::Str(path) => OwnedObjectPath::try_from(path.as_str()).ok(),
+        Value::ObjectPath(path) => Some(path.clone().into()),
+        _ => None,
+    }
+}
+
+/// A GlobalShortcuts session with the toggle shortcut and the action hotkeys that are set bound.
+pub(crate) struct PortalSession {
+    connection: Connection,
+    handle: OwnedObjectPath,
+    activated: ActivatedStream,
+    deactivated: DeactivatedStream,
+}
+
+impl PortalSession {
+    pub(crate) async fn open(connection: &Connection, settings: &HotkeySettings) -> Result<Self> {
+        let HotkeySettings { hotkey, actions, profile_ids, .. } = settings;
+        let portal = GlobalShortcutsProxy::new(connection).await?;
+
+        let token = next_token();
+        let responses = request_responses(connection, &token).await?;
+        let session_token = next_token();
+        let options = HashMap::from([
+            ("handle_token", Value::from(token.as_str())),
+            ("session_handle_token", Value::from(session_token.as_str())),
+        ]);
+        portal.create_session(options).await?;
+        let handle = session_handle(&response_results(responses).await?)
+            .ok_or_else(|| AppError::Portal("no session handle in the response".to_string()))?;
+
+        // Subscribed before binding, so a press right after the user confirms isn't missed
+        let activated = portal.receive_activated().await?;
+        let deactivated = portal.receive_deactivated().await?;
+
+        let shortcut = |description: &str, hotkey: &GlobalHotkey| {
+            HashMap::from([
+                ("description", Value::from(description.to_string())),
+                ("preferred_trigger", Value::from(preferred_trigger(hotkey))),
+            ])
+        };
+        let action_shortcuts: Vec<(String, String)> = actions.iter().map(|(action, _)| action_shortcut(action, profile_ids)).collect();
+        let mut shortcuts = vec![(TOGGLE_SHORTCUT, shortcut("Start or stop the simulation", hotkey))];
+        for ((id, description), (_, action_hotkey)) in action_shortcuts.iter().zip(actions) {
+            shortcuts.push((id.as_str(), shortcut(description, action_hotkey)));
+        }
+
+        let token = next_token();
+        let responses = request_responses(connection, &token).await?;
+        portal
+            .bind_shortcuts(&handle, &shortcuts, "", HashMap::from([("handle_token", Value::from(token.as_str()))]))
+            .await?;
+        response_results(responses).await?;
+        log::info!("Bound {} shortcuts through the GlobalShortcuts portal", shortcuts.len());
+
+        Ok(Self { connection: connection.clone(), handle, activated, deactivated })
+    }
+
+    /// Waits for the next press or release of one of this session's shortcuts.
+    pub(crate) async fn next_event(&mut self) -> Result<ShortcutEvent> {
+        let Self { handle, activated, deactivated, .. } = self;
+        loop {
+            let event = future::or(
+                async {
+                    let signal = activated.next().await?;
+                    let args = signal.args().ok()?;
+                    Some((args.session_handle, ShortcutEvent::Activated(args.shortcut_id)))
+                },
+                async {
+                    let signal = deactivated.next().await?;
+                    let args = signal.args().ok()?;
+                    Some((args.session_handle, ShortcutEvent::Deactivated(args.shortcut_id)))
+                },
+            )
+            .await;
+
+            match event {
+                Some((session, event)) if session == *handle => return Ok(event),
+                Some(_) => {}
+                None => return Err(AppError::Portal("shortcut signals ended".to_string())),
+            }
+        }
+    }
+
+    pub(crate) async fn close(self) {
+        let closed = async {
+            SessionProxy::builder(&self.connection).path(&self.handle)?.build().await?.close().await
+        };
+        if let Err(e) = closed.await {
+            log::debug!("Failed to close the GlobalShortcuts session: {}", e);
+        }
+    }
+}
+
+// What the listener wakes up for
+enum Wake {
+    Shortcut(Result<ShortcutEvent>),
+    SettingsChanged,
+    ReleaseDue,
+}
+
+async fn run_session(
+    settings: &HotkeySettings,
+    changes: &mut watch::Receiver<HotkeySettings>,
+    trigger: &mut HotkeyTrigger<'_>,
+    on_action: &dyn Fn(HotkeyAction),
+) -> Result<()> {
+    let connection = Connection::session().await?;
+    // Binding may wait on the user, who can change the settings meanwhile
+    let opened = future::or(
+        async { Some(PortalSession::open(&connection, settings).await) },
+        async {
+            changes.changed().await;
+            None
+        },
+    )
+    .await;
+    let Some(opened) = opened else {
+        return Ok(());
+    };
+    let mut session = opened?;
+    let mut actions_pressed = vec![false; settings.actions.len()];
+    // The compositor reports our own presses too, when the simulator holds the whole chord. It
+    // may have bound another trigger, but then the simulator can't press it by accident either.
+    let is_synthetic = |hotkey: &GlobalHotkey| {
+        hotkey_evkey(hotkey).is_some_and(|key| {
+            take_synthetic_chord(|held| is_evdev_hotkey_active(held, key, &hotkey.modifiers))
+        })
+    };
+    let action_index = |id: &str| settings.actions.iter().position(|(action, _)| action_shortcut(action, &settings.profile_ids).0 == id);
+    // Shortcuts pressed and not released yet
+    let mut held: Vec<String> = Vec::new();
+    set_listener_held(0);
+
+    let result = loop {
+        let release_due = trigger.timeout();
+        let wake = future::or(
+            async { Wake::Shortcut(session.next_event().await) },
+            future::or(
+                async {
+                    changes.changed().await;
+                    Wake::SettingsChanged
+                },
+                async {
+                    match release_due {
+                        Some(due) => {
+                            Timer::after(due).await;
+                        }
+                        None => future::pending::<()>().await,
+                    }
+                    Wake::ReleaseDue
+                },
+            ),
+        )
+        .await;
+
+        if let Wake::Shortcut(Ok(event)) = &wake {
+            match event {
+                ShortcutEvent::Activated(id) if !held.contains(id) => held.push(id.clone()),
+                ShortcutEvent::Activated(_) => {}
+                ShortcutEvent::Deactivated(id) => held.retain(|held| held != id),
+            }
+            set_listener_held(held.len());
+        }
+
+        match wake {
+            Wake::Shortcut(Ok(ShortcutEvent::Activated(id))) if id == TOGGLE_SHORTCUT => {
+                if !is_synthetic(&settings.hotkey) {
+                    trigger.update(true);
+                }
+            }
+            Wake::Shortcut(Ok(ShortcutEvent::Deactivated(id))) if id == TOGGLE_SHORTCUT => trigger.update(false),
+            Wake::Shortcut(Ok(ShortcutEvent::Activated(id))) => {
+                if let Some(index) = action_index(&id).filter(|&index| !is_synthetic(&settings.actions[index].1)) {
+                    let action = &settings.actions[index].0;
+                    fire_on_press(true, &mut actions_pressed[index], &|| on_action(action.clone()));
+                }
+            }
+            Wake::Shortcut(Ok(ShortcutEvent::Deactivated(id))) => {
+                if let Some(index) = action_index(&id) {
+                    let action = &settings.actions[index].0;
+                    fire_on_press(false, &mut actions_pressed[index], &|| on_action(action.clone()));
+                }
+            }
+            Wake::Shortcut(Err(e)) => break Err(e),
+            Wake::SettingsChanged => break Ok(()),
+            Wake::ReleaseDue => trigger.fire_due(),
+        }
+    };
+
+    session.close().await;
+    set_listener_held(0);
+    result
+}
+
+/// Listens until the hotkey settings change.
+pub(super) fn listen_portal(
+    settings: &HotkeySettings,
+    changes: &mut watch::Receiver<HotkeySettings>,
+    trigger: &mut HotkeyTrigger<'_>,
+    on_action: &dyn Fn(HotkeyAction),
+) {
+    if let Err(e) = smol::block_on(run_session(settings, changes, trigger, on_action)) {
+        log::error!("GlobalShortcuts portal hotkeys unavailable: {}", e);
+        // Retrying right away would only fail again, so wait for different settings
+        smol::block_on(changes.changed());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{HotkeyBackend, HotkeyModifiers};
+    use std::{
+        io::{BufRead, BufReader},
+        process::{Child, Command, Stdio},
+        sync::{Arc, Mutex},
+    };
+    use zbus::{interface, message::Header, object_server::SignalEmitter};
+
+    const SESSION_PATH: &str = "/org/freedesktop/portal/desktop/session/1/test";
+
+    // A dbus-daemon of our own, so the tests never talk to the real portal
+    struct PrivateBus(Child);
+
+    impl PrivateBus {
+        fn start() -> Option<(Self, String)> {
+            let mut child = Command::new("dbus-daemon")
+                .args(["--session", "--nofork", "--print-address"])
+                .stdout(Stdio::piped())
+                .spawn()
+                .ok()?;
+            let mut address = String::new();
+            BufReader::new(child.stdout.take()?).read_line(&mut address).ok()?;
+            Some((Self(child), address.trim().to_string()))
+        }
+    }
+
+    impl Drop for PrivateBus {
+        fn drop(&mut self) {
+            let _ = self.0.kill();
+            let _ = self.0.wait();
+        }
+    }
+
+    #[derive(Default)]
+    struct MockPortal {
+        bound: Arc<Mutex<Vec<(String, String)>>>,
+    }
+
+    async fn respond(emitter: &Connection, header: &Header<'_>, token: &str, results: HashMap<&str, Value<'_>>) -> zbus::fdo::Result<OwnedObjectPath> {
+        let sender = header.sender().unwrap().trim_start_matches(':').replace('.', "_");
+        let path = format!("/org/freedesktop/portal/desktop/request/{}/{}", sender, token);
+        emitter
+            .emit_signal(header.sender().cloned(), path.as_str(), "org.freedesktop.portal.Request", "Response", &(0u32, results))
+            .await?;
+        Ok(OwnedObjectPath::try_from(path).unwrap())
+    }
+
+    fn token(options: &HashMap<String, OwnedValue>) -> String {
+        options.get("handle_token").and_then(|value| String::try_from(value.clone()).ok()).unwrap()
+    }
+
+    #[interface(name = "org.freedesktop.portal.GlobalShortcuts")]
+    impl MockPortal {
+        async fn create_session(
+            &self,
+            #[zbus(header)] header: Header<'_>,
+            #[zbus(connection)] connection: &Connection,
+            options: HashMap<String, OwnedValue>,
+        ) -> zbus::fdo::Result<OwnedObjectPath> {
+            let results = HashMap::from([("session_handle", Value::from(SESSION_PATH))]);
+            respond(connection, &header, &token(&options), results).await
+        }
+
+        async fn bind_shortcuts(
+            &self,
+            #[zbus(header)] header: Header<'_>,
+            #[zbus(connection)] connection: &Connection,
+            _session_handle: OwnedObjectPath,
+            shortcuts: Vec<(String, HashMap<String, OwnedValue>)>,
+            _parent_window: String,
+            options: HashMap<String, OwnedValue>,
+        ) -> zbus::fdo::Result<OwnedObjectPath> {
+            for (id, shortcut) in shortcuts {
+                let trigger = shortcut.get("preferred_trigger").and_then(|value| String::try_from(value.clone()).ok());
+                self.bound.lock().unwrap().push((id, trigger.unwrap_or_default()));
+            }
+            respond(connection, &header, &token(&options), HashMap::new()).await
+        }
+
+        #[zbus(signal)]
+        async fn activated(
+            emitter: &SignalEmitter<'_>,
+            session_handle: OwnedObjectPath,
+            shortcut_id: &str,
+            timestamp: u64,
+            options: HashMap<&str, Value<'_>>,
+        ) -> zbus::Result<()>;
+    }
+
+    #[test]
+    fn test_preferred_trigger() {
+        let hotkey = |key: &str, modifiers| GlobalHotkey { key: key.to_string(), modifiers, ..GlobalHotkey::default() };
+        let ctrl_shift = HotkeyModifiers { ctrl: true, shift: true, ..HotkeyModifiers::default() };
+        assert_eq!(preferred_trigger(&hotkey("F8", HotkeyModifiers::default())), "F8");
+        assert_eq!(preferred_trigger(&hotkey("A", ctrl_shift)), "CTRL+SHIFT+a");
+        let logo = HotkeyModifiers { super_key: true, ..HotkeyModifiers::default() };
+        assert_eq!(preferred_trigger(&hotkey("Enter", logo)), "LOGO+Return");
+        assert_eq!(preferred_trigger(&hotkey("KP5", HotkeyModifiers::default())), "KP_5");
+    }
+
+    #[test]
+    fn test_portal_session_with_mock_portal() {
+        let Some((_bus, address)) = PrivateBus::start() else {
+            eprintln!("dbus-daemon not available, skipping the mock portal test");
+            return;
+        };
+
+        smol::block_on(async {
+            let portal = MockPortal::default();
+            let bound = Arc::clone(&portal.bound);
+            let server = zbus::connection::Builder::address(address.as_str()).unwrap()
+                .name("org.freedesktop.portal.Desktop").unwrap()
+                .serve_at("/org/freedesktop/portal/desktop", portal).unwrap()
+                .build()
+                .await
+                .unwrap();
+            let client = zbus::connection::Builder::address(address.as_str()).unwrap().build().await.unwrap();
+
+            let hotkey = GlobalHotkey {
+                modifiers: HotkeyModifiers { ctrl: true, ..HotkeyModifiers::default() },
+                ..GlobalHotkey::default()
+            };
+            let paste = GlobalHotkey {
+                key: "V".to_string(),
+                modifiers: HotkeyModifiers { super_key: true, ..HotkeyModifiers::default() },
+                ..GlobalHotkey::default()
+            };
+            let stop = GlobalHotkey { key: "F9".to_string(), ..GlobalHotkey::default() };
+            let f10 = GlobalHotkey { key: "F10".to_string(), ..GlobalHotkey::default() };
+            let f11 = GlobalHotkey { key: "F11".to_string(), ..GlobalHotkey::default() };
+            let settings = HotkeySettings {
+                backend: HotkeyBackend::Portal,
+                hotkey,
+                actions: vec![
+                    (HotkeyAction::Stop, stop),
+                    (HotkeyAction::Paste, paste),
+                    (HotkeyAction::Profile("Farm: clicks".to_string()), f10),
+                    (HotkeyAction::Profile("Hold W".to_string()), f11),
+                ],
+                profile_ids: HashMap::from([("Farm: clicks".to_string(), 3)]),
+            };
+            let mut session = PortalSession::open(&client, &settings).await.unwrap();
+            assert_eq!(*bound.lock().unwrap(), vec![
+                ("toggle".to_string(), "CTRL+F8".to_string()),
+                ("stop".to_string(), "F9".to_string()),
+                ("paste".to_string(), "LOGO+v".to_string()),
+                ("profile-3".to_string(), "F10".to_string()),
+                ("profile-named-hold_w".to_string(), "F11".to_string()),
+            ]);
+
+            let emitter = SignalEmitter::new(&server, "/org/freedesktop/portal/desktop").unwrap();
+            let other = OwnedObjectPath::try_from("/org/freedesktop/portal/desktop/session/1/other").unwrap();
+            let ours = OwnedObjectPath::try_from(SESSION_PATH).unwrap();
+            // Signals of other sessions are skipped
+            MockPortal::activated(&emitter, other, "toggle", 1, HashMap::new()).await.unwrap();
+            MockPortal::activated(&emitter, ours, "paste", 2, HashMap::new()).await.unwrap();
+            assert_eq!(session.next_event().await.unwrap(), ShortcutEvent::Activated("paste".to_string()));
+
+            session.close().await;
+        });
+    }
+}
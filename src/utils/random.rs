@@ -0,0 +1,77 @@
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+/// Creates the RNG for a simulation run, seeded when reproducible runs are wanted.
+pub fn create_rng(seed: Option<u64>) -> StdRng {
+    match seed {
+        Some(seed) => StdRng::seed_from_u64(seed),
+        None => StdRng::from_os_rng(),
+    }
+}
+
+/// Picks an index from `weights` with probability proportional to its weight.
+///
+/// `exclude` removes one index from the draw (used for the no-repeat option). If that
+/// leaves nothing to pick from, the exclusion is ignored so a single key still runs.
+pub fn pick_weighted_index<R: Rng>(weights: &[u32], exclude: Option<usize>, rng: &mut R) -> Option<usize> {
+    let weight_at = |index: usize, weight: u32| if Some(index) == exclude { 0 } else { weight as u64 };
+    let total: u64 = weights.iter().enumerate().map(|(i, &w)| weight_at(i, w)).sum();
+
+    if total == 0 {
+        return if exclude.is_some() {
+            pick_weighted_index(weights, None, rng)
+        } else {
+            None
+        };
+    }
+
+    let mut roll = rng.random_range(0..total);
+    for (index, &weight) in weights.iter().enumerate() {
+        let weight = weight_at(index, weight);
+        if roll < weight {
+            return Some(index);
+        }
+        roll -= weight;
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pick_weighted_index_skips_zero_weights() {
+        let mut rng = create_rng(Some(7));
+        for _ in 0..100 {
+            assert_eq!(pick_weighted_index(&[0, 3, 0], None, &mut rng), Some(1));
+        }
+        assert_eq!(pick_weighted_index(&[0, 0], None, &mut rng), None);
+        assert_eq!(pick_weighted_index(&[], None, &mut rng), None);
+    }
+
+    #[test]
+    fn test_pick_weighted_index_no_repeat() {
+        let mut rng = create_rng(Some(7));
+        let mut last = None;
+        for _ in 0..100 {
+            let index = pick_weighted_index(&[1, 1, 5], last, &mut rng);
+            assert!(index.is_some());
+            assert_ne!(index, last);
+            last = index;
+        }
+
+        // A single key can't avoid repeating, so it is still picked.
+        assert_eq!(pick_weighted_index(&[2], Some(0), &mut rng), Some(0));
+    }
+
+    #[test]
+    fn test_seeded_rng_is_reproducible() {
+        let picks = |seed| {
+            let mut rng = create_rng(Some(seed));
+            (0..20)
+                .map(|_| pick_weighted_index(&[1, 2, 3, 4], None, &mut rng))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(picks(42), picks(42));
+    }
+}
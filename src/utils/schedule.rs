@@ -0,0 +1,201 @@
+use std::time::{Duration, Instant};
+
+use crate::config::{KeyBehaviorMode, KeyOverride};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScheduledAction {
+    Press(usize),
+    Release(usize),
+}
+
+#[derive(Debug)]
+struct ClickTrack {
+    index: usize,
+    interval: Duration,
+    press_duration: Duration,
+    next_press: Instant,
+    release_at: Option<Instant>,
+}
+
+/// Timetable for `KeyBehaviorMode::PerKey`, with indices aligned to the simulation keys.
+#[derive(Debug)]
+pub struct KeySchedule {
+    held: Vec<usize>,
+    clicks: Vec<ClickTrack>,
+}
+
+impl KeySchedule {
+    pub fn new(overrides: &[KeyOverride], start: Instant) -> Self {
+        let mut held = Vec::new();
+        let mut clicks = Vec::new();
+
+        for (index, key_override) in overrides.iter().enumerate() {
+            if key_override.mode == KeyBehaviorMode::Hold {
+                held.push(index);
+            } else {
+                clicks.push(ClickTrack {
+                    index,
+                    interval: Duration::from_millis(key_override.interval_ms.max(1)),
+                    press_duration: Duration::from_millis(key_override.press_duration_ms),
+                    next_press: start,
+                    release_at: None,
+                });
+            }
+        }
+
+        Self { held, clicks }
+    }
+
+    /// Keys that stay down for the whole run.
+    pub fn held_keys(&self) -> &[usize] {
+        &self.held
+    }
+
+    /// Clicked keys that are currently down.
+    pub fn pressed_keys(&self) -> Vec<usize> {
+        self.clicks
+            .iter()
+            .filter(|track| track.release_at.is_some())
+            .map(|track| track.index)
+            .collect()
+    }
+
+    /// Returns the actions due at `now` and advances each key to its next press.
+    pub fn due_actions(&mut self, now: Instant) -> Vec<ScheduledAction> {
+        let mut actions = Vec::new();
+
+        for track in &mut self.clicks {
+            if track.release_at.is_some_and(|release_at| release_at <= now) {
+                actions.push(ScheduledAction::Release(track.index));
+                track.release_at = None;
+            }
+
+            if track.release_at.is_none() && track.next_press <= now {
+                actions.push(ScheduledAction::Press(track.index));
+                if track.press_duration.is_zero() {
+                    actions.push(ScheduledAction::Release(track.index));
+                } else {
+                    track.release_at = Some(now + track.press_duration);
+                }
+
+                // Skip missed presses instead of bursting to catch up.
+                track.next_press += track.interval;
+                if track.next_press <= now {
+                    track.next_press = now + track.interval;
+                }
+            }
+        }
+
+        actions
+    }
+
+    /// Moves every press and release `by` later, as after a pause.
+    pub fn delay(&mut self, by: Duration) {
+        for track in &mut self.clicks {
+            track.next_press += by;
+            if let Some(release_at) = &mut track.release_at {
+                *release_at += by;
+            }
+        }
+    }
+
+    /// The earliest instant at which `due_actions` has something to do.
+    pub fn next_due(&self) -> Option<Instant> {
+        self.clicks
+            .iter()
+            .map(|track| match track.release_at {
+                Some(release_at) => release_at,
+                None => track.next_press,
+            })
+            .min()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(mode: KeyBehaviorMode, interval_ms: u64, press_duration_ms: u64) -> KeyOverride {
+        KeyOverride { mode, interval_ms, press_duration_ms }
+    }
+
+    #[test]
+    fn test_hold_keys_are_not_scheduled() {
+        let start = Instant::now();
+        let mut schedule = KeySchedule::new(&[
+            key(KeyBehaviorMode::Hold, 100, 0),
+            key(KeyBehaviorMode::Click, 150, 0),
+        ], start);
+
+        assert_eq!(schedule.held_keys(), &[0]);
+        assert_eq!(schedule.due_actions(start), vec![
+            ScheduledAction::Press(1),
+            ScheduledAction::Release(1),
+        ]);
+        assert_eq!(schedule.next_due(), Some(start + Duration::from_millis(150)));
+    }
+
+    #[test]
+    fn test_independent_intervals() {
+        let start = Instant::now();
+        let at = |ms| start + Duration::from_millis(ms);
+        let mut schedule = KeySchedule::new(&[
+            key(KeyBehaviorMode::Click, 150, 0),
+            key(KeyBehaviorMode::Click, 2000, 0),
+        ], start);
+
+        assert_eq!(schedule.due_actions(at(0)).len(), 4);
+        assert!(schedule.due_actions(at(100)).is_empty());
+        assert_eq!(schedule.due_actions(at(150)), vec![
+            ScheduledAction::Press(0),
+            ScheduledAction::Release(0),
+        ]);
+        assert_eq!(schedule.next_due(), Some(at(300)));
+        assert_eq!(schedule.due_actions(at(2000)), vec![
+            ScheduledAction::Press(0),
+            ScheduledAction::Release(0),
+            ScheduledAction::Press(1),
+            ScheduledAction::Release(1),
+        ]);
+    }
+
+    #[test]
+    fn test_press_duration() {
+        let start = Instant::now();
+        let at = |ms| start + Duration::from_millis(ms);
+        let mut schedule = KeySchedule::new(&[key(KeyBehaviorMode::Click, 100, 30)], start);
+
+        assert_eq!(schedule.due_actions(at(0)), vec![ScheduledAction::Press(0)]);
+        assert_eq!(schedule.pressed_keys(), vec![0]);
+        assert_eq!(schedule.next_due(), Some(at(30)));
+        assert_eq!(schedule.due_actions(at(30)), vec![ScheduledAction::Release(0)]);
+        assert!(schedule.pressed_keys().is_empty());
+        assert_eq!(schedule.next_due(), Some(at(100)));
+    }
+
+    #[test]
+    fn test_missed_presses_are_skipped() {
+        let start = Instant::now();
+        let at = |ms| start + Duration::from_millis(ms);
+        let mut schedule = KeySchedule::new(&[key(KeyBehaviorMode::Click, 100, 0)], start);
+
+        schedule.due_actions(at(0));
+        assert_eq!(schedule.due_actions(at(550)).len(), 2);
+        assert_eq!(schedule.next_due(), Some(at(650)));
+    }
+
+    #[test]
+    fn test_delay_keeps_remaining_press_time() {
+        let start = Instant::now();
+        let at = |ms| start + Duration::from_millis(ms);
+        let mut schedule = KeySchedule::new(&[key(KeyBehaviorMode::Click, 100, 50)], start);
+
+        assert_eq!(schedule.due_actions(at(0)), vec![ScheduledAction::Press(0)]);
+        // Paused 20 ms into the press for 500 ms
+        schedule.delay(Duration::from_millis(500));
+        assert!(schedule.due_actions(at(520)).is_empty());
+        assert_eq!(schedule.pressed_keys(), vec![0]);
+        assert_eq!(schedule.due_actions(at(550)), vec![ScheduledAction::Release(0)]);
+        assert_eq!(schedule.next_due(), Some(at(600)));
+    }
+}
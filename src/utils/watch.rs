@@ -0,0 +1,110 @@
+//! A single-value channel: the sender replaces the value and receivers wake up to see the
+//! latest one. Background threads follow settings through it instead of locking `AppData`.
+
+use std::sync::{Arc, Mutex};
+
+use smol::channel;
+
+struct State<T> {
+    value: T,
+    version: u64,
+    // One bounded(1) channel per receiver; a pending wake-up is never lost or doubled
+    wakers: Vec<channel::Sender<()>>,
+}
+
+pub struct Sender<T> {
+    state: Arc<Mutex<State<T>>>,
+}
+
+pub struct Receiver<T> {
+    state: Arc<Mutex<State<T>>>,
+    seen: u64,
+    wake: channel::Receiver<()>,
+}
+
+impl<T: Clone + PartialEq> Sender<T> {
+    pub fn new(value: T) -> Self {
+        Self { state: Arc::new(Mutex::new(State { value, version: 0, wakers: Vec::new() })) }
+    }
+
+    /// Replaces the value and wakes the receivers, unless it's unchanged. Returns whether it changed.
+    pub fn send_if_changed(&self, value: T) -> bool {
+        let mut state = self.state.lock().unwrap();
+        if state.value == value {
+            return false;
+        }
+        state.value = value;
+        state.version += 1;
+        state.wakers.retain(|waker| !waker.is_closed());
+        for waker in &state.wakers {
+            // Full means a wake-up is already pending
+            let _ = waker.try_send(());
+        }
+        true
+    }
+
+    /// A receiver that sees the current value as unchanged.
+    pub fn subscribe(&self) -> Receiver<T> {
+        let (waker, wake) = channel::bounded(1);
+        let mut state = self.state.lock().unwrap();
+        state.wakers.push(waker);
+        Receiver { state: Arc::clone(&self.state), seen: state.version, wake }
+    }
+}
+
+impl<T: Clone> Receiver<T> {
+    /// The latest value, which counts as seen from now on.
+    pub fn borrow_and_update(&mut self) -> T {
+        let state = self.state.lock().unwrap();
+        self.seen = state.version;
+        state.value.clone()
+    }
+
+    pub fn has_changed(&self) -> bool {
+        self.state.lock().unwrap().version != self.seen
+    }
+
+    /// Waits until there's a value this receiver hasn't seen.
+    pub async fn changed(&mut self) {
+        while !self.has_changed() {
+            // The senders live in the shared state, so this only fails if it's gone too
+            if self.wake.recv().await.is_err() {
+                smol::future::pending::<()>().await;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{thread, time::Duration};
+
+    #[test]
+    fn test_watch_wakes_on_change_only() {
+        let sender = Sender::new(1);
+        let mut receiver = sender.subscribe();
+        assert!(!receiver.has_changed());
+        assert!(!sender.send_if_changed(1));
+        assert!(!receiver.has_changed());
+
+        assert!(sender.send_if_changed(2));
+        assert!(sender.send_if_changed(3));
+        smol::block_on(receiver.changed());
+        assert_eq!(receiver.borrow_and_update(), 3);
+        assert!(!receiver.has_changed());
+    }
+
+    #[test]
+    fn test_watch_wakes_blocked_receiver() {
+        let sender = Sender::new("F8".to_string());
+        let mut receiver = sender.subscribe();
+        let waiter = thread::spawn(move || {
+            smol::block_on(receiver.changed());
+            receiver.borrow_and_update()
+        });
+        thread::sleep(Duration::from_millis(20));
+        sender.send_if_changed("F9".to_string());
+        assert_eq!(waiter.join().unwrap(), "F9");
+    }
+}
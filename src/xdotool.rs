@@ -0,0 +1,531 @@
+//! Imports xdotool shell snippets as macros, so existing automation runs through the
+//! virtual device where xdotool can't (Wayland). Supported:
+//!
+//! ```text
+//! xdotool key [--delay MS] [--repeat N] ctrl+s Return
+//! xdotool keydown shift / keyup shift
+//! xdotool type [--delay MS] "text"
+//! xdotool click [--repeat N] [--delay MS] 1
+//! xdotool mousemove_relative -- -20 15   (or mousemove --relative)
+//! xdotool sleep 0.5   /   sleep 0.5
+//! ```
+//!
+//! Commands can be chained on one `xdotool` line and separated by newlines, `;` or `&&`.
+//! Shell quoting, comments and line continuations are understood; anything else, such as
+//! absolute mouse moves, window commands or variables, is reported as an error.
+
+use crate::{
+    cadence::TypingProfile,
+    config::{HotkeyModifiers, KeyChord},
+    error::{AppError, Result},
+    macros::{Macro, MacroStep, MouseButton},
+};
+
+// xdotool's own pause between repeated clicks
+const DEFAULT_CLICK_DELAY_MS: u64 = 100;
+
+const COMMANDS: [&str; 8] = ["key", "keydown", "keyup", "type", "click", "mousemove", "mousemove_relative", "sleep"];
+
+#[derive(Debug, Clone)]
+struct Word {
+    text: String,
+    line: usize,
+    column: usize,
+}
+
+fn syntax_error(line: usize, column: usize, message: impl Into<String>) -> AppError {
+    AppError::MacroSyntax { line, column, message: message.into() }
+}
+
+fn word_error(word: &Word, message: impl Into<String>) -> AppError {
+    syntax_error(word.line, word.column, message)
+}
+
+// Splits shell source into commands of words
+fn split_commands(source: &str) -> Result<Vec<Vec<Word>>> {
+    let mut commands = Vec::new();
+    let mut command: Vec<Word> = Vec::new();
+    let mut word: Option<Word> = None;
+    let mut quote: Option<(char, usize, usize)> = None;
+    let mut chars = source.chars().peekable();
+    let (mut line, mut column) = (1, 0);
+
+    fn end_word(command: &mut Vec<Word>, word: &mut Option<Word>) {
+        command.extend(word.take());
+    }
+    fn end_command(commands: &mut Vec<Vec<Word>>, command: &mut Vec<Word>) {
+        if !command.is_empty() {
+            commands.push(std::mem::take(command));
+        }
+    }
+
+    while let Some(c) = chars.next() {
+        column += 1;
+        let position = (line, column);
+        if c == '\n' {
+            line += 1;
+            column = 0;
+        }
+        let append = |word: &mut Option<Word>, c: char| {
+            word.get_or_insert_with(|| Word { text: String::new(), line: position.0, column: position.1 })
+                .text
+                .push(c);
+        };
+
+        match quote {
+            Some(('\'', ..)) => match c {
+                '\'' => quote = None,
+                c => append(&mut word, c),
+            },
+            Some(('"', ..)) => match c {
+                '"' => quote = None,
+                '\\' if chars.peek().is_some_and(|next| matches!(next, '"' | '\\' | '$' | '`' | '\n')) => {
+                    let escaped = chars.next().unwrap_or_default();
+                    column += 1;
+                    if escaped == '\n' {
+                        line += 1;
+                        column = 0;
+                    } else {
+                        append(&mut word, escaped);
+                    }
+                }
+                c => append(&mut word, c),
+            },
+            _ => match c {
+                '\\' => match chars.next() {
+                    Some('\n') => {
+                        line += 1;
+                        column = 0;
+                    }
+                    Some(escaped) => {
+                        column += 1;
+                        append(&mut word, escaped);
+                    }
+                    None => {}
+                },
+                '#' if word.is_none() => {
+                    // Comment to the end of the line
+                    for c in chars.by_ref() {
+                        if c == '\n' {
+                            line += 1;
+                            column = 0;
+                            break;
+                        }
+                    }
+                    end_command(&mut commands, &mut command);
+                }
+                '\'' | '"' => {
+                    word.get_or_insert_with(|| Word { text: String::new(), line: position.0, column: position.1 });
+                    quote = Some((c, position.0, position.1));
+                }
+                '\n' | ';' => {
+                    end_word(&mut command, &mut word);
+                    end_command(&mut commands, &mut command);
+                }
+                '&' if chars.peek() == Some(&'&') => {
+                    chars.next();
+                    column += 1;
+                    end_word(&mut command, &mut word);
+                    end_command(&mut commands, &mut command);
+                }
+                '|' | '&' | '$' | '`' | '>' | '<' => {
+                    return Err(syntax_error(position.0, position.1, format!("shell syntax `{}` isn't supported", c)));
+                }
+                c if c.is_whitespace() => end_word(&mut command, &mut word),
+                c => append(&mut word, c),
+            },
+        }
+    }
+
+    if let Some((_, line, column)) = quote {
+        return Err(syntax_error(line, column, "unclosed quote"));
+    }
+    end_word(&mut command, &mut word);
+    end_command(&mut commands, &mut command);
+    Ok(commands)
+}
+
+#[derive(Debug, Default)]
+struct Options {
+    delay_ms: Option<u64>,
+    repeat: Option<u32>,
+    repeat_delay_ms: Option<u64>,
+    relative: bool,
+}
+
+fn parse_number<T: std::str::FromStr>(word: &Word, what: &str) -> Result<T> {
+    word.text.parse().map_err(|_| word_error(word, format!("invalid {} `{}`", what, word.text)))
+}
+
+fn parse_seconds(word: &Word) -> Result<u64> {
+    let seconds: f64 = parse_number(word, "number of seconds")?;
+    if !seconds.is_finite() || seconds < 0.0 {
+        return Err(word_error(word, format!("invalid number of seconds `{}`", word.text)));
+    }
+    Ok((seconds * 1000.0).round() as u64)
+}
+
+struct Importer<'a> {
+    words: &'a [Word],
+    position: usize,
+}
+
+impl<'a> Importer<'a> {
+    fn next(&mut self) -> Option<&'a Word> {
+        let word = self.words.get(self.position);
+        self.position += 1;
+        word
+    }
+
+    fn expect(&mut self, after: &Word, what: &str) -> Result<&'a Word> {
+        self.next().ok_or_else(|| word_error(after, format!("expected {}", what)))
+    }
+
+    fn at_command(&self) -> bool {
+        self.words.get(self.position).is_none_or(|word| COMMANDS.contains(&word.text.as_str()))
+    }
+
+    // Reads `--option [value]` words up to the first argument or `--`
+    fn options(&mut self) -> Result<Options> {
+        let mut options = Options::default();
+        while let Some(word) = self.words.get(self.position).filter(|word| word.text.starts_with('-')) {
+            if word.text.parse::<i64>().is_ok() {
+                break;
+            }
+            self.position += 1;
+            match word.text.as_str() {
+                "--" => break,
+                "--delay" => options.delay_ms = Some(parse_number(self.expect(word, "a delay")?, "delay")?),
+                "--repeat" => options.repeat = Some(parse_number(self.expect(word, "a repeat count")?, "repeat count")?),
+                "--repeat-delay" => options.repeat_delay_ms = Some(parse_number(self.expect(word, "a delay")?, "delay")?),
+                "--window" | "--screen" => {
+                    let value = self.expect(word, "a value")?;
+                    log::warn!("Ignoring xdotool option {} {}", word.text, value.text);
+                }
+                "--relative" => options.relative = true,
+                "--clearmodifiers" | "--sync" => {}
+                _ => return Err(word_error(word, format!("unsupported xdotool option `{}`", word.text))),
+            }
+        }
+        Ok(options)
+    }
+
+    fn command(&mut self, name: &Word, steps: &mut Vec<MacroStep>) -> Result<()> {
+        match name.text.as_str() {
+            "key" | "keydown" | "keyup" => {
+                let options = self.options()?;
+                let mut keys = Vec::new();
+                while !self.at_command() {
+                    let word = self.next().unwrap_or(name);
+                    keys.push(keysym_to_key(word)?);
+                }
+                if keys.is_empty() {
+                    return Err(word_error(name, "expected a key"));
+                }
+
+                let mut block = Vec::new();
+                for (index, key) in keys.into_iter().enumerate() {
+                    if index > 0 {
+                        push_wait(&mut block, options.delay_ms.unwrap_or(0));
+                    }
+                    block.push(match name.text.as_str() {
+                        "keydown" => MacroStep::KeyDown { key },
+                        "keyup" => MacroStep::KeyUp { key },
+                        _ => MacroStep::Tap { key },
+                    });
+                }
+                push_repeated(steps, block, options.repeat, options.repeat_delay_ms.unwrap_or(0));
+            }
+            "type" => {
+                let options = self.options()?;
+                let mut text = String::new();
+                while let Some(word) = self.next() {
+                    text.push_str(&word.text);
+                }
+                steps.push(MacroStep::Type { text, profile: options.delay_ms.map(TypingProfile::fixed) });
+            }
+            "click" => {
+                let options = self.options()?;
+                let word = self.expect(name, "a mouse button")?;
+                let step = match word.text.as_str() {
+                    "1" => MacroStep::MouseClick { button: MouseButton::Left },
+                    "2" => MacroStep::MouseClick { button: MouseButton::Middle },
+                    "3" => MacroStep::MouseClick { button: MouseButton::Right },
+                    "4" => MacroStep::Scroll { dx: 0, dy: 1 },
+                    "5" => MacroStep::Scroll { dx: 0, dy: -1 },
+                    "6" => MacroStep::Scroll { dx: -1, dy: 0 },
+                    "7" => MacroStep::Scroll { dx: 1, dy: 0 },
+                    _ => return Err(word_error(word, format!("unknown mouse button `{}`", word.text))),
+                };
+                let delay_ms = options.delay_ms.unwrap_or(DEFAULT_CLICK_DELAY_MS);
+                push_repeated(steps, vec![step], options.repeat, delay_ms);
+            }
+            "mousemove" | "mousemove_relative" => {
+                let options = self.options()?;
+                if name.text == "mousemove" && !options.relative {
+                    return Err(word_error(name, "absolute mousemove isn't supported, use mousemove_relative"));
+                }
+                let dx = parse_number(self.expect(name, "a horizontal distance")?, "distance")?;
+                let dy = parse_number(self.expect(name, "a vertical distance")?, "distance")?;
+                steps.push(MacroStep::MouseMove { dx, dy });
+            }
+            "sleep" => {
+                let ms = parse_seconds(self.expect(name, "a number of seconds")?)?;
+                push_wait(steps, ms);
+            }
+            _ => return Err(word_error(name, format!("unsupported xdotool command `{}`", name.text))),
+        }
+        Ok(())
+    }
+}
+
+fn push_wait(steps: &mut Vec<MacroStep>, ms: u64) {
+    if ms > 0 {
+        steps.push(MacroStep::Wait { ms });
+    }
+}
+
+// `--repeat N` runs the block N times with `delay_ms` between the runs
+fn push_repeated(steps: &mut Vec<MacroStep>, mut block: Vec<MacroStep>, repeat: Option<u32>, delay_ms: u64) {
+    match repeat {
+        Some(count) if count > 1 => {
+            push_wait(&mut block, delay_ms);
+            steps.push(MacroStep::Repeat { count, steps: block });
+        }
+        _ => steps.extend(block),
+    }
+}
+
+// X keysym names for keys whose macro name differs
+fn named_keysym(keysym: &str) -> Option<&'static str> {
+    Some(match keysym {
+        "Return" => "Enter",
+        "KP_Enter" => "KEY_KPENTER",
+        "BackSpace" => "Backspace",
+        "space" => "Space",
+        "Prior" | "Page_Up" => "PageUp",
+        "Next" | "Page_Down" => "PageDown",
+        "Left" => "ArrowLeft",
+        "Right" => "ArrowRight",
+        "Up" => "ArrowUp",
+        "Down" => "ArrowDown",
+        "Caps_Lock" => "CapsLock",
+        "Print" => "KEY_SYSRQ",
+        "Menu" => "KEY_COMPOSE",
+        "Control_R" => "KEY_RIGHTCTRL",
+        "Shift_R" => "KEY_RIGHTSHIFT",
+        "Alt_R" => "KEY_RIGHTALT",
+        "ISO_Level3_Shift" => "AltGraph",
+        "minus" => "-",
+        "equal" => "=",
+        "comma" => ",",
+        "period" => ".",
+        "slash" => "/",
+        "semicolon" => ";",
+        "apostrophe" => "'",
+        "bracketleft" => "[",
+        "bracketright" => "]",
+        "backslash" => "KEY_BACKSLASH",
+        "grave" => "`",
+        "XF86AudioRaiseVolume" => "KEY_VOLUMEUP",
+        "XF86AudioLowerVolume" => "KEY_VOLUMEDOWN",
+        "XF86AudioMute" => "KEY_MUTE",
+        "XF86AudioPlay" => "KEY_PLAYPAUSE",
+        "XF86AudioNext" => "KEY_NEXTSONG",
+        "XF86AudioPrev" => "KEY_PREVIOUSSONG",
+        _ => return None,
+    })
+}
+
+// Symbol keysyms typed with Shift on a US layout, as the unshifted key they share
+fn shifted_keysym(keysym: &str) -> Option<&'static str> {
+    Some(match keysym {
+        "less" => ",",
+        "greater" => ".",
+        "question" => "/",
+        "colon" => ";",
+        "quotedbl" => "'",
+        "underscore" => "-",
+        "plus" => "=",
+        "braceleft" => "[",
+        "braceright" => "]",
+        "bar" => "KEY_BACKSLASH",
+        "asciitilde" => "`",
+        _ => return None,
+    })
+}
+
+// Sets the chord modifier a keysym names; returns false for other keysyms
+fn set_modifier(modifiers: &mut HotkeyModifiers, keysym: &str) -> bool {
+    match keysym.to_lowercase().as_str() {
+        "ctrl" | "control" | "control_l" => modifiers.ctrl = true,
+        "alt" | "alt_l" => modifiers.alt = true,
+        "shift" | "shift_l" => modifiers.shift = true,
+        "super" | "super_l" | "super_r" | "meta" | "meta_l" | "windows" => modifiers.super_key = true,
+        _ => return false,
+    }
+    true
+}
+
+fn single_keysym(word: &Word, keysym: &str) -> Result<String> {
+    if let Some(name) = named_keysym(keysym) {
+        return Ok(name.to_string());
+    }
+    let mut chars = keysym.chars();
+    match (chars.next(), chars.next()) {
+        (Some(c), None) if c.is_ascii_alphanumeric() => return Ok(c.to_ascii_uppercase().to_string()),
+        _ => {}
+    }
+    if let Some(digit) = keysym.strip_prefix("KP_").filter(|digit| digit.len() == 1 && digit.chars().all(|c| c.is_ascii_digit())) {
+        return Ok(format!("KP{}", digit));
+    }
+    if let Some(number) = keysym.strip_prefix('F').and_then(|number| number.parse::<u32>().ok()) {
+        return Ok(if number <= 12 { format!("F{}", number) } else { format!("KEY_F{}", number) });
+    }
+    if matches!(keysym, "Tab" | "Escape" | "Delete" | "Insert" | "Home" | "End") {
+        return Ok(keysym.to_string());
+    }
+    Err(word_error(word, format!("unknown keysym `{}`, use `type` for symbols", keysym)))
+}
+
+// "ctrl+shift+t" becomes "Ctrl+Shift+T"; an uppercase letter adds Shift like in xdotool
+fn keysym_to_key(word: &Word) -> Result<String> {
+    let parts: Vec<&str> = word.text.split('+').collect();
+    let Some((&last, prefixes)) = parts.split_last().filter(|(last, _)| !last.is_empty()) else {
+        return Err(word_error(word, format!("invalid key `{}`", word.text)));
+    };
+
+    let mut modifiers = HotkeyModifiers::default();
+    for prefix in prefixes {
+        if !set_modifier(&mut modifiers, prefix) {
+            return Err(word_error(word, format!("unknown modifier `{}`", prefix)));
+        }
+    }
+
+    let key = if parts.len() == 1 && set_modifier(&mut HotkeyModifiers::default(), last) {
+        // A bare modifier is pressed as a key of its own
+        let mut bare = HotkeyModifiers::default();
+        set_modifier(&mut bare, last);
+        if bare.ctrl { "Control" } else if bare.alt { "Alt" } else if bare.shift { "Shift" } else { "Super" }.to_string()
+    } else if let Some(key) = shifted_keysym(last) {
+        modifiers.shift = true;
+        key.to_string()
+    } else {
+        if last.len() == 1 && last.chars().all(|c| c.is_ascii_uppercase()) {
+            modifiers.shift = true;
+        }
+        single_keysym(word, last)?
+    };
+    Ok(KeyChord { modifiers, key }.to_string())
+}
+
+/// Converts xdotool shell source into the steps of a macro called `name`.
+pub fn import_xdotool(name: String, source: &str) -> Result<Macro> {
+    let mut steps = Vec::new();
+
+    for words in split_commands(source)? {
+        let program = &words[0];
+        let args = &words[1..];
+        if program.text == "sleep" {
+            let seconds = args.first().ok_or_else(|| word_error(program, "expected a number of seconds"))?;
+            push_wait(&mut steps, parse_seconds(seconds)?);
+        } else if program.text == "xdotool" || program.text.ends_with("/xdotool") {
+            let mut importer = Importer { words: args, position: 0 };
+            while let Some(command) = importer.next() {
+                importer.command(command, &mut steps)?;
+            }
+        } else {
+            return Err(word_error(program, format!("unsupported command `{}`", program.text)));
+        }
+    }
+
+    Ok(Macro { name, steps })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn import(source: &str) -> Vec<MacroStep> {
+        import_xdotool("test".to_string(), source).unwrap().steps
+    }
+
+    fn error_position(source: &str) -> (usize, usize) {
+        match import_xdotool("test".to_string(), source) {
+            Err(AppError::MacroSyntax { line, column, .. }) => (line, column),
+            other => panic!("expected a syntax error, got {:?}", other),
+        }
+    }
+
+    fn key(name: &str) -> String {
+        name.to_string()
+    }
+
+    #[test]
+    fn test_import_snippet() {
+        let steps = import(
+            "#!/bin/sh\n\
+             # open a terminal\n\
+             xdotool key super+Return\n\
+             sleep 0.5\n\
+             xdotool type 'echo \"hi\"' && xdotool key Return\n\
+             xdotool mousemove_relative -- -20 15 click 3\n",
+        );
+        assert_eq!(steps, vec![
+            MacroStep::Tap { key: key("Super+Enter") },
+            MacroStep::Wait { ms: 500 },
+            MacroStep::Type { text: "echo \"hi\"".to_string(), profile: None },
+            MacroStep::Tap { key: key("Enter") },
+            MacroStep::MouseMove { dx: -20, dy: 15 },
+            MacroStep::MouseClick { button: MouseButton::Right },
+        ]);
+    }
+
+    #[test]
+    fn test_import_keys() {
+        let steps = import("xdotool key --delay 50 ctrl+shift+t A F13 bracketleft less; xdotool keydown shift keyup shift");
+        assert_eq!(steps, vec![
+            MacroStep::Tap { key: key("Ctrl+Shift+T") },
+            MacroStep::Wait { ms: 50 },
+            MacroStep::Tap { key: key("Shift+A") },
+            MacroStep::Wait { ms: 50 },
+            MacroStep::Tap { key: key("KEY_F13") },
+            MacroStep::Wait { ms: 50 },
+            MacroStep::Tap { key: key("[") },
+            MacroStep::Wait { ms: 50 },
+            MacroStep::Tap { key: key("Shift+,") },
+            MacroStep::KeyDown { key: key("Shift") },
+            MacroStep::KeyUp { key: key("Shift") },
+        ]);
+    }
+
+    #[test]
+    fn test_import_type_delay() {
+        assert_eq!(import("xdotool type --delay 40 hi"), vec![
+            MacroStep::Type { text: "hi".to_string(), profile: Some(TypingProfile::fixed(40)) },
+        ]);
+    }
+
+    #[test]
+    fn test_import_repeats() {
+        let steps = import("xdotool click --repeat 3 1 \\\n  sleep 1 click 4");
+        assert_eq!(steps, vec![
+            MacroStep::Repeat {
+                count: 3,
+                steps: vec![MacroStep::MouseClick { button: MouseButton::Left }, MacroStep::Wait { ms: 100 }],
+            },
+            MacroStep::Wait { ms: 1000 },
+            MacroStep::Scroll { dx: 0, dy: 1 },
+        ]);
+    }
+
+    #[test]
+    fn test_import_errors() {
+        assert_eq!(error_position("xdotool key A\nxdotool mousemove 10 10"), (2, 9));
+        assert_eq!(error_position("xdotool key hyper+a"), (1, 13));
+        assert_eq!(error_position("xdotool type \"open"), (1, 14));
+        assert_eq!(error_position("echo hi"), (1, 1));
+        assert_eq!(error_position("xdotool type $HOME"), (1, 14));
+        assert_eq!(error_position("xdotool windowactivate 1"), (1, 9));
+    }
+}